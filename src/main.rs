@@ -1,4 +1,12 @@
-use render::{ppm, Render, RenderConfig};
+use render::{
+    environment::{Environment, EnvironmentMap},
+    medium::{Medium, VoxelGrid},
+    ppm,
+    scene::Scene,
+    sky::Sky,
+    texture::{Filter, ImageTexture, WrapMode},
+    Integrator, Render, RenderConfig,
+};
 
 mod render;
 
@@ -15,9 +23,92 @@ fn main() {
         height: 480,
         samples: 10,
         super_samples: 5,
+        spectral: std::env::var("SPECTRAL").is_ok(),
+        guiding: std::env::var("GUIDING").is_ok(),
+        integrator: std::env::var("INTEGRATOR")
+            .map(|s| Integrator::from_name(&s).expect("Failed to find env INTEGRATOR"))
+            .unwrap_or(Integrator::Path),
+        max_depth: std::env::var("MAX_DEPTH")
+            .map(|s| s.parse().expect("Failed to parse env MAX_DEPTH"))
+            .unwrap_or(64),
+        russian_roulette_depth: std::env::var("RR_DEPTH")
+            .map(|s| s.parse().expect("Failed to parse env RR_DEPTH"))
+            .unwrap_or(5),
+        merge_radius: std::env::var("MERGE_RADIUS")
+            .map(|s| s.parse().expect("Failed to parse env MERGE_RADIUS"))
+            .unwrap_or(1.0),
+        merge_radius_alpha: std::env::var("MERGE_RADIUS_ALPHA")
+            .map(|s| s.parse().expect("Failed to parse env MERGE_RADIUS_ALPHA"))
+            .unwrap_or(0.75),
+        ao_radius: std::env::var("AO_RADIUS")
+            .map(|s| s.parse().expect("Failed to parse env AO_RADIUS"))
+            .unwrap_or(30.0),
     };
 
-    let render = Render::new(config);
+    let mut scene = match std::env::var("TEXTURE") {
+        Ok(file_name) => Scene::textured(
+            ImageTexture::load(&file_name, WrapMode::Repeat, Filter::Trilinear, true)
+                .expect("Failed to load env TEXTURE")
+                .into(),
+        ),
+        Err(_) => match std::env::var("VOLUME") {
+            Ok(file_name) => {
+                Scene::volume(VoxelGrid::load(&file_name).expect("Failed to load env VOLUME"))
+            }
+            Err(_) => std::env::var("SCENE")
+                .map(|s| Scene::preset(&s).expect("Failed to find env SCENE"))
+                .unwrap_or_else(|_| Scene::new()),
+        },
+    };
+
+    if let Ok(file_name) = std::env::var("ENVIRONMENT") {
+        let rotation: f64 = std::env::var("ENVIRONMENT_ROTATION")
+            .map(|s| s.parse().expect("Failed to parse env ENVIRONMENT_ROTATION"))
+            .unwrap_or(0.0);
+        let intensity = std::env::var("ENVIRONMENT_INTENSITY")
+            .map(|s| {
+                s.parse()
+                    .expect("Failed to parse env ENVIRONMENT_INTENSITY")
+            })
+            .unwrap_or(1.0);
+
+        scene.set_environment(Environment::Map(
+            EnvironmentMap::load(&file_name, rotation.to_radians(), intensity)
+                .expect("Failed to load env ENVIRONMENT"),
+        ));
+    } else if let Ok(elevation) = std::env::var("SUN_ELEVATION") {
+        let elevation: f64 = elevation
+            .parse()
+            .expect("Failed to parse env SUN_ELEVATION");
+        let azimuth: f64 = std::env::var("SUN_AZIMUTH")
+            .map(|s| s.parse().expect("Failed to parse env SUN_AZIMUTH"))
+            .unwrap_or(0.0);
+        let turbidity = std::env::var("TURBIDITY")
+            .map(|s| s.parse().expect("Failed to parse env TURBIDITY"))
+            .unwrap_or(3.0);
+        let intensity = std::env::var("SKY_INTENSITY")
+            .map(|s| s.parse().expect("Failed to parse env SKY_INTENSITY"))
+            .unwrap_or(0.05);
+
+        scene.set_environment(Environment::Sky(Sky::new(
+            elevation.to_radians(),
+            azimuth.to_radians(),
+            turbidity,
+            intensity,
+        )));
+    }
+
+    if let Ok(density) = std::env::var("FOG") {
+        let density: f64 = density.parse().expect("Failed to parse env FOG");
+        let anisotropy = std::env::var("FOG_ANISOTROPY")
+            .map(|s| s.parse().expect("Failed to parse env FOG_ANISOTROPY"))
+            .unwrap_or(0.0);
+
+        let fog = scene.add_medium(Medium::fog(density, anisotropy));
+        scene.set_global_medium(fog);
+    }
+
+    let render = Render::new(config, scene);
 
     let now = Instant::now();
 
@@ -3,19 +3,53 @@ use std::{
     thread, vec,
 };
 
-use material::{Color, IOR};
-use random::XorShiftRandom;
-use ray::Ray;
-use scene::Scene;
+use bdpt::Bdpt;
+use camera::Camera;
+use debug::Debug;
+use film::Film;
+use guiding::{GuidedPath, GuidingTree, Region, TRAINING_PASSES};
+use material::{Color, ShadingContext};
+use mlt::Mlt;
+use random::{Sampler, XorShiftRandom};
+use ray::{Ray, RayCone};
+use scene::{Event, Scene};
+use spectrum::Wavelengths;
+use sppm::Sppm;
+use vcm::Vcm;
 use vec3::Vec3;
 
+mod bdpt;
+mod camera;
+#[cfg(test)]
+mod convergence;
+mod debug;
+mod distribution;
+pub mod environment;
+mod film;
+mod frame;
+mod guiding;
+mod hash_grid;
+mod hdr;
 mod intersection;
+pub mod light;
 mod material;
+pub mod medium;
+mod microfacet;
+mod mlt;
+mod png;
 pub mod ppm;
 mod random;
 mod ray;
-mod scene;
+#[cfg(test)]
+mod regression;
+mod sampling;
+pub mod scene;
+pub mod sky;
+pub mod spectrum;
 mod sphere;
+mod sppm;
+pub mod texture;
+mod vcm;
 mod vec3;
 
 const BACKGROUND_COLOR: Vec3 = Vec3 {
@@ -23,8 +57,6 @@ const BACKGROUND_COLOR: Vec3 = Vec3 {
     y: 0.0,
     z: 0.0,
 };
-const DEPTH: u32 = 5;
-const DEPTH_LIMIT: u32 = 64;
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 enum TaskStatus {
@@ -33,6 +65,57 @@ enum TaskStatus {
     Completed,
 }
 
+/// Light transport algorithm used for each camera sample.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Integrator {
+    Path,
+    /// Bidirectional path tracing, connecting camera and light subpaths.
+    Bidirectional,
+    /// Stochastic progressive photon mapping, with one iteration per sample.
+    PhotonMapping,
+    /// Primary sample space Metropolis light transport over path traced samples.
+    Metropolis,
+    /// Vertex connection and merging, with one iteration per sample.
+    VertexMerging,
+    AmbientOcclusion,
+    /// Diagnostic view of the first surface hit, instead of its lighting.
+    Debug(DebugView),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DebugView {
+    /// Shading normal, mapped from `[-1, 1]` to `[0, 1]`.
+    Normal,
+    Uv,
+    /// Distance from the camera, brighter when closer.
+    Depth,
+    MaterialId,
+    /// Intersection tests spent finding the first hit, as a heatmap.
+    TraversalCost,
+    /// Directional albedo of the BSDF towards the camera.
+    Albedo,
+}
+
+impl Integrator {
+    pub fn from_name(name: &str) -> Option<Integrator> {
+        match name {
+            "path" => Some(Integrator::Path),
+            "bdpt" => Some(Integrator::Bidirectional),
+            "sppm" => Some(Integrator::PhotonMapping),
+            "mlt" => Some(Integrator::Metropolis),
+            "vcm" => Some(Integrator::VertexMerging),
+            "ao" => Some(Integrator::AmbientOcclusion),
+            "normal" => Some(Integrator::Debug(DebugView::Normal)),
+            "uv" => Some(Integrator::Debug(DebugView::Uv)),
+            "depth" => Some(Integrator::Debug(DebugView::Depth)),
+            "material" => Some(Integrator::Debug(DebugView::MaterialId)),
+            "cost" => Some(Integrator::Debug(DebugView::TraversalCost)),
+            "albedo" => Some(Integrator::Debug(DebugView::Albedo)),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug)]
 pub struct RenderConfig {
     pub width: u32,
@@ -40,53 +123,78 @@ pub struct RenderConfig {
     pub tasks: u32,
     pub samples: u32,
     pub super_samples: u32,
+    /// Traces hero wavelengths instead of RGB.
+    pub spectral: bool,
+    pub integrator: Integrator,
+    /// Bounces after which path tracing stops, whatever the throughput of the path.
+    pub max_depth: u32,
+    /// Bounces a path takes before Russian roulette may end it according to its throughput.
+    pub russian_roulette_depth: u32,
+    /// Trains a guide to where light arrives from before path tracing, and samples bounces
+    /// from it alongside the BSDF.
+    pub guiding: bool,
+    /// Initial photon gathering and vertex merging radius of SPPM and VCM, in scene units.
+    pub merge_radius: f64,
+    /// Shrinks the merging radius with iteration `i` by `i^((alpha - 1) / 2)`. SPPM keeps this
+    /// fraction of the photons each pixel gathers, which shrinks it at the same rate.
+    pub merge_radius_alpha: f64,
+    /// Distance within which occluders darken the ambient occlusion view.
+    pub ao_radius: f64,
 }
 
 pub struct Render {
     config: RenderConfig,
     scene: Scene,
+    camera: Camera,
 }
 
 impl Render {
-    pub fn new(config: RenderConfig) -> Render {
+    pub fn new(config: RenderConfig, scene: Scene) -> Render {
+        let camera = Camera::new(
+            Vec3::new(50.0, 52.0, 220.0),
+            Vec3::new(0.0, -0.04, -1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            config.width,
+            config.height,
+        );
+
         Render {
             config,
-            scene: Scene::new(),
+            scene,
+            camera,
         }
     }
 
     pub fn render(&self) -> Vec<Color> {
+        match self.config.integrator {
+            Integrator::PhotonMapping => return Sppm::new(self).render(),
+            Integrator::Metropolis => return Mlt::new(self).render(),
+            Integrator::VertexMerging => return Vcm::new(self).render(),
+            Integrator::Path
+            | Integrator::Bidirectional
+            | Integrator::AmbientOcclusion
+            | Integrator::Debug(_) => {}
+        }
+
         let tasks = self.config.tasks;
         let width = self.config.width;
         let height = self.config.height;
         let samples = self.config.samples;
         let super_samples = self.config.super_samples;
+        let spectral = self.config.spectral;
 
-        let camera_pos = Vec3::new(50.0, 52.0, 220.0);
-        let camera_dir = Vec3::new(0.0, -0.04, -1.0).normalize();
-        let camera_up = Vec3::new(0.0, 1.0, 0.0);
-
-        let screen_width = 30.0 * self.config.width as f64 / self.config.height as f64;
-        let screen_height = 30.0;
-
-        let screen_dist = 40.0;
-
-        let screen_x = camera_dir.cross(camera_up).normalize() * screen_width;
-        let screen_y = screen_x.cross(camera_dir).normalize() * screen_height;
-        let screen_center = camera_pos + camera_dir * screen_dist;
-
-        let image = Arc::new(Mutex::new(vec![
-            Vec3::new(0.0, 0.0, 0.0);
-            (self.config.width * self.config.height)
-                as usize
-        ]));
+        let camera_medium = self.scene.global_medium();
+        let film = Film::new(width, height);
+        let guide = (self.config.guiding && self.config.integrator == Integrator::Path)
+            .then(|| self.train_guide());
+        let guide = guide.as_ref();
 
         let tasks_states = Arc::new(Mutex::new(vec![
             TaskStatus::NotStarted;
             self.config.height as usize
         ]));
 
-        let arc_image = image.clone();
+        let film_ref = &film;
         thread::scope(move |s| loop {
             let running_tasks = tasks_states
                 .lock()
@@ -127,7 +235,6 @@ impl Render {
             };
 
             let arc_tasks_states = tasks_states.clone();
-            let arc_arc_image = arc_image.clone();
             if let Some(y) = process_y {
                 s.spawn(move || {
                     let y = y as u32;
@@ -148,21 +255,48 @@ impl Render {
                                     let r1 = sx as f64 * rate / 2.0;
                                     let r2 = sy as f64 * rate / 2.0;
 
-                                    let screen_position = screen_center
-                                        + screen_x * ((r1 + x as f64) / width as f64 - 0.5)
-                                        + screen_y * ((r2 + y as f64) / height as f64 - 0.5);
-
-                                    let dir = (screen_position - camera_pos).normalize();
-
-                                    accumulated_radiance = accumulated_radiance
-                                        + self.radiance(
-                                            &Ray {
-                                                origin: camera_pos,
-                                                direction: dir,
-                                            },
-                                            &mut rnd,
-                                            0,
-                                        );
+                                    let wavelengths =
+                                        spectral.then(|| Wavelengths::sample(rnd.next_f64()));
+                                    let ray = self
+                                        .camera
+                                        .ray(r1 + x as f64, r2 + y as f64)
+                                        .with_wavelengths(wavelengths);
+
+                                    let radiance = match self.config.integrator {
+                                        Integrator::Path => spectrum::to_rgb(
+                                            wavelengths,
+                                            self.radiance(
+                                                &ray,
+                                                &mut rnd,
+                                                self.camera.cone(),
+                                                camera_medium,
+                                                guide,
+                                            ),
+                                        ),
+                                        Integrator::Bidirectional => Bdpt::new(
+                                            &self.scene,
+                                            &self.camera,
+                                            &self.config,
+                                            wavelengths,
+                                        )
+                                        .radiance(&ray, film_ref, &mut rnd),
+                                        Integrator::AmbientOcclusion => Debug::new(
+                                            &self.scene,
+                                            &self.camera,
+                                        )
+                                        .ambient_occlusion(&ray, self.config.ao_radius, &mut rnd),
+                                        Integrator::Debug(view) => {
+                                            Debug::new(&self.scene, &self.camera)
+                                                .visualize(&ray, view, &mut rnd)
+                                        }
+                                        Integrator::PhotonMapping
+                                        | Integrator::Metropolis
+                                        | Integrator::VertexMerging => {
+                                            unreachable!("rendered as whole images")
+                                        }
+                                    };
+
+                                    accumulated_radiance = accumulated_radiance + radiance;
                                 }
                                 cache[x as usize] = cache[x as usize]
                                     + accumulated_radiance
@@ -171,12 +305,7 @@ impl Render {
                         }
                     }
 
-                    {
-                        let mut image = arc_arc_image.lock().unwrap();
-                        for x in 0..width {
-                            image[(y * width + x) as usize] = cache[x as usize];
-                        }
-                    }
+                    film_ref.set_row(y, &cache);
 
                     {
                         let mut completed_tasks = arc_tasks_states.lock().unwrap();
@@ -188,137 +317,411 @@ impl Render {
             }
         });
 
-        return image.lock().unwrap().to_vec();
+        film.develop(1.0 / (samples * super_samples * super_samples) as f64)
     }
 
-    fn radiance(&self, ray: &Ray, rnd: &mut XorShiftRandom, depth: u32) -> Color {
-        if let Some(intersection) = self.scene.intersect(ray) {
-            let hit_point = intersection.hit_point;
-            let object_id = intersection.object_id;
+    /// Path traces the image over passes of doubling sample counts, learning a guide from
+    /// each pass and sampling from it in the next.
+    fn train_guide(&self) -> GuidingTree {
+        let tasks = self.config.tasks.max(1);
+        let width = self.config.width;
+        let height = self.config.height;
+        let spectral = self.config.spectral;
+
+        let (center, radius) = self.scene.bounding_sphere();
+        let mut guide = GuidingTree::new(center, radius);
+
+        for pass in 0..TRAINING_PASSES {
+            println!("Training guide (pass = {} / {})", pass, TRAINING_PASSES);
+
+            let rows = height.div_ceil(tasks);
+            let guide_ref = &guide;
+            thread::scope(|s| {
+                for task in 0..tasks {
+                    s.spawn(move || {
+                        let mut rnd = XorShiftRandom::new(height + pass * tasks + task + 1);
+
+                        for y in (task * rows)..((task + 1) * rows).min(height) {
+                            for x in 0..width {
+                                for _ in 0..1 << pass {
+                                    let wavelengths =
+                                        spectral.then(|| Wavelengths::sample(rnd.next_f64()));
+                                    let ray = self
+                                        .camera
+                                        .ray(x as f64 + rnd.next_f64(), y as f64 + rnd.next_f64())
+                                        .with_wavelengths(wavelengths);
+                                    self.radiance(
+                                        &ray,
+                                        &mut rnd,
+                                        self.camera.cone(),
+                                        self.scene.global_medium(),
+                                        Some(guide_ref),
+                                    );
+                                }
+                            }
+                        }
+                    });
+                }
+            });
 
-            let sphere = &self.scene.spheres()[object_id as usize];
-            let hitpoint = &intersection.hit_point;
-            let orienting_normal = if hitpoint.normal.dot(ray.direction) < 0.0 {
-                hitpoint.normal
-            } else {
-                hitpoint.normal * -1.0
-            };
-            let mut russian_roulette_probability = sphere.color.max();
+            guide.refine(pass);
+        }
 
-            if depth > DEPTH_LIMIT {
-                russian_roulette_probability *= 0.5f64.powf(depth as f64 - DEPTH_LIMIT as f64);
-            }
+        guide
+    }
 
-            if depth > DEPTH {
-                if rnd.next_f64() >= russian_roulette_probability {
-                    return sphere.emission;
-                }
-            } else {
-                russian_roulette_probability = 1.0;
-            }
+    fn sample_environment(
+        &self,
+        position: Vec3,
+        wavelengths: Option<Wavelengths>,
+        medium: &dyn Fn(Vec3) -> Option<u32>,
+        bsdf: &dyn Fn(Vec3) -> (Color, f64),
+        rnd: &mut dyn Sampler,
+    ) -> Color {
+        let Some(environment) = self.scene.environment() else {
+            return Color::new(0.0, 0.0, 0.0);
+        };
+
+        let (direction, radiance, light_pdf) = environment.sample(rnd.next_f64(), rnd.next_f64());
+        let radiance = spectrum::illuminant(wavelengths, radiance);
+        if light_pdf == 0.0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
 
-            let incoming_radiance;
-            let weight;
+        let (f, bsdf_pdf) = bsdf(direction);
+        if f.max() <= 0.0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
 
-            match sphere.reflection_type {
-                material::RefrectionType::Diffuse => {
-                    let w = orienting_normal;
-                    let u = if w.x.abs() > 0.1 {
-                        Vec3::new(0.0, 1.0, 0.0)
-                    } else {
-                        Vec3::new(1.0, 0.0, 0.0)
-                    };
-                    let v = w.cross(u);
+        let transmittance = self.scene.transmittance(
+            &Ray::new(position, direction).with_wavelengths(wavelengths),
+            f64::INFINITY,
+            medium(direction),
+            rnd,
+        );
+        if transmittance.max() <= 0.0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
 
-                    let r1 = 2.0 * std::f64::consts::PI * rnd.next_f64();
-                    let r2 = rnd.next_f64();
-                    let r2s = r2.sqrt();
-                    let dir = Vec3::new(r1.cos() * r2s, r1.sin() * r2s, (r2.sqrt()).sqrt());
+        radiance * f * transmittance * (power_heuristic(light_pdf, bsdf_pdf) / light_pdf)
+    }
 
-                    incoming_radiance = self.radiance(
-                        &Ray {
-                            origin: hitpoint.position,
-                            direction: u * dir.x + v * dir.y + w * dir.z,
-                        },
-                        rnd,
-                        depth + 1,
+    fn sample_lights(
+        &self,
+        position: Vec3,
+        wavelengths: Option<Wavelengths>,
+        medium: &dyn Fn(Vec3) -> Option<u32>,
+        bsdf: &dyn Fn(Vec3) -> (Color, f64),
+        rnd: &mut dyn Sampler,
+    ) -> Color {
+        let Some((light_index, selection_pdf)) = self.scene.sample_light(rnd.next_f64()) else {
+            return Color::new(0.0, 0.0, 0.0);
+        };
+        let light = &self.scene.lights()[light_index];
+
+        let Some(sample) = light.sample(
+            &self.scene,
+            position,
+            wavelengths,
+            rnd.next_f64(),
+            rnd.next_f64(),
+        ) else {
+            return Color::new(0.0, 0.0, 0.0);
+        };
+
+        if sample.pdf == 0.0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+
+        let (f, bsdf_pdf) = bsdf(sample.direction);
+        if f.max() <= 0.0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+
+        let transmittance = self.scene.transmittance(
+            &Ray::new(position, sample.direction).with_wavelengths(wavelengths),
+            sample.distance,
+            medium(sample.direction),
+            rnd,
+        );
+        if transmittance.max() <= 0.0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+
+        let light_pdf = sample.pdf * selection_pdf;
+        let mis_weight = if sample.is_delta {
+            1.0
+        } else {
+            power_heuristic(light_pdf, bsdf_pdf)
+        };
+
+        sample.radiance * f * transmittance * (mis_weight / light_pdf)
+    }
+
+    fn sample_direct_lighting(
+        &self,
+        position: Vec3,
+        wavelengths: Option<Wavelengths>,
+        medium: &dyn Fn(Vec3) -> Option<u32>,
+        bsdf: &dyn Fn(Vec3) -> (Color, f64),
+        rnd: &mut dyn Sampler,
+    ) -> Color {
+        self.sample_environment(position, wavelengths, medium, bsdf, rnd)
+            + self.sample_lights(position, wavelengths, medium, bsdf, rnd)
+    }
+
+    fn emitted_radiance(
+        &self,
+        radiance: Color,
+        light_index: Option<usize>,
+        ray: &Ray,
+        bsdf_pdf: Option<f64>,
+    ) -> Color {
+        match (light_index, bsdf_pdf) {
+            (Some(light_index), Some(bsdf_pdf)) => {
+                radiance * power_heuristic(bsdf_pdf, self.scene.light_pdf(light_index, ray))
+            }
+            _ => radiance,
+        }
+    }
+
+    /// Background radiance along an escaping `ray`, weighted against environment sampling
+    /// when the direction came from a density `bsdf_pdf`.
+    fn escaped_radiance(&self, ray: &Ray, bsdf_pdf: Option<f64>) -> Color {
+        let Some(environment) = self.scene.environment() else {
+            return spectrum::illuminant(ray.wavelengths, BACKGROUND_COLOR);
+        };
+
+        let emitted = spectrum::illuminant(ray.wavelengths, environment.radiance(ray.direction));
+        match bsdf_pdf {
+            Some(bsdf_pdf) => emitted * power_heuristic(bsdf_pdf, environment.pdf(ray.direction)),
+            None => emitted,
+        }
+    }
+
+    /// Radiance arriving along `ray`, following a single path that scatters off surfaces and
+    /// media until it escapes, hits a light, is cut off at the maximum depth or is ended by
+    /// Russian roulette.
+    fn radiance(
+        &self,
+        ray: &Ray,
+        rnd: &mut dyn Sampler,
+        cone: RayCone,
+        medium: Option<u32>,
+        guide: Option<&GuidingTree>,
+    ) -> Color {
+        let mut ray = *ray;
+        let mut cone = cone;
+        let mut medium = medium;
+        let mut beta = Color::new(1.0, 1.0, 1.0);
+        let mut radiance = GuidedPath::new();
+        // Density the last direction was sampled with, or `None` after specular scattering.
+        let mut bsdf_pdf = None;
+
+        for depth in 0.. {
+            // Interfaces only switch the medium, so the walk continues through them along the
+            // same ray. Light hits keep using `ray` so their MIS weights match light sampling
+            // from its origin.
+            let trace = self.scene.trace(&ray, medium, rnd);
+            cone = cone.scatter(trace.offset, None);
+            medium = trace.medium;
+            beta = beta * trace.weight;
+
+            let intersection = match trace.event {
+                Event::Medium(medium_id, distance) => {
+                    if depth == self.config.max_depth {
+                        break;
+                    }
+
+                    let segment = trace.segment;
+                    let phase = self.scene.medium(medium_id).phase();
+                    let position = segment.origin + segment.direction * distance;
+
+                    radiance.add(
+                        beta * self.sample_direct_lighting(
+                            position,
+                            segment.wavelengths,
+                            &|_| Some(medium_id),
+                            &|wi| {
+                                let p = phase.eval(segment.direction.dot(wi));
+                                (Color::new(p, p, p), p)
+                            },
+                            rnd,
+                        ),
                     );
-                    weight = sphere.color / russian_roulette_probability;
+
+                    // Phase sampling is exact, so the sample weight is one.
+                    let (direction, pdf) =
+                        phase.sample(segment.direction, rnd.next_f64(), rnd.next_f64());
+                    let Some(probability) =
+                        russian_roulette(depth, self.config.russian_roulette_depth, beta, rnd)
+                    else {
+                        break;
+                    };
+
+                    beta = beta / probability;
+                    bsdf_pdf = Some(pdf);
+                    cone = cone.scatter(distance, Some(pdf));
+                    ray = segment.spawn(position, direction);
+                    continue;
                 }
-                material::RefrectionType::Specular => {
-                    incoming_radiance = self.radiance(
-                        &Ray {
-                            origin: hitpoint.position,
-                            direction: ray.direction
-                                - hitpoint.normal * 2.0 * hitpoint.normal.dot(ray.direction),
-                        },
-                        rnd,
-                        depth + 1,
+                Event::Light(light_index, _) => {
+                    let light = &self.scene.lights()[light_index];
+                    radiance.add(
+                        beta * self.emitted_radiance(
+                            light.emitted(&ray),
+                            Some(light_index),
+                            &ray,
+                            bsdf_pdf,
+                        ),
                     );
-                    weight = sphere.color / russian_roulette_probability;
+                    break;
                 }
-                material::RefrectionType::Refraction => {
-                    let refrection_ray = Ray::new(hit_point.position, ray.direction);
-                    let into = hitpoint.normal.dot(orienting_normal) > 0.0;
-
-                    let nc = 1.0;
-                    let nt = IOR;
-                    let nnt = if into { nc / nt } else { nt / nc };
-                    let ddn = ray.direction.dot(orienting_normal);
-                    let cos2t = 1.0 - nnt * nnt * (1.0 - ddn * ddn);
-
-                    if cos2t < 0.0 {
-                        incoming_radiance = self.radiance(&refrection_ray, rnd, depth + 1);
-                        weight = sphere.color / russian_roulette_probability;
-                    } else {
-                        let refrection_ray = Ray::new(
-                            hitpoint.position,
-                            (ray.direction * nnt
-                                - hitpoint.normal
-                                    * (if into { 1.0 } else { -1.0 })
-                                    * (ddn * nnt + cos2t.sqrt()))
-                            .normalize(),
-                        );
-
-                        let a = nt - nc;
-                        let b = nt + nc;
-                        let r0 = (a * a) / (b * b);
-
-                        let c = 1.0
-                            - (if into {
-                                -ddn
-                            } else {
-                                refrection_ray.direction.dot(hitpoint.normal)
-                            });
-                        let re = r0 + (1.0 - r0) * c.powi(5);
-                        let nnt2 = (if into { nt / nc } else { nc / nt }).powi(2);
-                        let tr = (1.0 - re) * nnt2;
-
-                        let probability = 0.25 + 0.5 * re;
-                        if depth > 2 {
-                            if rnd.next_f64() < probability {
-                                incoming_radiance =
-                                    self.radiance(&refrection_ray, rnd, depth + 1) * re;
-                                weight =
-                                    sphere.color / (probability * russian_roulette_probability);
-                            } else {
-                                incoming_radiance =
-                                    self.radiance(&refrection_ray, rnd, depth + 1) * tr;
-                                weight = sphere.color
-                                    / ((1.0 - probability) * russian_roulette_probability);
-                            }
-                        } else {
-                            incoming_radiance =
-                                self.radiance(&refrection_ray, rnd, depth + 1) * (re + tr);
-                            weight = sphere.color / russian_roulette_probability;
-                        }
-                    }
+                Event::Surface(intersection) => intersection,
+                Event::Escape => {
+                    radiance.add(beta * self.escaped_radiance(&ray, bsdf_pdf));
+                    break;
                 }
+            };
+
+            let hitpoint = intersection.hit_point;
+            let object_id = intersection.object_id;
+
+            let sphere = &self.scene.spheres()[object_id as usize];
+            let material = self.scene.material(sphere.material_id);
+            let bsdf = material.bsdf.as_ref();
+
+            // Past a dispersive surface the path only carries its hero wavelength.
+            let mut wavelengths = ray.wavelengths;
+            let dispersion = match &mut wavelengths {
+                Some(wavelengths) if bsdf.is_dispersive() => wavelengths.terminate_secondary(),
+                _ => Color::new(1.0, 1.0, 1.0),
+            };
+
+            let context = ShadingContext::new(
+                hitpoint.position,
+                hitpoint.uv,
+                hitpoint.uv_footprint(cone.width_at(hitpoint.distance), ray.direction),
+            )
+            .with_wavelength(wavelengths.map(|wavelengths| wavelengths.hero()));
+            radiance.add(
+                beta * self.emitted_radiance(
+                    spectrum::illuminant(ray.wavelengths, material.emission.evaluate(&context)),
+                    self.scene.sphere_light(object_id),
+                    &ray,
+                    bsdf_pdf,
+                ),
+            );
+
+            if depth == self.config.max_depth {
+                break;
             }
+            beta = beta * dispersion;
+
+            let geometric_normal = hitpoint.normal;
+            let wo_world = -ray.direction;
+
+            let frame = material.shading_frame(&hitpoint, &context, wo_world);
+            let wo = frame.to_local(wo_world);
+
+            // Directions must lie on the same side of both normals, otherwise light leaks
+            // through the geometry.
+            let consistent = |wi_world: Vec3, wi: Vec3| wi_world.dot(geometric_normal) * wi.z > 0.0;
+            let medium_toward = |wi_world: Vec3| {
+                if wi_world.dot(geometric_normal) * wo_world.dot(geometric_normal) < 0.0 {
+                    self.scene
+                        .medium_across(material, geometric_normal, wi_world, medium)
+                } else {
+                    medium
+                }
+            };
 
-            return sphere.emission + incoming_radiance * weight;
-        } else {
-            BACKGROUND_COLOR
+            // Specular surfaces have no use for a guide, and no incident light to teach it.
+            let region = guide
+                .filter(|_| !bsdf.is_specular())
+                .map(|guide| guide.region(hitpoint.position));
+            let guided = region.filter(|region| region.is_trained());
+            let mixture_pdf = |bsdf_pdf: f64, wi_world: Vec3| {
+                guided.map_or(bsdf_pdf, |region: &Region| region.pdf(bsdf_pdf, wi_world))
+            };
+
+            if !bsdf.is_specular() {
+                radiance.add(
+                    beta * self.sample_direct_lighting(
+                        hitpoint.position,
+                        wavelengths,
+                        &medium_toward,
+                        &|wi_world| {
+                            let wi = frame.to_local(wi_world);
+                            if !consistent(wi_world, wi) {
+                                return (Color::new(0.0, 0.0, 0.0), 0.0);
+                            }
+                            (
+                                spectrum::reflectance(wavelengths, bsdf.eval(&context, wo, wi))
+                                    * wi.z.abs(),
+                                mixture_pdf(bsdf.pdf(&context, wo, wi), wi_world),
+                            )
+                        },
+                        rnd,
+                    ),
+                );
+            }
+
+            let sample = match guided {
+                Some(region) => region.sample(bsdf, &context, &frame, wo, rnd),
+                None => bsdf.sample(&context, wo, rnd),
+            };
+            let Some(sample) = sample else {
+                break;
+            };
+            let direction = frame.to_world(sample.direction);
+            if !consistent(direction, sample.direction) {
+                break;
+            }
+
+            beta = beta * spectrum::reflectance(wavelengths, sample.weight);
+            let Some(probability) =
+                russian_roulette(depth, self.config.russian_roulette_depth, beta, rnd)
+            else {
+                break;
+            };
+            beta = beta / probability;
+
+            bsdf_pdf = (sample.pdf > 0.0).then_some(sample.pdf);
+            if let (Some(region), Some(pdf)) = (region, bsdf_pdf) {
+                radiance.push(region, direction, pdf, beta, wavelengths);
+            }
+
+            cone = cone.scatter(hitpoint.distance, bsdf_pdf);
+            medium = medium_toward(direction);
+            ray = Ray::new(hitpoint.position, direction).with_wavelengths(wavelengths);
         }
+
+        radiance.finish()
+    }
+}
+
+/// Survival probability of a path continuing with `weight`, or `None` if it is terminated.
+fn russian_roulette(
+    depth: u32,
+    start_depth: u32,
+    weight: Color,
+    rnd: &mut dyn Sampler,
+) -> Option<f64> {
+    if depth <= start_depth {
+        return Some(1.0);
+    }
+
+    let probability = weight.max().min(1.0);
+    (rnd.next_f64() < probability).then_some(probability)
+}
+
+fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    let a = pdf * pdf;
+    let b = other_pdf * other_pdf;
+    if a + b == 0.0 {
+        0.0
+    } else {
+        a / (a + b)
     }
 }
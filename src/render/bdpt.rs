@@ -0,0 +1,965 @@
+use std::f64::consts::PI;
+
+use super::{
+    camera::Camera,
+    film::Film,
+    frame::Frame,
+    material::{self, Color, Material, ShadingContext, TransportMode},
+    medium::HenyeyGreenstein,
+    random::Sampler,
+    ray::Ray,
+    russian_roulette,
+    scene::{Emitter, EmitterSample, Event, Scene},
+    spectrum::{self, Wavelengths},
+    vec3::Vec3,
+    RenderConfig,
+};
+
+#[derive(Clone, Copy)]
+pub enum VertexKind<'a> {
+    Camera,
+    /// Point on an emitter, or where a camera subpath escaped to the environment.
+    Light(Emitter),
+    Surface {
+        material: &'a Material,
+        frame: Frame,
+        context: ShadingContext,
+        /// Light of an emissive sphere.
+        light: Option<usize>,
+    },
+    Medium(HenyeyGreenstein),
+}
+
+#[derive(Clone, Copy)]
+pub struct Vertex<'a> {
+    pub kind: VertexKind<'a>,
+    pub position: Vec3,
+    /// Geometric normal, zero off surfaces.
+    pub normal: Vec3,
+    /// Direction towards the previous vertex of the subpath.
+    pub wo: Vec3,
+    /// Medium around the vertex on the side of `wo`.
+    pub medium: Option<u32>,
+    pub beta: Color,
+    /// Scattered by a specular BSDF, so it cannot be connected to.
+    pub delta: bool,
+    /// Area densities of sampling the vertex from the previous one of its subpath, and from
+    /// the next one in the reverse direction.
+    pub pdf_fwd: f64,
+    pub pdf_rev: f64,
+}
+
+impl<'a> Vertex<'a> {
+    fn new(kind: VertexKind<'a>, position: Vec3, normal: Vec3, beta: Color) -> Vertex<'a> {
+        Vertex {
+            kind,
+            position,
+            normal,
+            wo: Vec3::new(0.0, 0.0, 0.0),
+            medium: None,
+            beta,
+            delta: false,
+            pdf_fwd: 0.0,
+            pdf_rev: 0.0,
+        }
+    }
+
+    fn is_on_surface(&self) -> bool {
+        self.normal.squared_length() > 0.0
+    }
+
+    fn shading_normal(&self) -> Vec3 {
+        match self.kind {
+            VertexKind::Surface { frame, .. } => frame.normal,
+            _ => self.normal,
+        }
+    }
+
+    pub fn emitter(&self) -> Option<Emitter> {
+        match self.kind {
+            VertexKind::Light(emitter) => Some(emitter),
+            VertexKind::Surface {
+                light: Some(light_index),
+                ..
+            } => Some(Emitter::Light(light_index)),
+            _ => None,
+        }
+    }
+}
+
+/// Area densities of sampling the endpoints of a connection, `pt` on the camera subpath and
+/// `qs` on the light subpath, and the vertices before them from the other side. Densities of
+/// vertices that do not exist are zero.
+pub struct Reverse {
+    pub pt: f64,
+    pub pt_minus: f64,
+    pub qs: f64,
+    pub qs_minus: f64,
+}
+
+/// Stands in for the density of specular scattering in ratios of densities, where it cancels
+/// out between the strategies that can sample the path.
+pub fn remap(pdf: f64) -> f64 {
+    if pdf != 0.0 {
+        pdf
+    } else {
+        1.0
+    }
+}
+
+/// Bidirectional path tracer for one camera sample, connecting every prefix of a camera
+/// subpath to every prefix of a light subpath and weighting the strategies with the balance
+/// heuristic.
+pub struct Bdpt<'a> {
+    scene: &'a Scene,
+    camera: &'a Camera,
+    wavelengths: Option<Wavelengths>,
+    /// Maximum number of bounces of a connected path.
+    max_depth: usize,
+    russian_roulette_depth: u32,
+}
+
+impl<'a> Bdpt<'a> {
+    pub fn new(
+        scene: &'a Scene,
+        camera: &'a Camera,
+        config: &RenderConfig,
+        wavelengths: Option<Wavelengths>,
+    ) -> Bdpt<'a> {
+        Bdpt {
+            scene,
+            camera,
+            wavelengths,
+            max_depth: config.max_depth as usize,
+            russian_roulette_depth: config.russian_roulette_depth,
+        }
+    }
+
+    /// RGB radiance arriving along the camera `ray`. Strategies that connect light subpaths
+    /// straight to the camera land on other pixels and are splatted onto `film` instead.
+    pub fn radiance(&self, ray: &Ray, film: &Film, rnd: &mut dyn Sampler) -> Color {
+        let mut dispersive = false;
+        let camera_path = self.camera_subpath(ray, rnd, &mut dispersive);
+        let light_path = self.light_subpath(rnd, &mut dispersive);
+
+        // A dispersive vertex on either subpath leaves only the hero wavelength.
+        let scale = match self.wavelengths {
+            Some(mut wavelengths) if dispersive => wavelengths.terminate_secondary(),
+            _ => Color::new(1.0, 1.0, 1.0),
+        };
+
+        let mut radiance = Color::new(0.0, 0.0, 0.0);
+        for t in 1..=camera_path.len() {
+            for s in 0..=light_path.len() {
+                if s + t < 2 || (s == 1 && t == 1) || s + t - 2 > self.max_depth {
+                    continue;
+                }
+
+                let (contribution, raster) = self.connect(&light_path[..s], &camera_path[..t], rnd);
+                match raster {
+                    Some((x, y)) => film.splat(
+                        x,
+                        y,
+                        spectrum::to_rgb(self.wavelengths, contribution * scale),
+                    ),
+                    None => radiance = radiance + contribution,
+                }
+            }
+        }
+
+        spectrum::to_rgb(self.wavelengths, radiance * scale)
+    }
+
+    pub fn camera_subpath(
+        &self,
+        ray: &Ray,
+        rnd: &mut dyn Sampler,
+        dispersive: &mut bool,
+    ) -> Vec<Vertex<'a>> {
+        let medium = self.scene.global_medium();
+        let mut camera = Vertex::new(
+            VertexKind::Camera,
+            ray.origin,
+            Vec3::new(0.0, 0.0, 0.0),
+            Color::new(1.0, 1.0, 1.0),
+        );
+        camera.medium = medium;
+
+        let mut path = vec![camera];
+        self.random_walk(
+            &mut path,
+            *ray,
+            medium,
+            Color::new(1.0, 1.0, 1.0),
+            self.camera.pdf(ray.direction),
+            TransportMode::Radiance,
+            rnd,
+            dispersive,
+        );
+
+        path
+    }
+
+    pub fn light_subpath(&self, rnd: &mut dyn Sampler, dispersive: &mut bool) -> Vec<Vertex<'a>> {
+        let mut path = Vec::new();
+
+        let Some(EmitterSample {
+            emitter,
+            selection_pdf,
+            emission,
+            beta,
+        }) = self.scene.sample_emitted_ray(self.wavelengths, rnd)
+        else {
+            return path;
+        };
+
+        let direction = emission.ray.direction;
+        let normal = emission.normal.unwrap_or(Vec3::new(0.0, 0.0, 0.0));
+
+        let mut vertex = Vertex::new(
+            VertexKind::Light(emitter),
+            emission.ray.origin,
+            normal,
+            emission.radiance,
+        );
+        vertex.pdf_fwd = emission.pdf_position * selection_pdf;
+        path.push(vertex);
+
+        self.random_walk(
+            &mut path,
+            emission.ray.with_wavelengths(self.wavelengths),
+            self.scene.global_medium(),
+            beta,
+            emission.pdf_direction,
+            TransportMode::Importance,
+            rnd,
+            dispersive,
+        );
+
+        // Rays from infinitely distant lights start on a disk, so the first hit is found with
+        // the density of that disk rather than of a solid angle.
+        if self.is_infinite(&path[0]) {
+            if let Some(first) = path.get_mut(1) {
+                first.pdf_fwd = emission.pdf_position;
+                if first.is_on_surface() {
+                    first.pdf_fwd *= direction.dot(first.normal).abs();
+                }
+            }
+            path[0].pdf_fwd = self.infinite_light_density(direction);
+        }
+
+        path
+    }
+
+    /// Extends `path` from its last vertex along `ray` until it escapes, is absorbed, is ended
+    /// by Russian roulette or holds enough vertices for the longest connected path.
+    #[allow(clippy::too_many_arguments)]
+    fn random_walk(
+        &self,
+        path: &mut Vec<Vertex<'a>>,
+        ray: Ray,
+        medium: Option<u32>,
+        beta: Color,
+        pdf: f64,
+        mode: TransportMode,
+        rnd: &mut dyn Sampler,
+        dispersive: &mut bool,
+    ) {
+        let max_vertices = match mode {
+            TransportMode::Radiance => self.max_depth + 2,
+            TransportMode::Importance => self.max_depth + 1,
+        };
+
+        let mut ray = ray;
+        let mut medium = medium;
+        let mut beta = beta;
+        let mut pdf_fwd = pdf;
+        // Light subpaths start out carrying the emitted power, so Russian roulette looks at
+        // the throughput relative to the start of the walk.
+        let power = beta.max();
+
+        while path.len() < max_vertices {
+            let trace = self.scene.trace(&ray, medium, rnd);
+            beta = beta * trace.weight;
+            if beta.max() <= 0.0 {
+                return;
+            }
+
+            let prev = path[path.len() - 1];
+            let direction = ray.direction;
+
+            let pdf_rev = match trace.event {
+                Event::Medium(medium_id, distance) => {
+                    let phase = self.scene.medium(medium_id).phase();
+                    let position = trace.segment.origin + direction * distance;
+
+                    let mut vertex = Vertex::new(
+                        VertexKind::Medium(phase),
+                        position,
+                        Vec3::new(0.0, 0.0, 0.0),
+                        beta,
+                    );
+                    vertex.wo = -direction;
+                    vertex.medium = Some(medium_id);
+                    vertex.pdf_fwd = self.convert_density(&prev, pdf_fwd, &vertex);
+                    path.push(vertex);
+                    if path.len() >= max_vertices {
+                        return;
+                    }
+
+                    // Phase sampling is exact, so the weight is one.
+                    let (wi, pdf) = phase.sample(direction, rnd.next_f64(), rnd.next_f64());
+                    let Some(probability) = self.russian_roulette(path.len(), beta / power, rnd)
+                    else {
+                        return;
+                    };
+                    beta = beta / probability;
+                    ray = Ray::new(position, wi).with_wavelengths(self.wavelengths);
+                    medium = Some(medium_id);
+                    pdf_fwd = pdf;
+                    pdf
+                }
+                Event::Escape => {
+                    if mode == TransportMode::Radiance && self.scene.environment().is_some() {
+                        let mut vertex = Vertex::new(
+                            VertexKind::Light(Emitter::Environment),
+                            trace.segment.origin + direction,
+                            -direction,
+                            beta,
+                        );
+                        vertex.wo = -direction;
+                        vertex.pdf_fwd = self.convert_density(&prev, pdf_fwd, &vertex);
+                        path.push(vertex);
+                    }
+                    return;
+                }
+                Event::Light(light_index, distance) => {
+                    // Lights have no BSDF and end light subpaths as well.
+                    if mode == TransportMode::Radiance {
+                        let position = trace.segment.origin + direction * distance;
+                        let normal = self.scene.lights()[light_index]
+                            .normal_at(self.scene, position)
+                            .unwrap_or(Vec3::new(0.0, 0.0, 0.0));
+
+                        let mut vertex = Vertex::new(
+                            VertexKind::Light(Emitter::Light(light_index)),
+                            position,
+                            normal,
+                            beta,
+                        );
+                        vertex.wo = -direction;
+                        vertex.medium = trace.medium;
+                        vertex.pdf_fwd = self.convert_density(&prev, pdf_fwd, &vertex);
+                        path.push(vertex);
+                    }
+                    return;
+                }
+                Event::Surface(intersection) => {
+                    let hit = intersection.hit_point;
+                    let sphere = &self.scene.spheres()[intersection.object_id as usize];
+                    let material = self.scene.material(sphere.material_id);
+                    let bsdf = material.bsdf.as_ref();
+                    *dispersive |= bsdf.is_dispersive() && self.wavelengths.is_some();
+
+                    let context = ShadingContext::new(hit.position, hit.uv, 0.0)
+                        .with_wavelength(self.wavelengths.map(|wavelengths| wavelengths.hero()))
+                        .with_mode(mode);
+
+                    let wo_world = -direction;
+                    let frame = material.shading_frame(&hit, &context, wo_world);
+
+                    let mut vertex = Vertex::new(
+                        VertexKind::Surface {
+                            material,
+                            frame,
+                            context,
+                            light: self.scene.sphere_light(intersection.object_id),
+                        },
+                        hit.position,
+                        hit.normal,
+                        beta,
+                    );
+                    vertex.wo = wo_world;
+                    vertex.medium = trace.medium;
+                    vertex.pdf_fwd = self.convert_density(&prev, pdf_fwd, &vertex);
+                    path.push(vertex);
+                    if path.len() >= max_vertices {
+                        return;
+                    }
+
+                    let wo = frame.to_local(wo_world);
+                    let Some(sample) = bsdf.sample(&context, wo, rnd) else {
+                        return;
+                    };
+                    let wi_world = frame.to_world(sample.direction);
+                    if wi_world.dot(hit.normal) * sample.direction.z <= 0.0 {
+                        return;
+                    }
+
+                    beta = beta
+                        * spectrum::reflectance(self.wavelengths, sample.weight)
+                        * material::shading_correction(
+                            mode,
+                            wo_world,
+                            wi_world,
+                            frame.normal,
+                            hit.normal,
+                        );
+                    let Some(probability) = self.russian_roulette(path.len(), beta / power, rnd)
+                    else {
+                        return;
+                    };
+                    beta = beta / probability;
+                    medium = self.medium_toward(&vertex, wi_world);
+                    ray = Ray::new(hit.position, wi_world).with_wavelengths(self.wavelengths);
+
+                    if sample.pdf == 0.0 {
+                        path.last_mut().unwrap().delta = true;
+                        pdf_fwd = 0.0;
+                        0.0
+                    } else {
+                        pdf_fwd = sample.pdf;
+                        bsdf.pdf(&context, sample.direction, wo)
+                    }
+                }
+            };
+
+            let n = path.len();
+            path[n - 2].pdf_rev = self.convert_density(&path[n - 1], pdf_rev, &path[n - 2]);
+        }
+    }
+
+    /// Survival probability of a subpath of `vertices` scattering with throughput `beta`.
+    /// The probabilities are left out of the MIS densities, which only need to agree across
+    /// the strategies for a path.
+    fn russian_roulette(&self, vertices: usize, beta: Color, rnd: &mut dyn Sampler) -> Option<f64> {
+        // Both subpaths start with an endpoint, so the first scattering vertex is the second.
+        russian_roulette(vertices as u32 - 2, self.russian_roulette_depth, beta, rnd)
+    }
+
+    /// Contribution of the path made of the light subpath prefix `light` and the camera
+    /// subpath prefix `camera`, with the raster position when it is seen from elsewhere on the
+    /// film.
+    fn connect(
+        &self,
+        light: &[Vertex<'a>],
+        camera: &[Vertex<'a>],
+        rnd: &mut dyn Sampler,
+    ) -> (Color, Option<(f64, f64)>) {
+        let none = (Color::new(0.0, 0.0, 0.0), None);
+        let (s, t) = (light.len(), camera.len());
+
+        // Paths that ended on a light can only be used as they are.
+        if t > 1 && s != 0 && matches!(camera[t - 1].kind, VertexKind::Light(_)) {
+            return none;
+        }
+
+        let mut sampled = None;
+        let mut raster = None;
+
+        let radiance = if s == 0 {
+            self.join_emitter(&camera[t - 1], &camera[t - 2])
+        } else if t == 1 {
+            let Some((radiance, vertex, position)) = self.join_camera(&light[s - 1], rnd) else {
+                return none;
+            };
+            sampled = Some(vertex);
+            raster = Some(position);
+            radiance
+        } else if s == 1 {
+            let Some((radiance, vertex)) = self.join_light(&camera[t - 1], rnd) else {
+                return none;
+            };
+            sampled = Some(vertex);
+            radiance
+        } else {
+            self.join(&light[s - 1], &camera[t - 1], rnd)
+        };
+
+        if radiance.max() <= 0.0 {
+            return none;
+        }
+
+        (radiance * self.mis_weight(light, camera, sampled), raster)
+    }
+
+    /// Unweighted contribution of a camera subpath ending on an emitter at `pt`.
+    pub fn join_emitter(&self, pt: &Vertex<'a>, pt_minus: &Vertex<'a>) -> Color {
+        self.emitted(pt, pt_minus) * pt.beta
+    }
+
+    /// Unweighted contribution of connecting the light subpath ending at `qs` to a point
+    /// sampled on the camera, returned with that camera vertex and the raster position seen.
+    pub fn join_camera(
+        &self,
+        qs: &Vertex<'a>,
+        rnd: &mut dyn Sampler,
+    ) -> Option<(Color, Vertex<'a>, (f64, f64))> {
+        if !self.is_connectible(qs) {
+            return None;
+        }
+        let sample = self.camera.sample_importance(qs.position)?;
+
+        let mut vertex = Vertex::new(
+            VertexKind::Camera,
+            self.camera.position,
+            Vec3::new(0.0, 0.0, 0.0),
+            Color::new(sample.weight, sample.weight, sample.weight),
+        );
+        vertex.medium = self.scene.global_medium();
+
+        let mut radiance = qs.beta * self.f(qs, &vertex) * vertex.beta;
+        if qs.is_on_surface() {
+            radiance = radiance * sample.direction.dot(qs.shading_normal()).abs();
+        }
+        if radiance.max() > 0.0 {
+            radiance = radiance * self.transmittance(qs, sample.direction, sample.distance, rnd);
+        }
+
+        Some((radiance, vertex, sample.raster))
+    }
+
+    /// Unweighted contribution of connecting the camera subpath ending at `pt` to a point
+    /// sampled on an emitter, returned with that light vertex.
+    pub fn join_light(
+        &self,
+        pt: &Vertex<'a>,
+        rnd: &mut dyn Sampler,
+    ) -> Option<(Color, Vertex<'a>)> {
+        if !self.is_connectible(pt) {
+            return None;
+        }
+        let (vertex, direction, distance) = self.sample_light(pt, rnd)?;
+
+        let mut radiance = pt.beta * self.f(pt, &vertex) * vertex.beta;
+        if pt.is_on_surface() {
+            radiance = radiance * direction.dot(pt.shading_normal()).abs();
+        }
+        if radiance.max() > 0.0 {
+            radiance = radiance * self.transmittance(pt, direction, distance, rnd);
+        }
+
+        Some((radiance, vertex))
+    }
+
+    /// Unweighted contribution of connecting the light subpath ending at `qs` to the camera
+    /// subpath ending at `pt`.
+    pub fn join(&self, qs: &Vertex<'a>, pt: &Vertex<'a>, rnd: &mut dyn Sampler) -> Color {
+        if !self.is_connectible(qs) || !self.is_connectible(pt) {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+
+        let radiance = qs.beta * self.f(qs, pt) * self.f(pt, qs) * pt.beta;
+        if radiance.max() > 0.0 {
+            radiance * self.geometry(pt, qs, rnd)
+        } else {
+            radiance
+        }
+    }
+
+    /// Balance heuristic weight of the strategy that produced the path, found by walking
+    /// along it and comparing the densities of sampling each vertex from either side.
+    fn mis_weight(
+        &self,
+        light: &[Vertex<'a>],
+        camera: &[Vertex<'a>],
+        sampled: Option<Vertex<'a>>,
+    ) -> f64 {
+        let (s, t) = (light.len(), camera.len());
+        if s + t == 2 {
+            return 1.0;
+        }
+
+        let mut light = light.to_vec();
+        let mut camera = camera.to_vec();
+        match sampled {
+            Some(vertex) if s == 1 => light[0] = vertex,
+            Some(vertex) if t == 1 => camera[0] = vertex,
+            _ => {}
+        }
+
+        // The connection endpoints are connected to, whatever their BSDF.
+        camera[t - 1].delta = false;
+        if s > 0 {
+            light[s - 1].delta = false;
+        }
+
+        let reverse = self.reverse_densities(&light, &camera);
+        camera[t - 1].pdf_rev = reverse.pt;
+        if t > 1 {
+            camera[t - 2].pdf_rev = reverse.pt_minus;
+        }
+        if s > 0 {
+            light[s - 1].pdf_rev = reverse.qs;
+        }
+        if s > 1 {
+            light[s - 2].pdf_rev = reverse.qs_minus;
+        }
+
+        let mut sum = 0.0;
+
+        let mut ratio = 1.0;
+        for i in (1..t).rev() {
+            ratio *= remap(camera[i].pdf_rev) / remap(camera[i].pdf_fwd);
+            if !camera[i].delta && !camera[i - 1].delta {
+                sum += ratio;
+            }
+        }
+
+        let mut ratio = 1.0;
+        for i in (0..s).rev() {
+            ratio *= remap(light[i].pdf_rev) / remap(light[i].pdf_fwd);
+            let delta_before = if i > 0 {
+                light[i - 1].delta
+            } else {
+                self.is_delta_light(&light[0])
+            };
+            if !light[i].delta && !delta_before {
+                sum += ratio;
+            }
+        }
+
+        1.0 / (1.0 + sum)
+    }
+
+    /// Densities of sampling the last two vertices of each prefix from the other subpath once
+    /// they are connected, with the sampled endpoint already in place of a lone one.
+    pub fn reverse_densities(&self, light: &[Vertex<'a>], camera: &[Vertex<'a>]) -> Reverse {
+        let (s, t) = (light.len(), camera.len());
+        let pt = &camera[t - 1];
+        let pt_minus = (t > 1).then(|| &camera[t - 2]);
+        let qs = (s > 0).then(|| &light[s - 1]);
+        let qs_minus = (s > 1).then(|| &light[s - 2]);
+
+        Reverse {
+            pt: match qs {
+                Some(qs) => self.pdf(qs, qs_minus, pt),
+                None => self.pdf_light_origin(pt, pt_minus.unwrap()),
+            },
+            pt_minus: match (pt_minus, qs) {
+                (Some(pt_minus), Some(qs)) => self.pdf(pt, Some(qs), pt_minus),
+                (Some(pt_minus), None) => self.pdf_light(pt, pt_minus),
+                (None, _) => 0.0,
+            },
+            qs: qs.map_or(0.0, |qs| self.pdf(pt, pt_minus, qs)),
+            qs_minus: match (qs, qs_minus) {
+                (Some(qs), Some(qs_minus)) => self.pdf(qs, Some(pt), qs_minus),
+                _ => 0.0,
+            },
+        }
+    }
+
+    /// Samples a point on an emitter to connect `pt` to, returning it with the direction and
+    /// distance from `pt`.
+    fn sample_light(
+        &self,
+        pt: &Vertex<'a>,
+        rnd: &mut dyn Sampler,
+    ) -> Option<(Vertex<'a>, Vec3, f64)> {
+        let (emitter, selection_pdf) = self.scene.sample_emitter(rnd.next_f64())?;
+        let (u0, u1) = (rnd.next_f64(), rnd.next_f64());
+        let (_, scene_radius) = self.scene.bounding_sphere();
+
+        let (direction, distance, radiance, pdf, position, normal) = match emitter {
+            Emitter::Light(light_index) => {
+                let light = &self.scene.lights()[light_index];
+                let sample = light.sample(self.scene, pt.position, self.wavelengths, u0, u1)?;
+                let (position, normal) = if light.is_infinite() {
+                    (
+                        pt.position + sample.direction * (2.0 * scene_radius),
+                        Vec3::new(0.0, 0.0, 0.0),
+                    )
+                } else {
+                    let position = pt.position + sample.direction * sample.distance;
+                    let normal = light
+                        .normal_at(self.scene, position)
+                        .unwrap_or(Vec3::new(0.0, 0.0, 0.0));
+                    (position, normal)
+                };
+
+                (
+                    sample.direction,
+                    sample.distance,
+                    sample.radiance,
+                    sample.pdf,
+                    position,
+                    normal,
+                )
+            }
+            Emitter::Environment => {
+                let (direction, radiance, pdf) = self.scene.environment()?.sample(u0, u1);
+                (
+                    direction,
+                    f64::INFINITY,
+                    spectrum::illuminant(self.wavelengths, radiance),
+                    pdf,
+                    pt.position + direction * (2.0 * scene_radius),
+                    Vec3::new(0.0, 0.0, 0.0),
+                )
+            }
+        };
+
+        if pdf == 0.0 || radiance.max() <= 0.0 {
+            return None;
+        }
+
+        let mut vertex = Vertex::new(
+            VertexKind::Light(emitter),
+            position,
+            normal,
+            radiance / (pdf * selection_pdf),
+        );
+        vertex.pdf_fwd = self.pdf_light_origin(&vertex, pt);
+
+        Some((vertex, direction, distance))
+    }
+
+    fn is_infinite(&self, vertex: &Vertex) -> bool {
+        match vertex.kind {
+            VertexKind::Light(Emitter::Environment) => true,
+            VertexKind::Light(Emitter::Light(light_index)) => {
+                self.scene.lights()[light_index].is_infinite()
+            }
+            _ => false,
+        }
+    }
+
+    pub fn is_delta_light(&self, vertex: &Vertex) -> bool {
+        match vertex.kind {
+            VertexKind::Light(Emitter::Light(light_index)) => {
+                self.scene.lights()[light_index].is_delta()
+            }
+            _ => false,
+        }
+    }
+
+    pub fn is_connectible(&self, vertex: &Vertex) -> bool {
+        match vertex.kind {
+            VertexKind::Surface { material, .. } => !material.bsdf.is_specular(),
+            VertexKind::Light(Emitter::Light(light_index)) => {
+                !self.scene.lights()[light_index].is_infinite()
+            }
+            _ => true,
+        }
+    }
+
+    fn medium_toward(&self, vertex: &Vertex, direction: Vec3) -> Option<u32> {
+        match vertex.kind {
+            VertexKind::Surface { material, .. }
+                if direction.dot(vertex.normal) * vertex.wo.dot(vertex.normal) < 0.0 =>
+            {
+                self.scene
+                    .medium_across(material, vertex.normal, direction, vertex.medium)
+            }
+            _ => vertex.medium,
+        }
+    }
+
+    fn transmittance(
+        &self,
+        from: &Vertex,
+        direction: Vec3,
+        distance: f64,
+        rnd: &mut dyn Sampler,
+    ) -> Color {
+        self.scene.transmittance(
+            &Ray::new(from.position, direction).with_wavelengths(self.wavelengths),
+            distance,
+            self.medium_toward(from, direction),
+            rnd,
+        )
+    }
+
+    /// Geometry term between two scene vertices, including the transmittance between them.
+    fn geometry(&self, a: &Vertex, b: &Vertex, rnd: &mut dyn Sampler) -> Color {
+        let to_b = b.position - a.position;
+        let distance = to_b.length();
+        let direction = to_b / distance;
+
+        let mut g = 1.0 / (distance * distance);
+        if a.is_on_surface() {
+            g *= direction.dot(a.shading_normal()).abs();
+        }
+        if b.is_on_surface() {
+            g *= direction.dot(b.shading_normal()).abs();
+        }
+
+        self.transmittance(a, direction, distance, rnd) * g
+    }
+
+    /// BSDF or phase function at `vertex` for light travelling between `next` and the previous
+    /// vertex of its subpath.
+    pub fn f(&self, vertex: &Vertex, next: &Vertex) -> Color {
+        let to_next = next.position - vertex.position;
+        if to_next.squared_length() == 0.0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+        let wi = to_next.normalize();
+
+        match vertex.kind {
+            VertexKind::Surface {
+                material,
+                frame,
+                context,
+                ..
+            } => {
+                let wi_local = frame.to_local(wi);
+                if wi.dot(vertex.normal) * wi_local.z <= 0.0 {
+                    return Color::new(0.0, 0.0, 0.0);
+                }
+
+                let f = material
+                    .bsdf
+                    .eval(&context, frame.to_local(vertex.wo), wi_local);
+                spectrum::reflectance(self.wavelengths, f)
+                    * material::shading_correction(
+                        context.mode,
+                        vertex.wo,
+                        wi,
+                        frame.normal,
+                        vertex.normal,
+                    )
+            }
+            VertexKind::Medium(phase) => {
+                let p = phase.eval(-vertex.wo.dot(wi));
+                Color::new(p, p, p)
+            }
+            _ => Color::new(0.0, 0.0, 0.0),
+        }
+    }
+
+    /// Converts a solid angle density at `from` to an area density at `next`.
+    fn convert_density(&self, from: &Vertex, pdf: f64, next: &Vertex) -> f64 {
+        if self.is_infinite(next) {
+            return pdf;
+        }
+
+        let to_next = next.position - from.position;
+        let distance_squared = to_next.squared_length();
+        if distance_squared == 0.0 {
+            return 0.0;
+        }
+
+        let mut pdf = pdf / distance_squared;
+        if next.is_on_surface() {
+            pdf *= next.normal.dot(to_next).abs() / distance_squared.sqrt();
+        }
+        pdf
+    }
+
+    /// Area density of sampling `next` from `vertex`, which was reached from `prev`.
+    pub fn pdf(&self, vertex: &Vertex, prev: Option<&Vertex>, next: &Vertex) -> f64 {
+        if let VertexKind::Light(_) = vertex.kind {
+            return self.pdf_light(vertex, next);
+        }
+
+        let to_next = next.position - vertex.position;
+        if to_next.squared_length() == 0.0 {
+            return 0.0;
+        }
+        let wn = to_next.normalize();
+        let wp = prev.map(|prev| (prev.position - vertex.position).normalize());
+
+        let pdf = match (vertex.kind, wp) {
+            (VertexKind::Camera, _) => self.camera.pdf(wn),
+            (
+                VertexKind::Surface {
+                    material,
+                    frame,
+                    context,
+                    ..
+                },
+                Some(wp),
+            ) => {
+                let (wo, wi) = (frame.to_local(wp), frame.to_local(wn));
+                if wp.dot(vertex.normal) * wo.z <= 0.0 || wn.dot(vertex.normal) * wi.z <= 0.0 {
+                    return 0.0;
+                }
+                material.bsdf.pdf(&context, wo, wi)
+            }
+            (VertexKind::Medium(phase), Some(wp)) => phase.eval(-wp.dot(wn)),
+            _ => 0.0,
+        };
+
+        self.convert_density(vertex, pdf, next)
+    }
+
+    /// Area density at `next` of light emitted from the emitter at `vertex`.
+    pub fn pdf_light(&self, vertex: &Vertex, next: &Vertex) -> f64 {
+        let to_next = next.position - vertex.position;
+        let distance_squared = to_next.squared_length();
+        let direction = to_next / distance_squared.sqrt();
+
+        let mut pdf = if self.is_infinite(vertex) {
+            let (_, radius) = self.scene.bounding_sphere();
+            1.0 / (PI * radius * radius)
+        } else {
+            match vertex.emitter() {
+                Some(Emitter::Light(light_index)) => {
+                    let (_, pdf_direction) = self.scene.lights()[light_index].pdf_emission(
+                        self.scene,
+                        vertex.position,
+                        direction,
+                    );
+                    pdf_direction / distance_squared
+                }
+                _ => 0.0,
+            }
+        };
+
+        if next.is_on_surface() {
+            pdf *= next.normal.dot(direction).abs();
+        }
+        pdf
+    }
+
+    /// Density of choosing the emitter at `vertex` and the point on it, towards `next`.
+    pub fn pdf_light_origin(&self, vertex: &Vertex, next: &Vertex) -> f64 {
+        let direction = (next.position - vertex.position).normalize();
+        if self.is_infinite(vertex) {
+            return self.infinite_light_density(direction);
+        }
+
+        match vertex.emitter() {
+            Some(Emitter::Light(light_index)) => {
+                let (pdf_position, _) = self.scene.lights()[light_index].pdf_emission(
+                    self.scene,
+                    vertex.position,
+                    direction,
+                );
+                pdf_position * self.scene.emitter_pdf(Emitter::Light(light_index))
+            }
+            _ => 0.0,
+        }
+    }
+
+    /// Density of sampling light arriving along `direction` from the environment.
+    fn infinite_light_density(&self, direction: Vec3) -> f64 {
+        self.scene.environment().map_or(0.0, |environment| {
+            self.scene.emitter_pdf(Emitter::Environment) * environment.pdf(-direction)
+        })
+    }
+
+    /// Radiance emitted from the emitter at `vertex` towards `prev`.
+    fn emitted(&self, vertex: &Vertex, prev: &Vertex) -> Color {
+        let direction = (prev.position - vertex.position).normalize();
+
+        match vertex.kind {
+            VertexKind::Light(Emitter::Environment) => match self.scene.environment() {
+                Some(environment) => {
+                    spectrum::illuminant(self.wavelengths, environment.radiance(-direction))
+                }
+                None => Color::new(0.0, 0.0, 0.0),
+            },
+            VertexKind::Light(Emitter::Light(light_index)) => self.scene.lights()[light_index]
+                .emitted(&Ray::new(prev.position, -direction).with_wavelengths(self.wavelengths)),
+            VertexKind::Surface {
+                material,
+                context,
+                light: Some(_),
+                ..
+            } => spectrum::illuminant(self.wavelengths, material.emission.evaluate(&context)),
+            _ => Color::new(0.0, 0.0, 0.0),
+        }
+    }
+}
@@ -0,0 +1,119 @@
+use super::{
+    ray::{Ray, RayCone},
+    vec3::Vec3,
+};
+
+const SCREEN_HEIGHT: f64 = 30.0;
+const SCREEN_DISTANCE: f64 = 40.0;
+
+/// Pinhole camera looking through a screen in front of it.
+#[derive(Debug, Clone, Copy)]
+pub struct Camera {
+    pub position: Vec3,
+    direction: Vec3,
+    screen_center: Vec3,
+    screen_x: Vec3,
+    screen_y: Vec3,
+    width: u32,
+    height: u32,
+}
+
+/// Connection from a point to the camera.
+pub struct CameraSample {
+    pub raster: (f64, f64),
+    /// Direction from the point towards the camera.
+    pub direction: Vec3,
+    pub distance: f64,
+    /// Importance arriving at the point divided by the pdf of the connection.
+    pub weight: f64,
+}
+
+impl Camera {
+    pub fn new(position: Vec3, direction: Vec3, up: Vec3, width: u32, height: u32) -> Camera {
+        let direction = direction.normalize();
+        let screen_width = SCREEN_HEIGHT * width as f64 / height as f64;
+
+        let screen_x = direction.cross(up).normalize() * screen_width;
+        let screen_y = screen_x.cross(direction).normalize() * SCREEN_HEIGHT;
+
+        Camera {
+            position,
+            direction,
+            screen_center: position + direction * SCREEN_DISTANCE,
+            screen_x,
+            screen_y,
+            width,
+            height,
+        }
+    }
+
+    /// Ray through the raster position `(x, y)` in pixels.
+    pub fn ray(&self, x: f64, y: f64) -> Ray {
+        let screen_position = self.screen_center
+            + self.screen_x * (x / self.width as f64 - 0.5)
+            + self.screen_y * (y / self.height as f64 - 0.5);
+
+        Ray::new(self.position, (screen_position - self.position).normalize())
+    }
+
+    /// Cone spanning one pixel.
+    pub fn cone(&self) -> RayCone {
+        RayCone::new(0.0, SCREEN_HEIGHT / self.height as f64 / SCREEN_DISTANCE)
+    }
+
+    /// Screen area at unit distance.
+    fn area(&self) -> f64 {
+        self.screen_x.length() * self.screen_y.length() / (SCREEN_DISTANCE * SCREEN_DISTANCE)
+    }
+
+    /// Raster position seen along `direction`, if it lies on the screen.
+    pub fn raster(&self, direction: Vec3) -> Option<(f64, f64)> {
+        let cos_theta = direction.dot(self.direction);
+        if cos_theta <= 0.0 {
+            return None;
+        }
+
+        let offset = self.position + direction * (SCREEN_DISTANCE / cos_theta) - self.screen_center;
+        let x =
+            (offset.dot(self.screen_x) / self.screen_x.squared_length() + 0.5) * self.width as f64;
+        let y =
+            (offset.dot(self.screen_y) / self.screen_y.squared_length() + 0.5) * self.height as f64;
+
+        ((0.0..self.width as f64).contains(&x) && (0.0..self.height as f64).contains(&y))
+            .then_some((x, y))
+    }
+
+    /// Solid angle pdf of a camera ray along `direction` with positions spread uniformly over
+    /// the screen.
+    pub fn pdf(&self, direction: Vec3) -> f64 {
+        if self.raster(direction).is_none() {
+            return 0.0;
+        }
+
+        let cos_theta = direction.dot(self.direction);
+        1.0 / (self.area() * cos_theta * cos_theta * cos_theta)
+    }
+
+    /// Importance emitted along `direction`, normalized over the whole screen.
+    pub fn importance(&self, direction: Vec3) -> f64 {
+        let cos_theta = direction.dot(self.direction);
+        self.pdf(direction) / cos_theta
+    }
+
+    pub fn sample_importance(&self, point: Vec3) -> Option<CameraSample> {
+        let to_camera = self.position - point;
+        let distance = to_camera.length();
+        let direction = to_camera / distance;
+        let raster = self.raster(-direction)?;
+
+        let cos_theta = -direction.dot(self.direction);
+        let pdf = distance * distance / cos_theta;
+
+        Some(CameraSample {
+            raster,
+            direction,
+            distance,
+            weight: self.importance(-direction) / pdf,
+        })
+    }
+}
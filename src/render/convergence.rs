@@ -0,0 +1,261 @@
+use std::f64::consts::PI;
+
+use super::{
+    light::Light,
+    material::{Color, Lambertian, Material, RoughDielectric, IOR},
+    random::{Sampler, XorShiftRandom},
+    ray::Ray,
+    scene::Scene,
+    sphere::Sphere,
+    vec3::Vec3,
+    Integrator, Render, RenderConfig,
+};
+
+/// Paths averaged per estimate, growing fourfold so the error should halve at each step.
+const SAMPLE_COUNTS: [u32; 4] = [16, 64, 256, 1024];
+/// Independent estimates the error at each sample count is measured over.
+const RUNS: u32 = 32;
+/// Slack on the slope of -1/2 of the error against the sample count on log-log axes.
+const SLOPE_TOLERANCE: f64 = 0.15;
+
+fn config(russian_roulette_depth: u32) -> RenderConfig {
+    RenderConfig {
+        width: 1,
+        height: 1,
+        tasks: 1,
+        samples: 1,
+        super_samples: 1,
+        spectral: false,
+        integrator: Integrator::Path,
+        max_depth: 64,
+        russian_roulette_depth,
+        guiding: false,
+        merge_radius: 1.0,
+        merge_radius_alpha: 0.75,
+        ao_radius: 30.0,
+    }
+}
+
+fn render(scene: Scene, russian_roulette_depth: u32) -> Render {
+    Render::new(config(russian_roulette_depth), scene)
+}
+
+fn radiance(render: &Render, ray: &Ray, rnd: &mut dyn Sampler) -> f64 {
+    render
+        .radiance(ray, rnd, render.camera.cone(), None, None)
+        .luminance()
+}
+
+/// Checks that averages of `estimate` converge to `exact` at the Monte Carlo rate of one over
+/// the square root of the number of paths, without a bias showing at the largest count.
+fn assert_converges(name: &str, exact: f64, estimate: impl Fn(&mut dyn Sampler) -> f64) {
+    let mut errors = Vec::new();
+    let mut means = Vec::new();
+
+    for (step, &samples) in SAMPLE_COUNTS.iter().enumerate() {
+        let estimates: Vec<f64> = (0..RUNS)
+            .map(|run| {
+                let mut rnd = XorShiftRandom::new(step as u32 * RUNS + run + 1);
+                (0..samples).map(|_| estimate(&mut rnd)).sum::<f64>() / samples as f64
+            })
+            .collect();
+
+        let squared_error: f64 = estimates.iter().map(|e| (e - exact).powi(2)).sum();
+        errors.push((squared_error / RUNS as f64).sqrt());
+        means.push(estimates.iter().sum::<f64>() / RUNS as f64);
+    }
+
+    // Least squares fit of the log error against the log sample count.
+    let points: Vec<(f64, f64)> = SAMPLE_COUNTS
+        .iter()
+        .zip(&errors)
+        .map(|(&samples, &error)| ((samples as f64).ln(), error.ln()))
+        .collect();
+    let n = points.len() as f64;
+    let (mean_x, mean_y) = (
+        points.iter().map(|p| p.0).sum::<f64>() / n,
+        points.iter().map(|p| p.1).sum::<f64>() / n,
+    );
+    let slope = points
+        .iter()
+        .map(|(x, y)| (x - mean_x) * (y - mean_y))
+        .sum::<f64>()
+        / points
+            .iter()
+            .map(|(x, _)| (x - mean_x).powi(2))
+            .sum::<f64>();
+
+    assert!(
+        (slope + 0.5).abs() < SLOPE_TOLERANCE,
+        "{name}: errors {errors:?} fall with slope {slope} instead of -1/2"
+    );
+
+    let mean = means[means.len() - 1];
+    let standard_error = errors[errors.len() - 1] / (RUNS as f64).sqrt();
+    assert!(
+        (mean - exact).abs() < 4.0 * standard_error,
+        "{name}: converges to {mean} instead of {exact}"
+    );
+}
+
+#[test]
+fn furnace() {
+    // A white sphere inside an enclosure with `albedo` that emits `emission` leaves the
+    // radiance everywhere uniform, at `emission / (1 - albedo)`. Russian roulette from the
+    // first bounce gives the paths their variance.
+    let (emission, albedo) = (1.0, 0.5);
+
+    let mut scene = Scene::empty();
+    let enclosure = scene.add_material(Material::emissive(
+        Lambertian::new(Color::new(albedo, albedo, albedo)),
+        Color::new(emission, emission, emission),
+    ));
+    scene.add_sphere(Sphere::new(100.0, Vec3::new(0.0, 0.0, 0.0), enclosure));
+    let white = scene.add_material(Material::new(Lambertian::new(Color::new(1.0, 1.0, 1.0))));
+    scene.add_sphere(Sphere::new(30.0, Vec3::new(0.0, 0.0, 0.0), white));
+
+    let render = render(scene, 0);
+    let ray = Ray::new(Vec3::new(0.0, 0.0, 60.0), Vec3::new(0.0, 0.0, -1.0));
+    assert_converges("furnace", emission / (1.0 - albedo), |rnd| {
+        radiance(&render, &ray, rnd)
+    });
+}
+
+#[test]
+fn metropolis_furnace() {
+    // The furnace seen by the camera, which Metropolis light transport only gets right on
+    // average over the image. The mutations split unevenly over its chains, which must not
+    // change the brightness.
+    let (emission, albedo) = (1.0, 0.5);
+
+    let mut scene = Scene::empty();
+    let enclosure = scene.add_material(Material::emissive(
+        Lambertian::new(Color::new(albedo, albedo, albedo)),
+        Color::new(emission, emission, emission),
+    ));
+    scene.add_sphere(Sphere::new(100.0, Vec3::new(50.0, 52.0, 220.0), enclosure));
+    let white = scene.add_material(Material::new(Lambertian::new(Color::new(1.0, 1.0, 1.0))));
+    scene.add_sphere(Sphere::new(30.0, Vec3::new(50.0, 52.0, 170.0), white));
+
+    let config = RenderConfig {
+        width: 32,
+        height: 24,
+        integrator: Integrator::Metropolis,
+        ..config(0)
+    };
+    let image = Render::new(config, scene).render();
+
+    let exact = emission / (1.0 - albedo);
+    let mean = image.iter().map(|pixel| pixel.luminance()).sum::<f64>() / image.len() as f64;
+    assert!(
+        (mean - exact).abs() < 0.02 * exact,
+        "metropolis furnace: averages {mean} instead of {exact}"
+    );
+}
+
+#[test]
+fn lambertian_plane_under_point_light() {
+    // A plane with `albedo` at distance `r` from below a point light at `height` reflects
+    // `albedo I height / (π (height² + r²)^(3/2))`, which averages to
+    // `2 albedo I (1 - height / √(height² + radius²)) / (π radius²)` over a disk.
+    let (albedo, intensity, height, radius) = (0.5, 100.0, 10.0, 10.0);
+
+    let mut scene = Scene::empty();
+    let plane = scene.add_material(Material::new(Lambertian::new(Color::new(
+        albedo, albedo, albedo,
+    ))));
+    scene.add_sphere(Sphere::new(1e5, Vec3::new(0.0, -1e5, 0.0), plane));
+    scene.add_light(Light::Point {
+        position: Vec3::new(0.0, height, 0.0),
+        intensity: Color::new(intensity, intensity, intensity).into(),
+        profile: None,
+    });
+
+    let render = render(scene, 5);
+    let exact =
+        2.0 * albedo * intensity * (1.0 - height / (height * height + radius * radius).sqrt())
+            / (PI * radius * radius);
+    assert_converges("lambertian plane", exact, |rnd| {
+        // Uniform points on the disk, seen from straight above.
+        let r = radius * rnd.next_f64().sqrt();
+        let phi = 2.0 * PI * rnd.next_f64();
+        let ray = Ray::new(
+            Vec3::new(r * phi.cos(), 1.0, r * phi.sin()),
+            Vec3::new(0.0, -1.0, 0.0),
+        );
+        radiance(&render, &ray, rnd)
+    });
+}
+
+#[test]
+fn glass_sphere_transmittance() {
+    // Along the axis each interface reflects `R = ((n - 1) / (n + 1))²` straight back, so
+    // light crosses the sphere to the emitter behind it with probability
+    // `(1 - R)² Σ R^(2k) = (1 - R) / (1 + R)`.
+    let emission = 1.0;
+    let reflectance = ((IOR - 1.0) / (IOR + 1.0)).powi(2);
+
+    let mut scene = Scene::empty();
+    let glass = scene.add_material(Material::new(RoughDielectric::new(IOR, 0.0)));
+    scene.add_sphere(Sphere::new(10.0, Vec3::new(0.0, 0.0, 0.0), glass));
+    let backdrop = scene.add_material(Material::emissive(
+        Lambertian::new(Color::new(0.0, 0.0, 0.0)),
+        Color::new(emission, emission, emission),
+    ));
+    scene.add_sphere(Sphere::new(1e5, Vec3::new(0.0, 0.0, -1e5 - 50.0), backdrop));
+
+    let render = render(scene, 5);
+    let ray = Ray::new(Vec3::new(0.0, 0.0, 50.0), Vec3::new(0.0, 0.0, -1.0));
+    assert_converges(
+        "glass sphere",
+        emission * (1.0 - reflectance) / (1.0 + reflectance),
+        |rnd| radiance(&render, &ray, rnd),
+    );
+}
+
+#[test]
+fn russian_roulette_keeps_the_mean() {
+    // Ending paths by their throughput and reweighting the survivors must leave the mean
+    // radiance of the foggy box, with its media, glass and delta lights, where following
+    // every path to the maximum depth puts it.
+    const PATHS: u32 = 4000;
+
+    let mean_and_error = |russian_roulette_depth: u32| {
+        let render = render(
+            Scene::preset("foggy").expect("Failed to find scene"),
+            russian_roulette_depth,
+        );
+        let mut rnd = XorShiftRandom::new(1);
+        let values: Vec<f64> = (0..PATHS)
+            .map(|_| {
+                // The single pixel spans the whole view.
+                let ray = render.camera.ray(rnd.next_f64(), rnd.next_f64());
+                render
+                    .radiance(
+                        &ray,
+                        &mut rnd,
+                        render.camera.cone(),
+                        render.scene.global_medium(),
+                        None,
+                    )
+                    .luminance()
+            })
+            .collect();
+
+        let n = PATHS as f64;
+        let mean = values.iter().sum::<f64>() / n;
+        let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (n - 1.0);
+        (mean, (variance / n).sqrt())
+    };
+
+    let (exact, exact_error) = mean_and_error(64);
+    for russian_roulette_depth in [0, 5] {
+        let (mean, error) = mean_and_error(russian_roulette_depth);
+        let tolerance = 4.0 * (error * error + exact_error * exact_error).sqrt();
+        assert!(
+            (mean - exact).abs() < tolerance,
+            "Russian roulette from depth {russian_roulette_depth} gives {mean} instead of \
+             {exact} (± {tolerance})"
+        );
+    }
+}
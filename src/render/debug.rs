@@ -0,0 +1,175 @@
+use std::f64::consts::PI;
+
+use super::{
+    camera::Camera,
+    frame::Frame,
+    intersection::Intersection,
+    material::{Color, ShadingContext},
+    random::Sampler,
+    ray::{Ray, RAY_EPSILON},
+    sampling::cosine_hemisphere,
+    scene::Scene,
+    vec3::Vec3,
+    DebugView,
+};
+
+/// BSDF samples averaged to estimate the albedo at a first hit.
+const ALBEDO_SAMPLES: u32 = 16;
+/// Full passes over the scene's primitives a camera ray may take, through interfaces, before
+/// its cost shows as red.
+const HEATMAP_SEGMENTS: f64 = 4.0;
+
+/// Fast diagnostic views of the first opaque surface along camera rays. Interfaces are seen
+/// through, and area lights show as background.
+pub struct Debug<'a> {
+    scene: &'a Scene,
+    camera: &'a Camera,
+}
+
+impl<'a> Debug<'a> {
+    pub fn new(scene: &'a Scene, camera: &'a Camera) -> Debug<'a> {
+        Debug { scene, camera }
+    }
+
+    /// Fraction of cosine-weighted directions above the first hit that are not blocked within
+    /// `radius`.
+    pub fn ambient_occlusion(&self, ray: &Ray, radius: f64, rnd: &mut dyn Sampler) -> Color {
+        let (Some(intersection), _) = self.first_hit(ray) else {
+            return Color::new(0.0, 0.0, 0.0);
+        };
+
+        let hitpoint = intersection.hit_point;
+        let normal = if hitpoint.normal.dot(ray.direction) < 0.0 {
+            hitpoint.normal
+        } else {
+            -hitpoint.normal
+        };
+
+        let direction =
+            Frame::from_normal(normal).to_world(cosine_hemisphere(rnd.next_f64(), rnd.next_f64()));
+
+        let visibility =
+            self.scene
+                .transmittance(&Ray::new(hitpoint.position, direction), radius, None, rnd);
+        if visibility.max() > 0.0 {
+            Color::new(1.0, 1.0, 1.0)
+        } else {
+            Color::new(0.0, 0.0, 0.0)
+        }
+    }
+
+    pub fn visualize(&self, ray: &Ray, view: DebugView, rnd: &mut dyn Sampler) -> Color {
+        let (intersection, tests) = self.first_hit(ray);
+
+        let color = match (view, intersection) {
+            (DebugView::TraversalCost, _) => {
+                let primitives = self.scene.spheres().len()
+                    + self
+                        .scene
+                        .lights()
+                        .iter()
+                        .filter(|light| light.has_surface())
+                        .count();
+                heatmap(tests as f64 / (primitives.max(1) as f64 * HEATMAP_SEGMENTS))
+            }
+            (DebugView::Depth, None) => Color::new(0.0, 0.0, 0.0),
+            (DebugView::Depth, Some(intersection)) => {
+                let (center, radius) = self.scene.bounding_sphere();
+                let far = (center - self.camera.position).length() + radius;
+                let depth = (intersection.hit_point.distance / far).min(1.0);
+                Color::new(1.0 - depth, 1.0 - depth, 1.0 - depth)
+            }
+            (_, None) => Color::new(0.0, 0.0, 0.0),
+            (DebugView::Normal, Some(intersection)) => {
+                let (_, frame, _) = self.shade(ray, &intersection);
+                frame.normal * 0.5 + Vec3::new(0.5, 0.5, 0.5)
+            }
+            (DebugView::Uv, Some(intersection)) => {
+                let (u, v) = intersection.hit_point.uv;
+                Color::new(u.fract(), v.fract(), 0.0)
+            }
+            (DebugView::MaterialId, Some(intersection)) => {
+                palette(self.scene.spheres()[intersection.object_id as usize].material_id)
+            }
+            (DebugView::Albedo, Some(intersection)) => {
+                let (context, frame, material_id) = self.shade(ray, &intersection);
+                let bsdf = self.scene.material(material_id).bsdf.as_ref();
+                let wo = frame.to_local(-ray.direction);
+
+                let albedo = (0..ALBEDO_SAMPLES)
+                    .filter_map(|_| bsdf.sample(&context, wo, rnd))
+                    .fold(Color::new(0.0, 0.0, 0.0), |sum, sample| sum + sample.weight);
+                albedo / ALBEDO_SAMPLES as f64
+            }
+        };
+
+        // Views are meant to be read off the saved image, so the gamma it is encoded with is
+        // undone.
+        Color::new(
+            color.x.max(0.0).powf(2.2),
+            color.y.max(0.0).powf(2.2),
+            color.z.max(0.0).powf(2.2),
+        )
+    }
+
+    /// First opaque surface along `ray`, if it comes before any area light, with the number
+    /// of intersection tests it took to find.
+    fn first_hit(&self, ray: &Ray) -> (Option<Intersection>, usize) {
+        let mut segment = *ray;
+        let mut tests = 0;
+
+        loop {
+            let light = self
+                .scene
+                .intersect_area_light_counted(&segment, &mut tests);
+            let Some(intersection) =
+                self.scene
+                    .intersect_counted(&segment, &mut tests)
+                    .filter(|intersection| {
+                        light.is_none_or(|(_, distance)| intersection.hit_point.distance < distance)
+                    })
+            else {
+                return (None, tests);
+            };
+
+            let sphere = &self.scene.spheres()[intersection.object_id as usize];
+            if !self.scene.material(sphere.material_id).bsdf.is_interface() {
+                return (Some(intersection), tests);
+            }
+            segment = segment.advance(intersection.hit_point.distance + RAY_EPSILON);
+        }
+    }
+
+    /// Shading context and frame at a hit, with its material.
+    fn shade(&self, ray: &Ray, intersection: &Intersection) -> (ShadingContext, Frame, u32) {
+        let hitpoint = intersection.hit_point;
+        let material_id = self.scene.spheres()[intersection.object_id as usize].material_id;
+        let material = self.scene.material(material_id);
+
+        let context = ShadingContext::new(hitpoint.position, hitpoint.uv, 0.0);
+        let wo = -ray.direction;
+        let frame = material.shading_frame(&hitpoint, &context, wo);
+
+        (context, frame, material_id)
+    }
+}
+
+/// Ramp from blue through green to red over `t` in `[0, 1]`.
+pub fn heatmap(t: f64) -> Color {
+    let t = t.clamp(0.0, 1.0);
+    if t < 0.5 {
+        Color::new(0.0, 2.0 * t, 1.0 - 2.0 * t)
+    } else {
+        Color::new(2.0 * t - 1.0, 2.0 - 2.0 * t, 0.0)
+    }
+}
+
+/// Distinct color for each id, stepping around the hues by the golden ratio.
+fn palette(id: u32) -> Color {
+    let hue = (id as f64 * 0.618_033_988_75).fract() * 2.0 * PI;
+    Color::new(
+        0.5 + 0.5 * hue.cos(),
+        0.5 + 0.5 * (hue - 2.0 * PI / 3.0).cos(),
+        0.5 + 0.5 * (hue + 2.0 * PI / 3.0).cos(),
+    )
+}
@@ -0,0 +1,130 @@
+use std::f64::consts::PI;
+
+use super::{distribution::Distribution2D, hdr, material::Color, sky::Sky, vec3::Vec3};
+
+pub enum Environment {
+    Map(EnvironmentMap),
+    Sky(Sky),
+}
+
+impl Environment {
+    pub fn radiance(&self, direction: Vec3) -> Color {
+        match self {
+            Environment::Map(map) => map.radiance(direction),
+            Environment::Sky(sky) => sky.radiance(direction),
+        }
+    }
+
+    pub fn sample(&self, u0: f64, u1: f64) -> (Vec3, Color, f64) {
+        match self {
+            Environment::Map(map) => map.sample(u0, u1),
+            Environment::Sky(sky) => sky.sample(u0, u1),
+        }
+    }
+
+    pub fn pdf(&self, direction: Vec3) -> f64 {
+        match self {
+            Environment::Map(map) => map.pdf(direction),
+            Environment::Sky(sky) => sky.pdf(direction),
+        }
+    }
+}
+
+pub struct EnvironmentMap {
+    width: u32,
+    height: u32,
+    pixels: Vec<Color>,
+    rotation: f64,
+    intensity: f64,
+    distribution: Distribution2D,
+}
+
+impl EnvironmentMap {
+    pub fn new(
+        pixels: Vec<Color>,
+        width: u32,
+        height: u32,
+        rotation: f64,
+        intensity: f64,
+    ) -> EnvironmentMap {
+        let mut func = vec![0.0; (width * height) as usize];
+        for y in 0..height {
+            let sin_theta = (PI * (y as f64 + 0.5) / height as f64).sin();
+            for x in 0..width {
+                let i = (y * width + x) as usize;
+                func[i] = pixels[i].luminance() * sin_theta;
+            }
+        }
+
+        let distribution = Distribution2D::new(&func, width as usize, height as usize);
+
+        EnvironmentMap {
+            width,
+            height,
+            pixels,
+            rotation,
+            intensity,
+            distribution,
+        }
+    }
+
+    pub fn load(file_name: &str, rotation: f64, intensity: f64) -> std::io::Result<EnvironmentMap> {
+        let (pixels, width, height) = hdr::load_hdr(file_name)?;
+        Ok(EnvironmentMap::new(
+            pixels, width, height, rotation, intensity,
+        ))
+    }
+
+    fn direction_to_uv(&self, direction: Vec3) -> (f64, f64) {
+        let phi = direction.z.atan2(direction.x) - self.rotation;
+        let theta = direction.y.clamp(-1.0, 1.0).acos();
+
+        ((phi / (2.0 * PI)).rem_euclid(1.0), theta / PI)
+    }
+
+    fn uv_to_direction(&self, u: f64, v: f64) -> Vec3 {
+        let phi = u * 2.0 * PI + self.rotation;
+        let theta = v * PI;
+
+        Vec3::new(
+            theta.sin() * phi.cos(),
+            theta.cos(),
+            theta.sin() * phi.sin(),
+        )
+    }
+
+    fn lookup(&self, u: f64, v: f64) -> Color {
+        let x = ((u * self.width as f64) as u32).min(self.width - 1);
+        let y = ((v * self.height as f64) as u32).min(self.height - 1);
+
+        self.pixels[(y * self.width + x) as usize] * self.intensity
+    }
+
+    pub fn radiance(&self, direction: Vec3) -> Color {
+        let (u, v) = self.direction_to_uv(direction);
+        self.lookup(u, v)
+    }
+
+    pub fn sample(&self, u0: f64, u1: f64) -> (Vec3, Color, f64) {
+        let ((u, v), map_pdf) = self.distribution.sample(u0, u1);
+
+        let sin_theta = (v * PI).sin();
+        if map_pdf == 0.0 || sin_theta == 0.0 {
+            return (Vec3::new(0.0, 1.0, 0.0), Color::new(0.0, 0.0, 0.0), 0.0);
+        }
+
+        let pdf = map_pdf / (2.0 * PI * PI * sin_theta);
+        (self.uv_to_direction(u, v), self.lookup(u, v), pdf)
+    }
+
+    pub fn pdf(&self, direction: Vec3) -> f64 {
+        let (u, v) = self.direction_to_uv(direction);
+
+        let sin_theta = (v * PI).sin();
+        if sin_theta == 0.0 {
+            return 0.0;
+        }
+
+        self.distribution.pdf(u, v) / (2.0 * PI * PI * sin_theta)
+    }
+}
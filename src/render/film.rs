@@ -0,0 +1,53 @@
+use std::sync::Mutex;
+
+use super::material::Color;
+
+/// Image being rendered: pixel estimates written row by row, plus contributions splatted
+/// anywhere on it by light tracing.
+pub struct Film {
+    width: u32,
+    height: u32,
+    pixels: Mutex<Vec<Color>>,
+    splats: Mutex<Vec<Color>>,
+}
+
+impl Film {
+    pub fn new(width: u32, height: u32) -> Film {
+        let size = (width * height) as usize;
+
+        Film {
+            width,
+            height,
+            pixels: Mutex::new(vec![Color::new(0.0, 0.0, 0.0); size]),
+            splats: Mutex::new(vec![Color::new(0.0, 0.0, 0.0); size]),
+        }
+    }
+
+    pub fn set_row(&self, y: u32, row: &[Color]) {
+        let start = (y * self.width) as usize;
+        self.pixels.lock().unwrap()[start..start + row.len()].copy_from_slice(row);
+    }
+
+    /// Adds `color` to the pixel containing the raster position `(x, y)`.
+    pub fn splat(&self, x: f64, y: f64, color: Color) {
+        if x < 0.0 || y < 0.0 || x >= self.width as f64 || y >= self.height as f64 {
+            return;
+        }
+
+        let index = (y as u32 * self.width + x as u32) as usize;
+        let mut splats = self.splats.lock().unwrap();
+        splats[index] = splats[index] + color;
+    }
+
+    /// Final image, with splats weighted by `splat_scale`.
+    pub fn develop(&self, splat_scale: f64) -> Vec<Color> {
+        let pixels = self.pixels.lock().unwrap();
+        let splats = self.splats.lock().unwrap();
+
+        pixels
+            .iter()
+            .zip(splats.iter())
+            .map(|(pixel, splat)| *pixel + *splat * splat_scale)
+            .collect()
+    }
+}
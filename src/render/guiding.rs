@@ -0,0 +1,446 @@
+use std::{
+    f64::consts::PI,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use super::{
+    frame::Frame,
+    material::{Bsdf, BsdfSample, Color, ShadingContext},
+    random::Sampler,
+    spectrum::{self, Wavelengths},
+    vec3::Vec3,
+};
+
+/// Passes rendered to train the guide, each with twice the samples per pixel of the last.
+pub const TRAINING_PASSES: u32 = 5;
+/// Probability of sampling the BSDF rather than the learned distribution.
+const BSDF_SAMPLING_FRACTION: f64 = 0.5;
+/// Fraction of the energy of a directional tree above which a node is split.
+const SUBDIVISION_THRESHOLD: f64 = 0.01;
+const MAX_DIRECTIONAL_DEPTH: u32 = 20;
+/// Samples a spatial region may collect in the first pass before it is split, growing with
+/// the square root of the samples per pixel of later passes.
+const SPATIAL_THRESHOLD: f64 = 12000.0;
+
+struct AtomicF64(AtomicU64);
+
+impl AtomicF64 {
+    fn new(value: f64) -> AtomicF64 {
+        AtomicF64(AtomicU64::new(value.to_bits()))
+    }
+
+    fn load(&self) -> f64 {
+        f64::from_bits(self.0.load(Ordering::Relaxed))
+    }
+
+    fn add(&self, value: f64) {
+        let _ = self
+            .0
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |bits| {
+                Some((f64::from_bits(bits) + value).to_bits())
+            });
+    }
+}
+
+/// Quadtree node over the unit square the sphere of directions is mapped to, with the energy
+/// recorded in each quadrant and the node subdividing it, if any.
+struct DirectionalNode {
+    sums: [AtomicF64; 4],
+    children: [usize; 4],
+}
+
+impl DirectionalNode {
+    fn new() -> DirectionalNode {
+        DirectionalNode {
+            sums: std::array::from_fn(|_| AtomicF64::new(0.0)),
+            children: [0; 4],
+        }
+    }
+
+    fn sums(&self) -> [f64; 4] {
+        std::array::from_fn(|quadrant| self.sums[quadrant].load())
+    }
+}
+
+/// Distribution of incident radiance over directions, refined where most of it arrives
+/// from.
+struct DirectionalTree {
+    nodes: Vec<DirectionalNode>,
+}
+
+impl DirectionalTree {
+    fn new() -> DirectionalTree {
+        DirectionalTree {
+            nodes: vec![DirectionalNode::new()],
+        }
+    }
+
+    fn total(&self) -> f64 {
+        self.nodes[0].sums().iter().sum()
+    }
+
+    fn record(&self, direction: Vec3, value: f64) {
+        let (mut x, mut y) = to_square(direction);
+        let mut node = &self.nodes[0];
+
+        loop {
+            let quadrant = quadrant(&mut x, &mut y);
+            node.sums[quadrant].add(value);
+            match node.children[quadrant] {
+                0 => return,
+                child => node = &self.nodes[child],
+            }
+        }
+    }
+
+    /// Solid angle density of sampling `direction`.
+    fn pdf(&self, direction: Vec3) -> f64 {
+        let (mut x, mut y) = to_square(direction);
+        let mut node = &self.nodes[0];
+        let mut pdf = 1.0;
+
+        loop {
+            let sums = node.sums();
+            let total: f64 = sums.iter().sum();
+            if total <= 0.0 {
+                return 0.0;
+            }
+
+            let quadrant = quadrant(&mut x, &mut y);
+            pdf *= 4.0 * sums[quadrant] / total;
+            match node.children[quadrant] {
+                0 => return pdf / (4.0 * PI),
+                child => node = &self.nodes[child],
+            }
+        }
+    }
+
+    fn sample(&self, rnd: &mut dyn Sampler) -> Vec3 {
+        let mut node = &self.nodes[0];
+        let (mut x, mut y, mut size) = (0.0, 0.0, 1.0);
+
+        loop {
+            let sums = node.sums();
+            let mut u = rnd.next_f64() * sums.iter().sum::<f64>();
+            let mut quadrant = 0;
+            while quadrant < 3 && (u >= sums[quadrant] || sums[quadrant] <= 0.0) {
+                u -= sums[quadrant];
+                quadrant += 1;
+            }
+
+            size /= 2.0;
+            x += (quadrant % 2) as f64 * size;
+            y += (quadrant / 2) as f64 * size;
+            match node.children[quadrant] {
+                0 => break,
+                child => node = &self.nodes[child],
+            }
+        }
+
+        from_square(x + rnd.next_f64() * size, y + rnd.next_f64() * size)
+    }
+
+    /// Empty tree subdivided where this one recorded more than a small fraction of its
+    /// energy, and coarsened where it recorded less.
+    fn refined(&self) -> DirectionalTree {
+        let mut tree = DirectionalTree::new();
+        let total = self.total();
+        if total > 0.0 {
+            self.refine_node(&mut tree, 0, Some(0), total, total, 1);
+        }
+        tree
+    }
+
+    fn refine_node(
+        &self,
+        tree: &mut DirectionalTree,
+        index: usize,
+        old: Option<usize>,
+        energy: f64,
+        total: f64,
+        depth: u32,
+    ) {
+        for quadrant in 0..4 {
+            // Energy below a leaf of the old tree is taken to be spread evenly.
+            let (energy, old_child) = match old {
+                Some(old) => {
+                    let node = &self.nodes[old];
+                    let child = node.children[quadrant];
+                    (node.sums[quadrant].load(), (child != 0).then_some(child))
+                }
+                None => (energy / 4.0, None),
+            };
+
+            if depth < MAX_DIRECTIONAL_DEPTH && energy > total * SUBDIVISION_THRESHOLD {
+                let child = tree.nodes.len();
+                tree.nodes.push(DirectionalNode::new());
+                tree.nodes[index].children[quadrant] = child;
+                self.refine_node(tree, child, old_child, energy, total, depth + 1);
+            }
+        }
+    }
+}
+
+impl Clone for DirectionalTree {
+    fn clone(&self) -> DirectionalTree {
+        DirectionalTree {
+            nodes: self
+                .nodes
+                .iter()
+                .map(|node| DirectionalNode {
+                    sums: std::array::from_fn(|quadrant| {
+                        AtomicF64::new(node.sums[quadrant].load())
+                    }),
+                    children: node.children,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Cylindrical mapping of a direction to the unit square, which preserves areas.
+fn to_square(direction: Vec3) -> (f64, f64) {
+    let phi = direction.y.atan2(direction.x).rem_euclid(2.0 * PI);
+    (
+        ((direction.z + 1.0) / 2.0).clamp(0.0, 1.0),
+        (phi / (2.0 * PI)).clamp(0.0, 1.0),
+    )
+}
+
+fn from_square(x: f64, y: f64) -> Vec3 {
+    let cos_theta = 2.0 * x - 1.0;
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi = 2.0 * PI * y;
+    Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta)
+}
+
+/// Quadrant of the square containing `(x, y)`, which are rescaled to the quadrant.
+fn quadrant(x: &mut f64, y: &mut f64) -> usize {
+    let right = *x >= 0.5;
+    let top = *y >= 0.5;
+    *x = (*x * 2.0 - right as u32 as f64).min(1.0);
+    *y = (*y * 2.0 - top as u32 as f64).min(1.0);
+    right as usize + 2 * top as usize
+}
+
+/// Part of the scene sharing one learned distribution, sampled from while the next one is
+/// recorded.
+pub struct Region {
+    sampling: DirectionalTree,
+    building: DirectionalTree,
+    samples: AtomicU64,
+    /// Axis the region is halved along when it collects too many samples.
+    axis: usize,
+}
+
+impl Region {
+    pub fn is_trained(&self) -> bool {
+        self.sampling.total() > 0.0
+    }
+
+    /// Density of sampling `direction` from the mixture of the learned distribution and a
+    /// BSDF with `bsdf_pdf`.
+    pub fn pdf(&self, bsdf_pdf: f64, direction: Vec3) -> f64 {
+        BSDF_SAMPLING_FRACTION * bsdf_pdf
+            + (1.0 - BSDF_SAMPLING_FRACTION) * self.sampling.pdf(direction)
+    }
+
+    /// Samples `bsdf` or the learned distribution, weighting by the density of the mixture.
+    pub fn sample(
+        &self,
+        bsdf: &dyn Bsdf,
+        context: &ShadingContext,
+        frame: &Frame,
+        wo: Vec3,
+        rnd: &mut dyn Sampler,
+    ) -> Option<BsdfSample> {
+        let wi = if rnd.next_f64() < BSDF_SAMPLING_FRACTION {
+            let sample = bsdf.sample(context, wo, rnd)?;
+            // Specular lobes can only come from the BSDF.
+            if sample.pdf == 0.0 {
+                return Some(BsdfSample {
+                    weight: sample.weight / BSDF_SAMPLING_FRACTION,
+                    ..sample
+                });
+            }
+            sample.direction
+        } else {
+            frame.to_local(self.sampling.sample(rnd))
+        };
+
+        let pdf = self.pdf(bsdf.pdf(context, wo, wi), frame.to_world(wi));
+        if pdf <= 0.0 {
+            return None;
+        }
+
+        Some(BsdfSample {
+            direction: wi,
+            weight: bsdf.eval(context, wo, wi) * (wi.z.abs() / pdf),
+            pdf,
+        })
+    }
+
+    /// Adds the incident radiance estimate `value` arriving along `direction`, sampled with
+    /// `pdf`.
+    pub fn record(&self, direction: Vec3, value: f64, pdf: f64) {
+        self.samples.fetch_add(1, Ordering::Relaxed);
+        if value > 0.0 && pdf > 0.0 {
+            self.building.record(direction, value / pdf);
+        }
+    }
+}
+
+/// Vertex of a path scattered in a region, with the radiance that later arrived along the
+/// sampled direction.
+struct GuidedVertex<'a> {
+    region: &'a Region,
+    direction: Vec3,
+    pdf: f64,
+    wavelengths: Option<Wavelengths>,
+    beta: Color,
+    radiance: Color,
+}
+
+/// Radiance gathered along an iteratively traced path, credited back to the vertices that
+/// sampled their direction in a region so it can be recorded there once the path ends.
+pub struct GuidedPath<'a> {
+    radiance: Color,
+    vertices: Vec<GuidedVertex<'a>>,
+}
+
+impl<'a> GuidedPath<'a> {
+    pub fn new() -> GuidedPath<'a> {
+        GuidedPath {
+            radiance: Color::new(0.0, 0.0, 0.0),
+            vertices: Vec::new(),
+        }
+    }
+
+    /// Adds `contribution`, already weighted by the throughput of the path so far.
+    pub fn add(&mut self, contribution: Color) {
+        self.radiance = self.radiance + contribution;
+
+        let divide = |a: f64, b: f64| if b > 0.0 { a / b } else { 0.0 };
+        for vertex in self.vertices.iter_mut() {
+            vertex.radiance = vertex.radiance
+                + Color::new(
+                    divide(contribution.x, vertex.beta.x),
+                    divide(contribution.y, vertex.beta.y),
+                    divide(contribution.z, vertex.beta.z),
+                );
+        }
+    }
+
+    /// Starts crediting radiance to a direction sampled with `pdf` in `region`, after which
+    /// the path carries `beta`.
+    pub fn push(
+        &mut self,
+        region: &'a Region,
+        direction: Vec3,
+        pdf: f64,
+        beta: Color,
+        wavelengths: Option<Wavelengths>,
+    ) {
+        self.vertices.push(GuidedVertex {
+            region,
+            direction,
+            pdf,
+            wavelengths,
+            beta,
+            radiance: Color::new(0.0, 0.0, 0.0),
+        });
+    }
+
+    /// Records the incident radiance at every vertex and returns the radiance of the path.
+    pub fn finish(self) -> Color {
+        for vertex in self.vertices {
+            let incident = spectrum::to_rgb(vertex.wavelengths, vertex.radiance).luminance();
+            vertex.region.record(vertex.direction, incident, vertex.pdf);
+        }
+        self.radiance
+    }
+}
+
+enum SpatialNode {
+    Split { axis: usize, children: [usize; 2] },
+    Leaf(Region),
+}
+
+/// Spatial binary tree over the scene bounds, each leaf learning the directions light arrives
+/// from in its region (Müller et al. 2017).
+pub struct GuidingTree {
+    min: Vec3,
+    size: f64,
+    nodes: Vec<SpatialNode>,
+}
+
+impl GuidingTree {
+    pub fn new(center: Vec3, radius: f64) -> GuidingTree {
+        GuidingTree {
+            min: center - Vec3::new(radius, radius, radius),
+            size: 2.0 * radius,
+            nodes: vec![SpatialNode::Leaf(Region {
+                sampling: DirectionalTree::new(),
+                building: DirectionalTree::new(),
+                samples: AtomicU64::new(0),
+                axis: 0,
+            })],
+        }
+    }
+
+    /// Region containing `position`, or the closest one outside the bounds.
+    pub fn region(&self, position: Vec3) -> &Region {
+        let offset = (position - self.min) / self.size;
+        let mut point = [offset.x, offset.y, offset.z].map(|x| x.clamp(0.0, 1.0));
+        let mut node = &self.nodes[0];
+
+        loop {
+            match node {
+                SpatialNode::Split { axis, children } => {
+                    let upper = point[*axis] >= 0.5;
+                    point[*axis] = point[*axis] * 2.0 - upper as u32 as f64;
+                    node = &self.nodes[children[upper as usize]];
+                }
+                SpatialNode::Leaf(region) => return region,
+            }
+        }
+    }
+
+    /// Ends training pass `pass`, splitting the regions that collected many samples and
+    /// sampling from what was recorded during it from now on.
+    pub fn refine(&mut self, pass: u32) {
+        let threshold = SPATIAL_THRESHOLD * 2.0f64.powf(pass as f64 / 2.0);
+
+        // Children are appended, so they are split further in the same sweep.
+        let mut index = 0;
+        while index < self.nodes.len() {
+            if let SpatialNode::Leaf(region) = &self.nodes[index] {
+                let samples = region.samples.load(Ordering::Relaxed);
+                if samples as f64 > threshold {
+                    let axis = region.axis;
+                    let halves = [(); 2].map(|_| {
+                        SpatialNode::Leaf(Region {
+                            sampling: region.sampling.clone(),
+                            building: region.building.clone(),
+                            samples: AtomicU64::new(samples / 2),
+                            axis: (axis + 1) % 3,
+                        })
+                    });
+
+                    let children = [self.nodes.len(), self.nodes.len() + 1];
+                    self.nodes.extend(halves);
+                    self.nodes[index] = SpatialNode::Split { axis, children };
+                }
+            }
+            index += 1;
+        }
+
+        for node in self.nodes.iter_mut() {
+            if let SpatialNode::Leaf(region) = node {
+                let building = region.building.refined();
+                region.sampling = std::mem::replace(&mut region.building, building);
+                region.samples = AtomicU64::new(0);
+            }
+        }
+    }
+}
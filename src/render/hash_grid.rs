@@ -0,0 +1,51 @@
+use std::collections::HashMap;
+
+use super::vec3::Vec3;
+
+/// Uniform grid storing only its occupied cells, each listing the spheres that overlap it.
+pub struct HashGrid {
+    cell_size: f64,
+    cells: HashMap<(i64, i64, i64), Vec<u32>>,
+}
+
+impl HashGrid {
+    pub fn new(cell_size: f64) -> HashGrid {
+        HashGrid {
+            cell_size,
+            cells: HashMap::new(),
+        }
+    }
+
+    pub fn insert(&mut self, index: u32, center: Vec3, radius: f64) {
+        let extent = Vec3::new(radius, radius, radius);
+        let min = self.cell(center - extent);
+        let max = self.cell(center + extent);
+
+        for z in min.2..=max.2 {
+            for y in min.1..=max.1 {
+                for x in min.0..=max.0 {
+                    self.cells.entry((x, y, z)).or_default().push(index);
+                }
+            }
+        }
+    }
+
+    /// Spheres that may contain `position`.
+    pub fn candidates(&self, position: Vec3) -> &[u32] {
+        if self.cells.is_empty() {
+            return &[];
+        }
+
+        self.cells
+            .get(&self.cell(position))
+            .map_or(&[], |cell| cell.as_slice())
+    }
+
+    fn cell(&self, position: Vec3) -> (i64, i64, i64) {
+        (
+            (position.x / self.cell_size).floor() as i64,
+            (position.y / self.cell_size).floor() as i64,
+            (position.z / self.cell_size).floor() as i64,
+        )
+    }
+}
@@ -0,0 +1,275 @@
+use super::material::Color;
+
+use std::io::{BufRead, BufReader, Error, ErrorKind, Read};
+
+fn invalid_data(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message.to_string())
+}
+
+fn rgbe_to_color(rgbe: [u8; 4]) -> Color {
+    if rgbe[3] == 0 {
+        return Color::new(0.0, 0.0, 0.0);
+    }
+
+    let f = 2.0f64.powi(rgbe[3] as i32 - (128 + 8));
+    Color::new(
+        (rgbe[0] as f64 + 0.5) * f,
+        (rgbe[1] as f64 + 0.5) * f,
+        (rgbe[2] as f64 + 0.5) * f,
+    )
+}
+
+fn read_scanline(reader: &mut impl Read, width: usize) -> std::io::Result<Vec<[u8; 4]>> {
+    let mut scanline = vec![[0u8; 4]; width];
+
+    let mut first = [0u8; 4];
+    reader.read_exact(&mut first)?;
+
+    let is_rle =
+        (8..0x8000).contains(&width) && first[0] == 2 && first[1] == 2 && first[2] & 0x80 == 0;
+
+    if !is_rle {
+        scanline[0] = first;
+        for pixel in scanline.iter_mut().skip(1) {
+            reader.read_exact(pixel)?;
+        }
+        return Ok(scanline);
+    }
+
+    if ((first[2] as usize) << 8 | first[3] as usize) != width {
+        return Err(invalid_data("HDR scanline width mismatch"));
+    }
+
+    for channel in 0..4 {
+        let mut x = 0;
+        while x < width {
+            let mut count = [0u8; 1];
+            reader.read_exact(&mut count)?;
+
+            if count[0] > 128 {
+                let run = (count[0] - 128) as usize;
+                if x + run > width {
+                    return Err(invalid_data("HDR run length overflows scanline"));
+                }
+
+                let mut value = [0u8; 1];
+                reader.read_exact(&mut value)?;
+                for pixel in scanline[x..x + run].iter_mut() {
+                    pixel[channel] = value[0];
+                }
+                x += run;
+            } else {
+                let run = count[0] as usize;
+                if run == 0 || x + run > width {
+                    return Err(invalid_data("HDR literal run overflows scanline"));
+                }
+
+                for pixel in scanline[x..x + run].iter_mut() {
+                    let mut value = [0u8; 1];
+                    reader.read_exact(&mut value)?;
+                    pixel[channel] = value[0];
+                }
+                x += run;
+            }
+        }
+    }
+
+    Ok(scanline)
+}
+
+pub fn load_hdr(file_name: &str) -> std::io::Result<(Vec<Color>, u32, u32)> {
+    let file = std::fs::File::open(file_name)?;
+    decode_hdr(&mut BufReader::new(file))
+}
+
+fn decode_hdr(reader: &mut impl BufRead) -> std::io::Result<(Vec<Color>, u32, u32)> {
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    if !line.starts_with("#?") {
+        return Err(invalid_data("Missing Radiance HDR signature"));
+    }
+
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(invalid_data("Unexpected end of HDR header"));
+        }
+
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            break;
+        }
+        if let Some(format) = trimmed.strip_prefix("FORMAT=") {
+            if format != "32-bit_rle_rgbe" {
+                return Err(invalid_data("Unsupported HDR pixel format"));
+            }
+        }
+    }
+
+    line.clear();
+    reader.read_line(&mut line)?;
+    let resolution: Vec<&str> = line.split_whitespace().collect();
+    let (height, width) = match resolution.as_slice() {
+        ["-Y", height, "+X", width] => (
+            height
+                .parse::<u32>()
+                .map_err(|_| invalid_data("Invalid HDR height"))?,
+            width
+                .parse::<u32>()
+                .map_err(|_| invalid_data("Invalid HDR width"))?,
+        ),
+        _ => return Err(invalid_data("Unsupported HDR orientation")),
+    };
+    if width == 0 || height == 0 {
+        return Err(invalid_data("Empty HDR image"));
+    }
+
+    let size = (width as usize)
+        .checked_mul(height as usize)
+        .ok_or_else(|| invalid_data("HDR image too large"))?;
+    let mut image = Vec::with_capacity(size);
+    for _ in 0..height {
+        let scanline = read_scanline(reader, width as usize)?;
+        image.extend(scanline.into_iter().map(rgbe_to_color));
+    }
+
+    Ok((image, width, height))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn color_to_rgbe(color: Color) -> [u8; 4] {
+        let v = color.max();
+        if v < 1e-32 {
+            return [0, 0, 0, 0];
+        }
+
+        // `v = m 2^e` with the mantissa `m` in `[0.5, 1)`.
+        let e = v.log2().floor() as i32 + 1;
+        let scale = 256.0 / 2.0f64.powi(e);
+        [
+            (color.x * scale) as u8,
+            (color.y * scale) as u8,
+            (color.z * scale) as u8,
+            (e + 128) as u8,
+        ]
+    }
+
+    /// Run-length encodes each channel of a scanline separately, as the new RLE scheme does.
+    fn encode_scanline(scanline: &[[u8; 4]], bytes: &mut Vec<u8>) {
+        let width = scanline.len();
+        bytes.extend([2, 2, (width >> 8) as u8, width as u8]);
+
+        for channel in 0..4 {
+            let values: Vec<u8> = scanline.iter().map(|pixel| pixel[channel]).collect();
+            let run_at = |x: usize| {
+                values[x..]
+                    .iter()
+                    .take(127)
+                    .take_while(|&&value| value == values[x])
+                    .count()
+            };
+
+            let mut x = 0;
+            while x < width {
+                let run = run_at(x);
+                if run >= 3 {
+                    bytes.extend([128 + run as u8, values[x]]);
+                    x += run;
+                    continue;
+                }
+
+                let start = x;
+                while x < width && x - start < 128 && run_at(x) < 3 {
+                    x += 1;
+                }
+                bytes.push((x - start) as u8);
+                bytes.extend(&values[start..x]);
+            }
+        }
+    }
+
+    fn encode_hdr(image: &[Color], width: u32, height: u32) -> Vec<u8> {
+        let mut bytes = format!(
+            "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n",
+            height, width
+        )
+        .into_bytes();
+
+        for row in image.chunks_exact(width as usize) {
+            let scanline: Vec<[u8; 4]> = row.iter().map(|&color| color_to_rgbe(color)).collect();
+            if (8..0x8000).contains(&width) {
+                encode_scanline(&scanline, &mut bytes);
+            } else {
+                bytes.extend(scanline.iter().flatten());
+            }
+        }
+        bytes
+    }
+
+    fn test_image(width: u32, height: u32) -> Vec<Color> {
+        (0..width * height)
+            .map(|i| {
+                let (x, y) = ((i % width) as f64, (i / width) as f64);
+                // Constant stretches give the encoder runs, the rest literals.
+                if x < width as f64 / 2.0 {
+                    Color::new(0.25, 1.0, 4.0)
+                } else {
+                    Color::new(x * 0.37, y * 11.0 + 1e-3, (x + y).sin().abs() * 100.0)
+                }
+            })
+            .collect()
+    }
+
+    fn assert_round_trip(width: u32, height: u32) {
+        let image = test_image(width, height);
+        let bytes = encode_hdr(&image, width, height);
+        let (decoded, decoded_width, decoded_height) =
+            decode_hdr(&mut bytes.as_slice()).expect("Failed to decode HDR");
+
+        assert_eq!((decoded_width, decoded_height), (width, height));
+        for (&expected, &actual) in image.iter().zip(&decoded) {
+            // RGBE keeps eight bits of mantissa relative to the brightest channel.
+            let tolerance = expected.max() / 128.0;
+            for (e, a) in [
+                (expected.x, actual.x),
+                (expected.y, actual.y),
+                (expected.z, actual.z),
+            ] {
+                assert!(
+                    (e - a).abs() <= tolerance,
+                    "{expected:?} decoded as {actual:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn round_trips_flat_scanlines() {
+        assert_round_trip(5, 3);
+    }
+
+    #[test]
+    fn round_trips_run_length_scanlines() {
+        assert_round_trip(300, 4);
+    }
+
+    #[test]
+    fn rejects_empty_images() {
+        for resolution in ["-Y 0 +X 4", "-Y 4 +X 0"] {
+            let bytes = format!("#?RADIANCE\n\n{}\n", resolution);
+            let error = decode_hdr(&mut bytes.as_bytes()).unwrap_err();
+            assert_eq!(error.kind(), ErrorKind::InvalidData, "{resolution}");
+        }
+    }
+
+    #[test]
+    fn rejects_truncated_pixel_data() {
+        for width in [5, 300] {
+            let bytes = encode_hdr(&test_image(width, 4), width, 4);
+            assert!(decode_hdr(&mut &bytes[..bytes.len() - 1]).is_err());
+        }
+    }
+}
@@ -1,20 +1,47 @@
-use super::vec3::Vec3;
+use super::{frame::Frame, vec3::Vec3};
 
 #[derive(Debug, Clone, Copy)]
 pub struct HitPoint {
     pub distance: f64,
     pub normal: Vec3,
     pub position: Vec3,
+    pub uv: (f64, f64),
+    pub dpdu: Vec3,
+    pub dpdv: Vec3,
 }
 
 impl HitPoint {
-    pub fn new(distance: f64, normal: Vec3, position: Vec3) -> HitPoint {
+    pub fn new(
+        distance: f64,
+        normal: Vec3,
+        position: Vec3,
+        uv: (f64, f64),
+        dpdu: Vec3,
+        dpdv: Vec3,
+    ) -> HitPoint {
         HitPoint {
-            distance: distance,
-            normal: normal,
-            position: position,
+            distance,
+            normal,
+            position,
+            uv,
+            dpdu,
+            dpdv,
         }
     }
+
+    pub fn frame(&self) -> Frame {
+        Frame::from_normal_tangent(self.normal, self.dpdu)
+    }
+
+    /// Width of a ray cone footprint of `width` in texture space, widened at grazing angles.
+    pub fn uv_footprint(&self, width: f64, direction: Vec3) -> f64 {
+        let area = self.dpdu.length() * self.dpdv.length();
+        if area == 0.0 {
+            return 0.0;
+        }
+
+        width / direction.dot(self.normal).abs().max(1e-3) / area.sqrt()
+    }
 }
 
 pub struct Intersection {
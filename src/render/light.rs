@@ -0,0 +1,660 @@
+use std::f64::consts::PI;
+
+use super::{
+    frame::Frame,
+    material::{Color, ShadingContext},
+    ray::{Ray, RAY_EPSILON},
+    sampling::{concentric_disk, cosine_hemisphere, uniform_sphere},
+    scene::Scene,
+    spectrum::{self, Spectrum, Wavelengths},
+    vec3::Vec3,
+};
+
+#[derive(Debug, Clone)]
+pub struct IntensityProfile {
+    axis: Vec3,
+    values: Vec<f64>,
+}
+
+impl IntensityProfile {
+    /// `values` are relative intensities at evenly spaced angles from 0 to 180 degrees off `axis`.
+    pub fn new(axis: Vec3, values: Vec<f64>) -> IntensityProfile {
+        IntensityProfile {
+            axis: axis.normalize(),
+            values,
+        }
+    }
+
+    fn evaluate(&self, direction: Vec3) -> f64 {
+        if self.values.len() < 2 {
+            return self.values.first().copied().unwrap_or(1.0);
+        }
+
+        let angle = direction.dot(self.axis).clamp(-1.0, 1.0).acos();
+        let position = angle / PI * (self.values.len() - 1) as f64;
+        let index = (position as usize).min(self.values.len() - 2);
+        let t = position - index as f64;
+
+        self.values[index] * (1.0 - t) + self.values[index + 1] * t
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Light {
+    Point {
+        position: Vec3,
+        intensity: Spectrum,
+        profile: Option<IntensityProfile>,
+    },
+    Spot {
+        position: Vec3,
+        direction: Vec3,
+        intensity: Spectrum,
+        cone_angle: f64,
+        falloff_start: f64,
+        profile: Option<IntensityProfile>,
+    },
+    Directional {
+        direction: Vec3,
+        irradiance: Spectrum,
+    },
+    /// Rectangle spanned by two perpendicular edges, emitting towards `edge_u × edge_v`.
+    Quad {
+        corner: Vec3,
+        edge_u: Vec3,
+        edge_v: Vec3,
+        emission: Spectrum,
+    },
+    Disk {
+        center: Vec3,
+        normal: Vec3,
+        radius: f64,
+        emission: Spectrum,
+    },
+    Sphere {
+        object_id: u32,
+    },
+}
+
+/// Ray leaving a light, starting a light subpath.
+pub struct EmissionSample {
+    pub ray: Ray,
+    /// Normal of the emitting surface, or the ray direction for directional lights.
+    pub normal: Option<Vec3>,
+    pub radiance: Color,
+    pub pdf_position: f64,
+    pub pdf_direction: f64,
+}
+
+pub struct LightSample {
+    pub direction: Vec3,
+    pub distance: f64,
+    pub radiance: Color,
+    pub pdf: f64,
+    pub is_delta: bool,
+}
+
+fn smoothstep(edge0: f64, edge1: f64, x: f64) -> f64 {
+    if edge0 == edge1 {
+        return if x < edge0 { 0.0 } else { 1.0 };
+    }
+
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+/// Ray leaving an area light at `point` in a cosine-distributed direction.
+fn area_emission(
+    point: Vec3,
+    normal: Vec3,
+    pdf_position: f64,
+    radiance: Color,
+    u: [f64; 4],
+) -> EmissionSample {
+    let local = cosine_hemisphere(u[2], u[3]);
+    let direction = Frame::from_normal(normal).to_world(local);
+
+    EmissionSample {
+        ray: Ray::new(point + normal * RAY_EPSILON, direction),
+        normal: Some(normal),
+        radiance,
+        pdf_position,
+        pdf_direction: local.z / PI,
+    }
+}
+
+/// Origin of a ray entering the scene along `direction` from infinitely far away: a point on
+/// the disk facing it outside the scene's bounding sphere, with its area pdf.
+pub fn sample_infinite_origin(scene: &Scene, direction: Vec3, u0: f64, u1: f64) -> (Vec3, f64) {
+    let (center, radius) = scene.bounding_sphere();
+    let (x, y) = concentric_disk(u0, u1);
+    let frame = Frame::from_normal(direction);
+    let origin = center + (frame.tangent * x + frame.bitangent * y - direction) * radius;
+
+    (origin, 1.0 / (PI * radius * radius))
+}
+
+fn area_sample(
+    origin: Vec3,
+    point: Vec3,
+    normal: Vec3,
+    area: f64,
+    emission: Color,
+) -> Option<LightSample> {
+    let to_light = point - origin;
+    let distance = to_light.length();
+    let direction = to_light / distance;
+    let cos_light = -direction.dot(normal);
+
+    if cos_light <= 0.0 {
+        return None;
+    }
+
+    Some(LightSample {
+        direction,
+        distance,
+        radiance: emission,
+        pdf: distance * distance / (cos_light * area),
+        is_delta: false,
+    })
+}
+
+impl Light {
+    fn quad_normal(edge_u: Vec3, edge_v: Vec3) -> Vec3 {
+        edge_u.cross(edge_v).normalize()
+    }
+
+    pub fn power(&self, scene: &Scene) -> f64 {
+        match self {
+            Light::Point { intensity, .. } => 4.0 * PI * intensity.to_rgb().luminance(),
+            Light::Spot {
+                intensity,
+                cone_angle,
+                falloff_start,
+                ..
+            } => {
+                2.0 * PI
+                    * intensity.to_rgb().luminance()
+                    * (1.0 - 0.5 * (cone_angle.cos() + falloff_start.cos()))
+            }
+            Light::Directional { irradiance, .. } => {
+                let (_, radius) = scene.bounding_sphere();
+                PI * radius * radius * irradiance.to_rgb().luminance()
+            }
+            Light::Quad {
+                edge_u,
+                edge_v,
+                emission,
+                ..
+            } => PI * edge_u.cross(*edge_v).length() * emission.to_rgb().luminance(),
+            Light::Disk {
+                radius, emission, ..
+            } => PI * PI * radius * radius * emission.to_rgb().luminance(),
+            Light::Sphere { object_id } => {
+                let sphere = &scene.spheres()[*object_id as usize];
+                let emission = scene.material(sphere.material_id).emission.average(sphere);
+                4.0 * PI * PI * sphere.radius * sphere.radius * emission.luminance()
+            }
+        }
+    }
+
+    /// Lights at a single point or arriving from a single direction, which rays cannot hit.
+    pub fn is_delta(&self) -> bool {
+        matches!(
+            self,
+            Light::Point { .. } | Light::Spot { .. } | Light::Directional { .. }
+        )
+    }
+
+    pub fn is_infinite(&self) -> bool {
+        matches!(self, Light::Directional { .. })
+    }
+
+    /// Lights with a surface of their own, rather than one of the scene's spheres, that
+    /// `intersect` tests rays against.
+    pub fn has_surface(&self) -> bool {
+        matches!(self, Light::Quad { .. } | Light::Disk { .. })
+    }
+
+    /// Normal of the emitting surface at `position`, for area lights.
+    pub fn normal_at(&self, scene: &Scene, position: Vec3) -> Option<Vec3> {
+        match self {
+            Light::Quad { edge_u, edge_v, .. } => Some(Light::quad_normal(*edge_u, *edge_v)),
+            Light::Disk { normal, .. } => Some(*normal),
+            Light::Sphere { object_id } => {
+                Some((position - scene.spheres()[*object_id as usize].position).normalize())
+            }
+            _ => None,
+        }
+    }
+
+    pub fn sample_emission(
+        &self,
+        scene: &Scene,
+        wavelengths: Option<Wavelengths>,
+        u: [f64; 4],
+    ) -> Option<EmissionSample> {
+        match self {
+            Light::Point {
+                position,
+                intensity,
+                profile,
+            } => {
+                let direction = uniform_sphere(u[0], u[1]);
+                let scale = profile.as_ref().map_or(1.0, |p| p.evaluate(direction));
+
+                Some(EmissionSample {
+                    ray: Ray::new(*position, direction),
+                    normal: None,
+                    radiance: intensity.evaluate(wavelengths) * scale,
+                    pdf_position: 1.0,
+                    pdf_direction: 1.0 / (4.0 * PI),
+                })
+            }
+            Light::Spot {
+                position,
+                direction: spot_direction,
+                intensity,
+                cone_angle,
+                falloff_start,
+                profile,
+            } => {
+                let cos_max = cone_angle.cos();
+                let cos_theta = 1.0 - u[0] * (1.0 - cos_max);
+                let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+                let phi = 2.0 * PI * u[1];
+                let direction = Frame::from_normal(*spot_direction).to_world(Vec3::new(
+                    sin_theta * phi.cos(),
+                    sin_theta * phi.sin(),
+                    cos_theta,
+                ));
+
+                let falloff = smoothstep(cos_max, falloff_start.cos(), cos_theta);
+                let scale = profile.as_ref().map_or(1.0, |p| p.evaluate(direction));
+
+                Some(EmissionSample {
+                    ray: Ray::new(*position, direction),
+                    normal: None,
+                    radiance: intensity.evaluate(wavelengths) * (falloff * scale),
+                    pdf_position: 1.0,
+                    pdf_direction: 1.0 / (2.0 * PI * (1.0 - cos_max)),
+                })
+            }
+            Light::Directional {
+                direction,
+                irradiance,
+            } => {
+                let (origin, pdf_position) = sample_infinite_origin(scene, *direction, u[0], u[1]);
+
+                Some(EmissionSample {
+                    ray: Ray::new(origin, *direction),
+                    normal: Some(*direction),
+                    radiance: irradiance.evaluate(wavelengths),
+                    pdf_position,
+                    pdf_direction: 1.0,
+                })
+            }
+            Light::Quad {
+                corner,
+                edge_u,
+                edge_v,
+                emission,
+            } => Some(area_emission(
+                *corner + *edge_u * u[0] + *edge_v * u[1],
+                Light::quad_normal(*edge_u, *edge_v),
+                1.0 / edge_u.cross(*edge_v).length(),
+                emission.evaluate(wavelengths),
+                u,
+            )),
+            Light::Disk {
+                center,
+                normal,
+                radius,
+                emission,
+            } => {
+                let (x, y) = concentric_disk(u[0], u[1]);
+                let point =
+                    *center + Frame::from_normal(*normal).to_world(Vec3::new(x, y, 0.0)) * *radius;
+                Some(area_emission(
+                    point,
+                    *normal,
+                    1.0 / (PI * radius * radius),
+                    emission.evaluate(wavelengths),
+                    u,
+                ))
+            }
+            Light::Sphere { object_id } => {
+                let sphere = &scene.spheres()[*object_id as usize];
+                let (reference, _) = scene.bounding_sphere();
+
+                // Emit from the cap facing the rest of the scene, which skips the parts of big
+                // lamp spheres hidden behind walls.
+                let (point, pdf_position) =
+                    if (reference - sphere.position).length() <= sphere.radius {
+                        let point = sphere.position + uniform_sphere(u[0], u[1]) * sphere.radius;
+                        (point, 1.0 / (4.0 * PI * sphere.radius * sphere.radius))
+                    } else {
+                        let sample = self.sample(scene, reference, None, u[0], u[1])?;
+                        let point = reference + sample.direction * sample.distance;
+                        let cos_light = sample
+                            .direction
+                            .dot((point - sphere.position).normalize())
+                            .abs();
+                        (
+                            point,
+                            sample.pdf * cos_light / (sample.distance * sample.distance),
+                        )
+                    };
+
+                let hit = sphere.hit_point(point, 0.0);
+                let radiance = spectrum::illuminant(
+                    wavelengths,
+                    scene
+                        .material(sphere.material_id)
+                        .emission
+                        .evaluate(&ShadingContext::new(hit.position, hit.uv, 0.0)),
+                );
+
+                Some(area_emission(
+                    hit.position,
+                    hit.normal,
+                    pdf_position,
+                    radiance,
+                    u,
+                ))
+            }
+        }
+    }
+
+    /// Position and direction pdfs of emitting from `position` along `direction`, matching
+    /// `sample_emission`.
+    pub fn pdf_emission(&self, scene: &Scene, position: Vec3, direction: Vec3) -> (f64, f64) {
+        match self {
+            Light::Point { .. } => (1.0, 1.0 / (4.0 * PI)),
+            Light::Spot {
+                direction: spot_direction,
+                cone_angle,
+                ..
+            } => {
+                let cos_max = cone_angle.cos();
+                if direction.dot(*spot_direction) < cos_max {
+                    (1.0, 0.0)
+                } else {
+                    (1.0, 1.0 / (2.0 * PI * (1.0 - cos_max)))
+                }
+            }
+            Light::Directional { .. } => {
+                let (_, radius) = scene.bounding_sphere();
+                (1.0 / (PI * radius * radius), 0.0)
+            }
+            Light::Quad { edge_u, edge_v, .. } => (
+                1.0 / edge_u.cross(*edge_v).length(),
+                direction.dot(Light::quad_normal(*edge_u, *edge_v)).max(0.0) / PI,
+            ),
+            Light::Disk { normal, radius, .. } => (
+                1.0 / (PI * radius * radius),
+                direction.dot(*normal).max(0.0) / PI,
+            ),
+            Light::Sphere { object_id } => {
+                let sphere = &scene.spheres()[*object_id as usize];
+                let normal = (position - sphere.position).normalize();
+                let (reference, _) = scene.bounding_sphere();
+
+                let pdf_position = if (reference - sphere.position).length() <= sphere.radius {
+                    1.0 / (4.0 * PI * sphere.radius * sphere.radius)
+                } else {
+                    let to_reference = reference - position;
+                    let distance_squared = to_reference.squared_length();
+                    let cos_light = normal.dot(to_reference) / distance_squared.sqrt();
+                    if cos_light <= 0.0 {
+                        0.0
+                    } else {
+                        self.pdf(scene, &Ray::new(reference, -to_reference)) * cos_light
+                            / distance_squared
+                    }
+                };
+
+                (pdf_position, direction.dot(normal).max(0.0) / PI)
+            }
+        }
+    }
+
+    pub fn sample(
+        &self,
+        scene: &Scene,
+        origin: Vec3,
+        wavelengths: Option<Wavelengths>,
+        u0: f64,
+        u1: f64,
+    ) -> Option<LightSample> {
+        match self {
+            Light::Point {
+                position,
+                intensity,
+                profile,
+            } => {
+                let to_light = *position - origin;
+                let distance = to_light.length();
+                let direction = to_light / distance;
+                let scale = profile.as_ref().map_or(1.0, |p| p.evaluate(-direction));
+
+                Some(LightSample {
+                    direction,
+                    distance,
+                    radiance: intensity.evaluate(wavelengths) * (scale / (distance * distance)),
+                    pdf: 1.0,
+                    is_delta: true,
+                })
+            }
+            Light::Spot {
+                position,
+                direction: spot_direction,
+                intensity,
+                cone_angle,
+                falloff_start,
+                profile,
+            } => {
+                let to_light = *position - origin;
+                let distance = to_light.length();
+                let direction = to_light / distance;
+
+                let cos_theta = -direction.dot(*spot_direction);
+                let falloff = smoothstep(cone_angle.cos(), falloff_start.cos(), cos_theta);
+                let scale = profile.as_ref().map_or(1.0, |p| p.evaluate(-direction));
+
+                if falloff * scale <= 0.0 {
+                    return None;
+                }
+
+                Some(LightSample {
+                    direction,
+                    distance,
+                    radiance: intensity.evaluate(wavelengths)
+                        * (falloff * scale / (distance * distance)),
+                    pdf: 1.0,
+                    is_delta: true,
+                })
+            }
+            Light::Directional {
+                direction,
+                irradiance,
+            } => Some(LightSample {
+                direction: -*direction,
+                distance: f64::INFINITY,
+                radiance: irradiance.evaluate(wavelengths),
+                pdf: 1.0,
+                is_delta: true,
+            }),
+            Light::Quad {
+                corner,
+                edge_u,
+                edge_v,
+                emission,
+            } => {
+                let point = *corner + *edge_u * u0 + *edge_v * u1;
+                let area = edge_u.cross(*edge_v).length();
+                area_sample(
+                    origin,
+                    point,
+                    Light::quad_normal(*edge_u, *edge_v),
+                    area,
+                    emission.evaluate(wavelengths),
+                )
+            }
+            Light::Disk {
+                center,
+                normal,
+                radius,
+                emission,
+            } => {
+                let (x, y) = concentric_disk(u0, u1);
+                let point =
+                    *center + Frame::from_normal(*normal).to_world(Vec3::new(x, y, 0.0)) * *radius;
+                area_sample(
+                    origin,
+                    point,
+                    *normal,
+                    PI * radius * radius,
+                    emission.evaluate(wavelengths),
+                )
+            }
+            Light::Sphere { object_id } => {
+                let sphere = &scene.spheres()[*object_id as usize];
+                let to_center = sphere.position - origin;
+                let distance_squared = to_center.squared_length();
+                let radius_squared = sphere.radius * sphere.radius;
+
+                if distance_squared <= radius_squared {
+                    return None;
+                }
+
+                let sin2_max = radius_squared / distance_squared;
+                let cos_max = (1.0 - sin2_max).max(0.0).sqrt();
+                let cos_theta = 1.0 - u0 * (1.0 - cos_max);
+                let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+                let phi = 2.0 * PI * u1;
+
+                let frame = Frame::from_normal(to_center.normalize());
+                let direction = frame.to_world(Vec3::new(
+                    phi.cos() * sin_theta,
+                    phi.sin() * sin_theta,
+                    cos_theta,
+                ));
+
+                let hit = sphere.intersect(&Ray::new(origin, direction))?;
+
+                Some(LightSample {
+                    direction,
+                    distance: hit.distance,
+                    radiance: spectrum::illuminant(
+                        wavelengths,
+                        scene
+                            .material(sphere.material_id)
+                            .emission
+                            .evaluate(&ShadingContext::new(hit.position, hit.uv, 0.0)),
+                    ),
+                    pdf: 1.0 / (2.0 * PI * (1.0 - cos_max)),
+                    is_delta: false,
+                })
+            }
+        }
+    }
+
+    pub fn pdf(&self, scene: &Scene, ray: &Ray) -> f64 {
+        match self {
+            Light::Point { .. } | Light::Spot { .. } | Light::Directional { .. } => 0.0,
+            Light::Quad { edge_u, edge_v, .. } => match self.intersect(ray) {
+                Some(distance) => {
+                    let cos_light = -ray.direction.dot(Light::quad_normal(*edge_u, *edge_v));
+                    let area = edge_u.cross(*edge_v).length();
+                    distance * distance / (cos_light * area)
+                }
+                None => 0.0,
+            },
+            Light::Disk { normal, radius, .. } => match self.intersect(ray) {
+                Some(distance) => {
+                    let cos_light = -ray.direction.dot(*normal);
+                    distance * distance / (cos_light * PI * radius * radius)
+                }
+                None => 0.0,
+            },
+            Light::Sphere { object_id } => {
+                let sphere = &scene.spheres()[*object_id as usize];
+                let distance_squared = (sphere.position - ray.origin).squared_length();
+                let radius_squared = sphere.radius * sphere.radius;
+
+                if distance_squared <= radius_squared {
+                    return 0.0;
+                }
+
+                let cos_max = (1.0 - radius_squared / distance_squared).max(0.0).sqrt();
+                1.0 / (2.0 * PI * (1.0 - cos_max))
+            }
+        }
+    }
+
+    pub fn intersect(&self, ray: &Ray) -> Option<f64> {
+        match self {
+            Light::Quad {
+                corner,
+                edge_u,
+                edge_v,
+                ..
+            } => {
+                let normal = edge_u.cross(*edge_v);
+                let denominator = normal.dot(ray.direction);
+                if denominator.abs() < f64::EPSILON {
+                    return None;
+                }
+
+                let distance = normal.dot(*corner - ray.origin) / denominator;
+                if distance <= f64::EPSILON {
+                    return None;
+                }
+
+                let offset = ray.origin + ray.direction * distance - *corner;
+                let u = offset.dot(*edge_u) / edge_u.squared_length();
+                let v = offset.dot(*edge_v) / edge_v.squared_length();
+
+                ((0.0..=1.0).contains(&u) && (0.0..=1.0).contains(&v)).then_some(distance)
+            }
+            Light::Disk {
+                center,
+                normal,
+                radius,
+                ..
+            } => {
+                let denominator = normal.dot(ray.direction);
+                if denominator.abs() < f64::EPSILON {
+                    return None;
+                }
+
+                let distance = normal.dot(*center - ray.origin) / denominator;
+                if distance <= f64::EPSILON {
+                    return None;
+                }
+
+                let offset = ray.origin + ray.direction * distance - *center;
+                (offset.squared_length() <= radius * radius).then_some(distance)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn emitted(&self, ray: &Ray) -> Color {
+        match self {
+            Light::Quad {
+                edge_u,
+                edge_v,
+                emission,
+                ..
+            } if ray.direction.dot(Light::quad_normal(*edge_u, *edge_v)) < 0.0 => {
+                emission.evaluate(ray.wavelengths)
+            }
+            Light::Disk {
+                normal, emission, ..
+            } if ray.direction.dot(*normal) < 0.0 => emission.evaluate(ray.wavelengths),
+            _ => Color::new(0.0, 0.0, 0.0),
+        }
+    }
+}
@@ -1,12 +1,171 @@
-use super::vec3::Vec3;
+use super::{frame::Frame, intersection::HitPoint, random::Sampler, texture::Texture, vec3::Vec3};
+
+mod bump;
+mod conductor;
+mod dielectric;
+mod interface;
+mod lambertian;
+mod mirror;
+mod principled;
+#[cfg(test)]
+mod tests;
+
+pub use bump::Bump;
+pub use conductor::{Conductor, Metal};
+pub use dielectric::{Ior, RoughDielectric};
+pub use interface::Interface;
+pub use lambertian::Lambertian;
+pub use mirror::Mirror;
+pub use principled::Principled;
 
 pub type Color = Vec3;
 
+pub const IOR: f64 = 1.5;
+
+/// Quantity carried along a path: radiance from the lights, or importance from the camera
+/// for light subpaths, which sees the adjoint BSDF.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransportMode {
+    Radiance,
+    Importance,
+}
+
+/// Ratio of the adjoint BSDF with shading normals to the one that keeps light subpaths
+/// reciprocal, from Veach's thesis.
+pub fn shading_correction(
+    mode: TransportMode,
+    wo: Vec3,
+    wi: Vec3,
+    shading: Vec3,
+    geometric: Vec3,
+) -> f64 {
+    if mode == TransportMode::Radiance {
+        return 1.0;
+    }
+
+    let denominator = wo.dot(geometric).abs() * wi.dot(shading).abs();
+    if denominator == 0.0 {
+        return 0.0;
+    }
+
+    wo.dot(shading).abs() * wi.dot(geometric).abs() / denominator
+}
+
+/// Surface parameters at a hit, with `footprint` the ray cone width in texture space and
+/// `wavelength` the hero wavelength in nanometers in spectral mode.
 #[derive(Debug, Clone, Copy)]
-pub enum RefrectionType {
-    Diffuse,
-    Specular,
-    Refraction,
+pub struct ShadingContext {
+    pub position: Vec3,
+    pub uv: (f64, f64),
+    pub footprint: f64,
+    pub wavelength: Option<f64>,
+    pub mode: TransportMode,
 }
 
-pub const IOR: f64 = 1.5;
+impl ShadingContext {
+    pub fn new(position: Vec3, uv: (f64, f64), footprint: f64) -> ShadingContext {
+        ShadingContext {
+            position,
+            uv,
+            footprint,
+            wavelength: None,
+            mode: TransportMode::Radiance,
+        }
+    }
+
+    pub fn with_wavelength(mut self, wavelength: Option<f64>) -> ShadingContext {
+        self.wavelength = wavelength;
+        self
+    }
+
+    pub fn with_mode(mut self, mode: TransportMode) -> ShadingContext {
+        self.mode = mode;
+        self
+    }
+}
+
+pub struct BsdfSample {
+    pub direction: Vec3,
+    pub weight: Color,
+    pub pdf: f64,
+}
+
+/// Scattering function in a local frame whose z axis is the outward surface normal.
+/// Sampled directions with a zero `pdf` are specular.
+pub trait Bsdf: Send + Sync {
+    fn eval(&self, context: &ShadingContext, wo: Vec3, wi: Vec3) -> Color;
+
+    fn pdf(&self, context: &ShadingContext, wo: Vec3, wi: Vec3) -> f64;
+
+    fn sample(
+        &self,
+        context: &ShadingContext,
+        wo: Vec3,
+        rnd: &mut dyn Sampler,
+    ) -> Option<BsdfSample>;
+
+    fn is_specular(&self) -> bool {
+        false
+    }
+
+    /// Dispersive BSDFs scatter each wavelength differently, so only the hero wavelength of a
+    /// spectral path can follow the sampled direction.
+    fn is_dispersive(&self) -> bool {
+        false
+    }
+
+    /// Interfaces pass light straight through and are skipped by the integrator.
+    fn is_interface(&self) -> bool {
+        false
+    }
+}
+
+pub struct Material {
+    pub bsdf: Box<dyn Bsdf>,
+    pub emission: Texture,
+    pub bump: Option<Bump>,
+    /// Medium filling the closed object this material is applied to.
+    pub interior: Option<u32>,
+}
+
+impl Material {
+    pub fn new(bsdf: impl Bsdf + 'static) -> Material {
+        Material::emissive(bsdf, Color::new(0.0, 0.0, 0.0))
+    }
+
+    pub fn emissive(bsdf: impl Bsdf + 'static, emission: impl Into<Texture>) -> Material {
+        Material {
+            bsdf: Box::new(bsdf),
+            emission: emission.into(),
+            bump: None,
+            interior: None,
+        }
+    }
+
+    pub fn with_bump(mut self, bump: Bump) -> Material {
+        self.bump = Some(bump);
+        self
+    }
+
+    pub fn with_interior(mut self, medium_id: u32) -> Material {
+        self.interior = Some(medium_id);
+        self
+    }
+
+    /// Frame to shade `hit` in, as seen from the outgoing direction `wo`. A perturbed normal
+    /// that hides the viewer would make the BSDF see `wo` on the wrong side, so the geometric
+    /// frame is used there instead of returning black.
+    pub fn shading_frame(&self, hit: &HitPoint, context: &ShadingContext, wo: Vec3) -> Frame {
+        match &self.bump {
+            Some(bump) => match bump.shading_frame(hit, context) {
+                frame if wo.dot(frame.normal) * wo.dot(hit.normal) > 0.0 => frame,
+                _ => hit.frame(),
+            },
+            None => hit.frame(),
+        }
+    }
+}
+
+fn flip_z(v: Vec3) -> Vec3 {
+    Vec3::new(v.x, v.y, -v.z)
+}
@@ -0,0 +1,58 @@
+use super::{
+    super::{frame::Frame, intersection::HitPoint, texture::Texture, vec3::Vec3},
+    ShadingContext,
+};
+
+const MIN_DIFFERENTIAL: f64 = 1e-4;
+
+/// Perturbs the shading normal, either from a tangent-space normal map with +z along the
+/// surface normal and +x along `dpdu`, or from a height field offset along the normal by
+/// `scale` world units per unit of texture value.
+#[derive(Debug, Clone)]
+pub enum Bump {
+    NormalMap(Texture),
+    Height { texture: Texture, scale: f64 },
+}
+
+impl Bump {
+    pub fn shading_frame(&self, hit: &HitPoint, context: &ShadingContext) -> Frame {
+        let normal = match self {
+            Bump::NormalMap(texture) => {
+                let value = texture.evaluate(context) * 2.0 - Vec3::new(1.0, 1.0, 1.0);
+                hit.frame().to_world(value)
+            }
+            Bump::Height { texture, scale } => {
+                let du = (0.5 * context.footprint).max(MIN_DIFFERENTIAL);
+                let dv = du;
+
+                let height = texture.evaluate_scalar(context);
+                let shifted = |dp: Vec3, uv: (f64, f64)| {
+                    texture.evaluate_scalar(&ShadingContext::new(
+                        context.position + dp,
+                        uv,
+                        context.footprint,
+                    ))
+                };
+                let height_u = shifted(hit.dpdu * du, (context.uv.0 + du, context.uv.1));
+                let height_v = shifted(hit.dpdv * dv, (context.uv.0, context.uv.1 + dv));
+
+                let dpdu = hit.dpdu + hit.normal * ((height_u - height) / du * scale);
+                let dpdv = hit.dpdv + hit.normal * ((height_v - height) / dv * scale);
+                dpdu.cross(dpdv)
+            }
+        };
+
+        if normal.squared_length() == 0.0 || normal.squared_length().is_nan() {
+            return hit.frame();
+        }
+
+        let normal = normal.normalize();
+        let normal = if normal.dot(hit.normal) < 0.0 {
+            -normal
+        } else {
+            normal
+        };
+
+        Frame::from_normal_tangent(normal, hit.dpdu)
+    }
+}
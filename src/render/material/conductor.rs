@@ -0,0 +1,170 @@
+use super::{
+    super::{
+        microfacet::{reflect, Ggx},
+        random::Sampler,
+        vec3::Vec3,
+    },
+    flip_z, Bsdf, BsdfSample, Color, ShadingContext,
+};
+
+#[derive(Debug, Clone, Copy)]
+pub enum Metal {
+    Gold,
+    Copper,
+    Aluminum,
+    Silver,
+    Chrome,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Conductor {
+    eta: Color,
+    k: Color,
+    distribution: Ggx,
+}
+
+fn fresnel_conductor_channel(cos_theta: f64, eta: f64, k: f64) -> f64 {
+    let cos2 = cos_theta * cos_theta;
+    let sin2 = 1.0 - cos2;
+    let eta2 = eta * eta;
+    let k2 = k * k;
+
+    let t0 = eta2 - k2 - sin2;
+    let a2_plus_b2 = (t0 * t0 + 4.0 * eta2 * k2).max(0.0).sqrt();
+    let t1 = a2_plus_b2 + cos2;
+    let a = (0.5 * (a2_plus_b2 + t0)).max(0.0).sqrt();
+    let t2 = 2.0 * cos_theta * a;
+    let rs = (t1 - t2) / (t1 + t2);
+
+    let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+    let t4 = t2 * sin2;
+    let rp = rs * (t3 - t4) / (t3 + t4);
+
+    0.5 * (rp + rs)
+}
+
+pub fn fresnel_conductor(cos_theta: f64, eta: Color, k: Color) -> Color {
+    let cos_theta = cos_theta.clamp(0.0, 1.0);
+    Color::new(
+        fresnel_conductor_channel(cos_theta, eta.x, k.x),
+        fresnel_conductor_channel(cos_theta, eta.y, k.y),
+        fresnel_conductor_channel(cos_theta, eta.z, k.z),
+    )
+}
+
+impl Conductor {
+    pub fn new(eta: Color, k: Color, roughness_u: f64, roughness_v: f64) -> Conductor {
+        Conductor {
+            eta,
+            k,
+            distribution: Ggx::new(roughness_u * roughness_u, roughness_v * roughness_v),
+        }
+    }
+
+    pub fn from_metal(metal: Metal, roughness_u: f64, roughness_v: f64) -> Conductor {
+        let (eta, k) = match metal {
+            Metal::Gold => (
+                Color::new(0.143, 0.374, 1.442),
+                Color::new(3.983, 2.385, 1.603),
+            ),
+            Metal::Copper => (
+                Color::new(0.200, 0.924, 1.102),
+                Color::new(3.912, 2.452, 2.142),
+            ),
+            Metal::Aluminum => (
+                Color::new(1.657, 0.880, 0.521),
+                Color::new(9.224, 6.270, 4.837),
+            ),
+            Metal::Silver => (
+                Color::new(0.155, 0.117, 0.138),
+                Color::new(4.828, 3.122, 2.147),
+            ),
+            Metal::Chrome => (
+                Color::new(3.107, 3.181, 2.323),
+                Color::new(3.331, 3.329, 3.135),
+            ),
+        };
+
+        Conductor::new(eta, k, roughness_u, roughness_v)
+    }
+}
+
+impl Bsdf for Conductor {
+    fn is_specular(&self) -> bool {
+        self.distribution.is_smooth()
+    }
+
+    fn eval(&self, _context: &ShadingContext, wo: Vec3, wi: Vec3) -> Color {
+        if self.is_specular() || wo.z * wi.z <= 0.0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+
+        let (wo, wi) = if wo.z < 0.0 {
+            (flip_z(wo), flip_z(wi))
+        } else {
+            (wo, wi)
+        };
+
+        let h = (wo + wi).normalize();
+        let fresnel = fresnel_conductor(wo.dot(h), self.eta, self.k);
+
+        fresnel * (self.distribution.d(h) * self.distribution.g(wo, wi) / (4.0 * wo.z * wi.z))
+    }
+
+    fn pdf(&self, _context: &ShadingContext, wo: Vec3, wi: Vec3) -> f64 {
+        if self.is_specular() || wo.z * wi.z <= 0.0 {
+            return 0.0;
+        }
+
+        let (wo, wi) = if wo.z < 0.0 {
+            (flip_z(wo), flip_z(wi))
+        } else {
+            (wo, wi)
+        };
+
+        let h = (wo + wi).normalize();
+        self.distribution.visible_pdf(wo, h) / (4.0 * wo.dot(h))
+    }
+
+    fn sample(
+        &self,
+        context: &ShadingContext,
+        wo: Vec3,
+        rnd: &mut dyn Sampler,
+    ) -> Option<BsdfSample> {
+        if wo.z == 0.0 {
+            return None;
+        }
+
+        let flipped = wo.z < 0.0;
+        let wo = if flipped { flip_z(wo) } else { wo };
+
+        let (wi, weight) = if self.is_specular() {
+            (
+                Vec3::new(-wo.x, -wo.y, wo.z),
+                fresnel_conductor(wo.z, self.eta, self.k),
+            )
+        } else {
+            let h = self
+                .distribution
+                .sample_visible_normal(wo, rnd.next_f64(), rnd.next_f64());
+            let wi = reflect(wo, h);
+            if wi.z <= 0.0 {
+                return None;
+            }
+
+            let fresnel = fresnel_conductor(wo.dot(h), self.eta, self.k);
+            (
+                wi,
+                fresnel * (self.distribution.g(wo, wi) / self.distribution.g1(wo)),
+            )
+        };
+
+        let pdf = self.pdf(context, wo, wi);
+        Some(BsdfSample {
+            direction: if flipped { flip_z(wi) } else { wi },
+            weight,
+            pdf,
+        })
+    }
+}
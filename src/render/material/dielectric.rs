@@ -0,0 +1,292 @@
+use super::{
+    super::{
+        microfacet::{reflect, Ggx},
+        random::Sampler,
+        vec3::Vec3,
+    },
+    Bsdf, BsdfSample, Color, ShadingContext, TransportMode,
+};
+
+pub fn fresnel_dielectric(cos_theta_i: f64, eta: f64) -> f64 {
+    let (cos_theta_i, eta) = if cos_theta_i < 0.0 {
+        (-cos_theta_i, 1.0 / eta)
+    } else {
+        (cos_theta_i, eta)
+    };
+
+    let sin2_theta_t = (1.0 - cos_theta_i * cos_theta_i).max(0.0) / (eta * eta);
+    if sin2_theta_t >= 1.0 {
+        return 1.0;
+    }
+
+    let cos_theta_t = (1.0 - sin2_theta_t).max(0.0).sqrt();
+    let r_parallel = (eta * cos_theta_i - cos_theta_t) / (eta * cos_theta_i + cos_theta_t);
+    let r_perpendicular = (cos_theta_i - eta * cos_theta_t) / (cos_theta_i + eta * cos_theta_t);
+
+    (r_parallel * r_parallel + r_perpendicular * r_perpendicular) / 2.0
+}
+
+/// Refracts `wi` through the interface with normal `n`, returning the direction and the
+/// relative IOR along the path, or `None` on total internal reflection.
+pub fn refract(wi: Vec3, n: Vec3, eta: f64) -> Option<(Vec3, f64)> {
+    let mut cos_theta_i = n.dot(wi);
+    let (n, eta) = if cos_theta_i < 0.0 {
+        cos_theta_i = -cos_theta_i;
+        (-n, 1.0 / eta)
+    } else {
+        (n, eta)
+    };
+
+    let sin2_theta_t = (1.0 - cos_theta_i * cos_theta_i).max(0.0) / (eta * eta);
+    if sin2_theta_t >= 1.0 {
+        return None;
+    }
+
+    let cos_theta_t = (1.0 - sin2_theta_t).sqrt();
+    Some((-wi / eta + n * (cos_theta_i / eta - cos_theta_t), eta))
+}
+
+/// Index of refraction, either constant or varying with the wavelength in micrometers.
+#[derive(Debug, Clone, Copy)]
+pub enum Ior {
+    Constant(f64),
+    /// `n = a + b / λ²`.
+    Cauchy {
+        a: f64,
+        b: f64,
+    },
+    /// `n² = 1 + Σ b λ² / (λ² - c)`.
+    Sellmeier {
+        b: [f64; 3],
+        c: [f64; 3],
+    },
+}
+
+impl Ior {
+    pub const BK7: Ior = Ior::Sellmeier {
+        b: [1.03961212, 0.231792344, 1.01046945],
+        c: [0.00600069867, 0.0200179144, 103.560653],
+    };
+    pub const FUSED_SILICA: Ior = Ior::Sellmeier {
+        b: [0.6961663, 0.4079426, 0.8974794],
+        c: [0.00467914826, 0.0135120631, 97.9340025],
+    };
+    pub const DIAMOND: Ior = Ior::Sellmeier {
+        b: [4.3356, 0.3306, 0.0],
+        c: [0.011236, 0.030625, 0.0],
+    };
+    /// Dense flint glass.
+    pub const FLINT: Ior = Ior::Cauchy {
+        a: 1.7280,
+        b: 0.01342,
+    };
+
+    /// Wavelength in nanometers used when rendering in RGB, the helium d line.
+    const D_LINE: f64 = 587.56;
+
+    /// IOR at `wavelength` in nanometers, or at the d line without one.
+    pub fn at(&self, wavelength: Option<f64>) -> f64 {
+        let lambda = wavelength.unwrap_or(Ior::D_LINE) * 1e-3;
+        let lambda2 = lambda * lambda;
+
+        match *self {
+            Ior::Constant(ior) => ior,
+            Ior::Cauchy { a, b } => a + b / lambda2,
+            Ior::Sellmeier { b, c } => (1.0
+                + (0..3)
+                    .map(|i| b[i] * lambda2 / (lambda2 - c[i]))
+                    .sum::<f64>())
+            .sqrt(),
+        }
+    }
+
+    pub fn is_dispersive(&self) -> bool {
+        !matches!(self, Ior::Constant(_))
+    }
+}
+
+impl From<f64> for Ior {
+    fn from(ior: f64) -> Ior {
+        Ior::Constant(ior)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RoughDielectric {
+    ior: Ior,
+    distribution: Ggx,
+    /// Scales both reflected and transmitted light.
+    tint: Color,
+}
+
+impl RoughDielectric {
+    pub fn new(ior: impl Into<Ior>, roughness: f64) -> RoughDielectric {
+        RoughDielectric {
+            ior: ior.into(),
+            distribution: Ggx::new(roughness * roughness, roughness * roughness),
+            tint: Color::new(1.0, 1.0, 1.0),
+        }
+    }
+
+    pub fn with_tint(mut self, tint: Color) -> RoughDielectric {
+        self.tint = tint;
+        self
+    }
+
+    fn half_vector(&self, ior: f64, wo: Vec3, wi: Vec3) -> Option<(Vec3, f64)> {
+        if wo.z == 0.0 || wi.z == 0.0 {
+            return None;
+        }
+
+        let reflect = wo.z * wi.z > 0.0;
+        let etap = if reflect {
+            1.0
+        } else if wo.z > 0.0 {
+            ior
+        } else {
+            1.0 / ior
+        };
+
+        let wm = wi * etap + wo;
+        if wm.squared_length() == 0.0 {
+            return None;
+        }
+
+        let wm = wm.normalize();
+        let wm = if wm.z < 0.0 { -wm } else { wm };
+
+        if wm.dot(wi) * wi.z < 0.0 || wm.dot(wo) * wo.z < 0.0 {
+            return None;
+        }
+
+        Some((wm, etap))
+    }
+}
+
+impl Bsdf for RoughDielectric {
+    fn is_specular(&self) -> bool {
+        self.distribution.is_smooth()
+    }
+
+    fn is_dispersive(&self) -> bool {
+        self.ior.is_dispersive()
+    }
+
+    fn eval(&self, context: &ShadingContext, wo: Vec3, wi: Vec3) -> Color {
+        if self.is_specular() {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+
+        let ior = self.ior.at(context.wavelength);
+        let Some((wm, etap)) = self.half_vector(ior, wo, wi) else {
+            return Color::new(0.0, 0.0, 0.0);
+        };
+
+        let fresnel = fresnel_dielectric(wo.dot(wm), ior);
+        let d = self.distribution.d(wm);
+        let g = self.distribution.g(wo, wi);
+
+        let f = if wo.z * wi.z > 0.0 {
+            d * g * fresnel / (4.0 * wi.z * wo.z).abs()
+        } else {
+            let denominator = (wi.dot(wm) + wo.dot(wm) / etap).powi(2) * wi.z * wo.z;
+            let f = d * (1.0 - fresnel) * g * (wi.dot(wm) * wo.dot(wm) / denominator).abs();
+            // Radiance is compressed into the smaller solid angle of the denser side; importance
+            // is not.
+            match context.mode {
+                TransportMode::Radiance => f / (etap * etap),
+                TransportMode::Importance => f,
+            }
+        };
+
+        self.tint * f
+    }
+
+    fn pdf(&self, context: &ShadingContext, wo: Vec3, wi: Vec3) -> f64 {
+        if self.is_specular() {
+            return 0.0;
+        }
+
+        let ior = self.ior.at(context.wavelength);
+        let Some((wm, etap)) = self.half_vector(ior, wo, wi) else {
+            return 0.0;
+        };
+
+        let reflectance = fresnel_dielectric(wo.dot(wm), ior);
+        let visible_pdf = self.distribution.visible_pdf(wo, wm);
+
+        if wo.z * wi.z > 0.0 {
+            visible_pdf / (4.0 * wo.dot(wm).abs()) * reflectance
+        } else {
+            let denominator = (wi.dot(wm) + wo.dot(wm) / etap).powi(2);
+            visible_pdf * wi.dot(wm).abs() / denominator * (1.0 - reflectance)
+        }
+    }
+
+    fn sample(
+        &self,
+        context: &ShadingContext,
+        wo: Vec3,
+        rnd: &mut dyn Sampler,
+    ) -> Option<BsdfSample> {
+        if wo.z == 0.0 {
+            return None;
+        }
+
+        let u = rnd.next_f64();
+        let ior = self.ior.at(context.wavelength);
+
+        if self.is_specular() {
+            let reflectance = fresnel_dielectric(wo.z, ior);
+
+            return if u < reflectance {
+                Some(BsdfSample {
+                    direction: Vec3::new(-wo.x, -wo.y, wo.z),
+                    weight: self.tint,
+                    pdf: 0.0,
+                })
+            } else {
+                let (wi, etap) = refract(wo, Vec3::new(0.0, 0.0, 1.0), ior)?;
+                let weight = match context.mode {
+                    TransportMode::Radiance => 1.0 / (etap * etap),
+                    TransportMode::Importance => 1.0,
+                };
+                Some(BsdfSample {
+                    direction: wi,
+                    weight: self.tint * weight,
+                    pdf: 0.0,
+                })
+            };
+        }
+
+        let wm = self
+            .distribution
+            .sample_visible_normal(wo, rnd.next_f64(), rnd.next_f64());
+        let reflectance = fresnel_dielectric(wo.dot(wm), ior);
+
+        let wi = if u < reflectance {
+            let wi = reflect(wo, wm);
+            if wi.z * wo.z <= 0.0 {
+                return None;
+            }
+            wi
+        } else {
+            let (wi, _) = refract(wo, wm, ior)?;
+            if wi.z * wo.z >= 0.0 {
+                return None;
+            }
+            wi
+        };
+
+        let pdf = self.pdf(context, wo, wi);
+        if pdf == 0.0 {
+            return None;
+        }
+
+        Some(BsdfSample {
+            direction: wi,
+            weight: self.eval(context, wo, wi) * (wi.z.abs() / pdf),
+            pdf,
+        })
+    }
+}
@@ -0,0 +1,39 @@
+use super::{
+    super::{random::Sampler, vec3::Vec3},
+    Bsdf, BsdfSample, Color, ShadingContext,
+};
+
+/// Invisible surface that only marks the boundary of a medium.
+#[derive(Debug, Clone, Copy)]
+pub struct Interface;
+
+impl Bsdf for Interface {
+    fn eval(&self, _context: &ShadingContext, _wo: Vec3, _wi: Vec3) -> Color {
+        Color::new(0.0, 0.0, 0.0)
+    }
+
+    fn pdf(&self, _context: &ShadingContext, _wo: Vec3, _wi: Vec3) -> f64 {
+        0.0
+    }
+
+    fn sample(
+        &self,
+        _context: &ShadingContext,
+        wo: Vec3,
+        _rnd: &mut dyn Sampler,
+    ) -> Option<BsdfSample> {
+        Some(BsdfSample {
+            direction: -wo,
+            weight: Color::new(1.0, 1.0, 1.0),
+            pdf: 0.0,
+        })
+    }
+
+    fn is_specular(&self) -> bool {
+        true
+    }
+
+    fn is_interface(&self) -> bool {
+        true
+    }
+}
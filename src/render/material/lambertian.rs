@@ -0,0 +1,57 @@
+use std::f64::consts::PI;
+
+use super::{
+    super::{random::Sampler, sampling::cosine_hemisphere, texture::Texture, vec3::Vec3},
+    Bsdf, BsdfSample, Color, ShadingContext,
+};
+
+#[derive(Debug, Clone)]
+pub struct Lambertian {
+    albedo: Texture,
+}
+
+impl Lambertian {
+    pub fn new(albedo: impl Into<Texture>) -> Lambertian {
+        Lambertian {
+            albedo: albedo.into(),
+        }
+    }
+}
+
+impl Bsdf for Lambertian {
+    fn eval(&self, context: &ShadingContext, wo: Vec3, wi: Vec3) -> Color {
+        if wo.z * wi.z <= 0.0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+
+        self.albedo.evaluate(context) / PI
+    }
+
+    fn pdf(&self, _context: &ShadingContext, wo: Vec3, wi: Vec3) -> f64 {
+        if wo.z * wi.z <= 0.0 {
+            return 0.0;
+        }
+
+        wi.z.abs() / PI
+    }
+
+    fn sample(
+        &self,
+        context: &ShadingContext,
+        wo: Vec3,
+        rnd: &mut dyn Sampler,
+    ) -> Option<BsdfSample> {
+        if wo.z == 0.0 {
+            return None;
+        }
+
+        let direction = cosine_hemisphere(rnd.next_f64(), rnd.next_f64());
+        let direction = Vec3::new(direction.x, direction.y, direction.z.copysign(wo.z));
+
+        Some(BsdfSample {
+            direction,
+            weight: self.albedo.evaluate(context),
+            pdf: direction.z.abs() / PI,
+        })
+    }
+}
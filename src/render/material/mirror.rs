@@ -0,0 +1,42 @@
+use super::{
+    super::{random::Sampler, vec3::Vec3},
+    Bsdf, BsdfSample, Color, ShadingContext,
+};
+
+#[derive(Debug, Clone, Copy)]
+pub struct Mirror {
+    color: Color,
+}
+
+impl Mirror {
+    pub fn new(color: Color) -> Mirror {
+        Mirror { color }
+    }
+}
+
+impl Bsdf for Mirror {
+    fn eval(&self, _context: &ShadingContext, _wo: Vec3, _wi: Vec3) -> Color {
+        Color::new(0.0, 0.0, 0.0)
+    }
+
+    fn pdf(&self, _context: &ShadingContext, _wo: Vec3, _wi: Vec3) -> f64 {
+        0.0
+    }
+
+    fn sample(
+        &self,
+        _context: &ShadingContext,
+        wo: Vec3,
+        _rnd: &mut dyn Sampler,
+    ) -> Option<BsdfSample> {
+        Some(BsdfSample {
+            direction: Vec3::new(-wo.x, -wo.y, wo.z),
+            weight: self.color,
+            pdf: 0.0,
+        })
+    }
+
+    fn is_specular(&self) -> bool {
+        true
+    }
+}
@@ -0,0 +1,325 @@
+use std::f64::consts::PI;
+
+use super::{
+    super::{
+        microfacet::{reflect, Ggx},
+        random::Sampler,
+        sampling::cosine_hemisphere,
+        texture::Texture,
+        vec3::Vec3,
+    },
+    Bsdf, BsdfSample, Color, RoughDielectric, ShadingContext,
+};
+
+#[derive(Debug, Clone)]
+pub struct Principled {
+    pub base_color: Texture,
+    pub metallic: Texture,
+    pub roughness: Texture,
+    pub specular: f64,
+    pub specular_tint: f64,
+    pub sheen: f64,
+    pub sheen_tint: f64,
+    pub clearcoat: f64,
+    pub clearcoat_gloss: f64,
+    pub transmission: f64,
+    pub subsurface: f64,
+    pub ior: f64,
+}
+
+impl Default for Principled {
+    fn default() -> Principled {
+        Principled {
+            base_color: Color::new(0.8, 0.8, 0.8).into(),
+            metallic: 0.0.into(),
+            roughness: 0.5.into(),
+            specular: 0.5,
+            specular_tint: 0.0,
+            sheen: 0.0,
+            sheen_tint: 0.5,
+            clearcoat: 0.0,
+            clearcoat_gloss: 1.0,
+            transmission: 0.0,
+            subsurface: 0.0,
+            ior: 1.5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Parameters {
+    base_color: Color,
+    metallic: f64,
+    roughness: f64,
+    specular: f64,
+    specular_tint: f64,
+    sheen: f64,
+    sheen_tint: f64,
+    clearcoat: f64,
+    clearcoat_gloss: f64,
+    transmission: f64,
+    subsurface: f64,
+    ior: f64,
+}
+
+struct Lobes {
+    diffuse: f64,
+    specular: f64,
+    clearcoat: f64,
+    transmission: f64,
+}
+
+fn schlick_weight(cos_theta: f64) -> f64 {
+    (1.0 - cos_theta).clamp(0.0, 1.0).powi(5)
+}
+
+fn lerp(a: f64, b: f64, t: f64) -> f64 {
+    a + (b - a) * t
+}
+
+fn lerp_color(a: Color, b: Color, t: f64) -> Color {
+    a + (b - a) * t
+}
+
+fn gtr1(cos_theta_h: f64, alpha: f64) -> f64 {
+    let a2 = alpha * alpha;
+    (a2 - 1.0) / (PI * a2.ln() * (1.0 + (a2 - 1.0) * cos_theta_h * cos_theta_h))
+}
+
+fn smith_g1(cos_theta: f64, alpha: f64) -> f64 {
+    let a2 = alpha * alpha;
+    let c2 = cos_theta * cos_theta;
+    2.0 * cos_theta / (cos_theta + (a2 + c2 - a2 * c2).sqrt())
+}
+
+impl Principled {
+    fn parameters(&self, context: &ShadingContext) -> Parameters {
+        Parameters {
+            base_color: self.base_color.evaluate(context),
+            metallic: self.metallic.evaluate_scalar(context).clamp(0.0, 1.0),
+            roughness: self.roughness.evaluate_scalar(context).clamp(0.0, 1.0),
+            specular: self.specular,
+            specular_tint: self.specular_tint,
+            sheen: self.sheen,
+            sheen_tint: self.sheen_tint,
+            clearcoat: self.clearcoat,
+            clearcoat_gloss: self.clearcoat_gloss,
+            transmission: self.transmission,
+            subsurface: self.subsurface,
+            ior: self.ior,
+        }
+    }
+}
+
+impl Parameters {
+    fn tint(&self) -> Color {
+        let luminance = self.base_color.luminance();
+        if luminance > 0.0 {
+            self.base_color / luminance
+        } else {
+            Color::new(1.0, 1.0, 1.0)
+        }
+    }
+
+    fn specular_distribution(&self) -> Ggx {
+        let alpha = (self.roughness * self.roughness).max(1e-3);
+        Ggx::new(alpha, alpha)
+    }
+
+    fn clearcoat_alpha(&self) -> f64 {
+        lerp(0.1, 0.001, self.clearcoat_gloss)
+    }
+
+    fn dielectric(&self) -> RoughDielectric {
+        RoughDielectric::new(self.ior, self.roughness.max(0.032))
+    }
+
+    fn lobes(&self, wo: Vec3) -> Lobes {
+        if wo.z < 0.0 && self.transmission > 0.0 {
+            return Lobes {
+                diffuse: 0.0,
+                specular: 0.0,
+                clearcoat: 0.0,
+                transmission: 1.0,
+            };
+        }
+
+        let dielectric = (1.0 - self.metallic) * self.transmission;
+        let diffuse = (1.0 - self.metallic) * (1.0 - self.transmission);
+        let specular = 1.0 - dielectric;
+        let clearcoat = 0.25 * self.clearcoat;
+
+        let total = diffuse + specular + clearcoat + dielectric;
+        Lobes {
+            diffuse: diffuse / total,
+            specular: specular / total,
+            clearcoat: clearcoat / total,
+            transmission: dielectric / total,
+        }
+    }
+
+    fn opaque_directions(&self, wo: Vec3, wi: Vec3) -> Option<(Vec3, Vec3)> {
+        if wo.z > 0.0 && wi.z > 0.0 {
+            Some((wo, wi))
+        } else if self.transmission == 0.0 && wo.z < 0.0 && wi.z < 0.0 {
+            Some((-wo, -wi))
+        } else {
+            None
+        }
+    }
+
+    fn eval_opaque(&self, wo: Vec3, wi: Vec3) -> Color {
+        let h = (wo + wi).normalize();
+        let cos_theta_d = wi.dot(h);
+
+        let fl = schlick_weight(wi.z);
+        let fv = schlick_weight(wo.z);
+        let fh = schlick_weight(cos_theta_d);
+
+        let fd90 = 0.5 + 2.0 * self.roughness * cos_theta_d * cos_theta_d;
+        let fd = (1.0 + (fd90 - 1.0) * fl) * (1.0 + (fd90 - 1.0) * fv);
+
+        let fss90 = self.roughness * cos_theta_d * cos_theta_d;
+        let fss = (1.0 + (fss90 - 1.0) * fl) * (1.0 + (fss90 - 1.0) * fv);
+        let ss = 1.25 * (fss * (1.0 / (wi.z + wo.z) - 0.5) + 0.5);
+
+        let sheen_color = lerp_color(Color::new(1.0, 1.0, 1.0), self.tint(), self.sheen_tint);
+        let sheen = sheen_color * (fh * self.sheen);
+
+        let diffuse_weight = (1.0 - self.metallic) * (1.0 - self.transmission);
+        let diffuse =
+            (self.base_color * (lerp(fd, ss, self.subsurface) / PI) + sheen) * diffuse_weight;
+
+        let specular_color = lerp_color(
+            lerp_color(Color::new(1.0, 1.0, 1.0), self.tint(), self.specular_tint)
+                * (self.specular * 0.08),
+            self.base_color,
+            self.metallic,
+        );
+        let fresnel = lerp_color(specular_color, Color::new(1.0, 1.0, 1.0), fh);
+        let distribution = self.specular_distribution();
+        let specular = fresnel
+            * (distribution.d(h) * distribution.g(wo, wi) / (4.0 * wo.z * wi.z))
+            * (1.0 - (1.0 - self.metallic) * self.transmission);
+
+        let clearcoat_alpha = self.clearcoat_alpha();
+        let clearcoat = 0.25
+            * self.clearcoat
+            * gtr1(h.z, clearcoat_alpha)
+            * lerp(0.04, 1.0, fh)
+            * smith_g1(wo.z, 0.25)
+            * smith_g1(wi.z, 0.25)
+            / (4.0 * wo.z * wi.z);
+
+        diffuse + specular + Color::new(clearcoat, clearcoat, clearcoat)
+    }
+
+    fn eval_transmission(&self, context: &ShadingContext, wo: Vec3, wi: Vec3) -> Color {
+        let f = self.dielectric().eval(context, wo, wi);
+        let weight = (1.0 - self.metallic) * self.transmission;
+
+        if wo.z * wi.z < 0.0 {
+            f * self.base_color * weight
+        } else {
+            f * weight
+        }
+    }
+
+    fn eval(&self, context: &ShadingContext, wo: Vec3, wi: Vec3) -> Color {
+        let mut f = self.eval_transmission(context, wo, wi);
+
+        if let Some((wo, wi)) = self.opaque_directions(wo, wi) {
+            f = f + self.eval_opaque(wo, wi);
+        }
+
+        f
+    }
+
+    fn pdf(&self, context: &ShadingContext, wo: Vec3, wi: Vec3) -> f64 {
+        let lobes = self.lobes(wo);
+        let mut pdf = lobes.transmission * self.dielectric().pdf(context, wo, wi);
+
+        if let Some((wo, wi)) = self.opaque_directions(wo, wi) {
+            let h = (wo + wi).normalize();
+
+            pdf += lobes.diffuse * wi.z / PI;
+            pdf += lobes.specular * self.specular_distribution().visible_pdf(wo, h)
+                / (4.0 * wo.dot(h));
+            pdf += lobes.clearcoat * gtr1(h.z, self.clearcoat_alpha()) * h.z / (4.0 * wo.dot(h));
+        }
+
+        pdf
+    }
+
+    fn sample(
+        &self,
+        context: &ShadingContext,
+        wo: Vec3,
+        rnd: &mut dyn Sampler,
+    ) -> Option<BsdfSample> {
+        if wo.z == 0.0 {
+            return None;
+        }
+
+        let lobes = self.lobes(wo);
+        let (u0, u1) = (rnd.next_f64(), rnd.next_f64());
+        let mut u = rnd.next_f64();
+
+        let wi = if u < lobes.transmission {
+            self.dielectric().sample(context, wo, rnd)?.direction
+        } else {
+            u -= lobes.transmission;
+
+            let flip = if wo.z < 0.0 { -1.0 } else { 1.0 };
+            let wo_up = wo * flip;
+
+            let wi = if u < lobes.diffuse {
+                cosine_hemisphere(u0, u1)
+            } else if u < lobes.diffuse + lobes.specular {
+                let h = self
+                    .specular_distribution()
+                    .sample_visible_normal(wo_up, u0, u1);
+                reflect(wo_up, h)
+            } else {
+                let a2 = self.clearcoat_alpha().powi(2);
+                let cos_theta = ((1.0 - a2.powf(1.0 - u0)) / (1.0 - a2)).max(0.0).sqrt();
+                let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+                let phi = 2.0 * PI * u1;
+                let h = Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta);
+                reflect(wo_up, h)
+            };
+
+            wi * flip
+        };
+
+        let pdf = self.pdf(context, wo, wi);
+        if pdf <= 0.0 {
+            return None;
+        }
+
+        Some(BsdfSample {
+            direction: wi,
+            weight: self.eval(context, wo, wi) * (wi.z.abs() / pdf),
+            pdf,
+        })
+    }
+}
+
+impl Bsdf for Principled {
+    fn eval(&self, context: &ShadingContext, wo: Vec3, wi: Vec3) -> Color {
+        self.parameters(context).eval(context, wo, wi)
+    }
+
+    fn pdf(&self, context: &ShadingContext, wo: Vec3, wi: Vec3) -> f64 {
+        self.parameters(context).pdf(context, wo, wi)
+    }
+
+    fn sample(
+        &self,
+        context: &ShadingContext,
+        wo: Vec3,
+        rnd: &mut dyn Sampler,
+    ) -> Option<BsdfSample> {
+        self.parameters(context).sample(context, wo, rnd)
+    }
+}
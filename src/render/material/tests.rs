@@ -0,0 +1,277 @@
+use std::f64::consts::PI;
+
+use super::{
+    super::{
+        random::{Sampler, XorShiftRandom},
+        vec3::Vec3,
+    },
+    Bsdf, Color, Conductor, Interface, Lambertian, Metal, Mirror, Principled, RoughDielectric,
+    ShadingContext, TransportMode, IOR,
+};
+
+/// Cells of the sphere of directions along `cos(theta)` and `phi`, all of equal solid angle.
+const Z_CELLS: usize = 16;
+const PHI_CELLS: usize = 32;
+/// Points per cell side the pdf is integrated with.
+const SUBDIVISIONS: usize = 16;
+const SAMPLES: u32 = 100_000;
+/// Cells expecting fewer samples than this are pooled, so the statistic stays chi-square
+/// distributed.
+const MIN_EXPECTED: f64 = 5.0;
+/// Standard score of the chi-square statistic above which the sampled directions are taken
+/// not to follow the pdf.
+const MAX_Z_SCORE: f64 = 4.0;
+
+struct Case {
+    name: &'static str,
+    bsdf: Box<dyn Bsdf>,
+    /// Scatters all the energy arriving from any direction.
+    lossless: bool,
+}
+
+fn cases() -> Vec<Case> {
+    let case = |name, bsdf: Box<dyn Bsdf>, lossless| Case {
+        name,
+        bsdf,
+        lossless,
+    };
+
+    vec![
+        case(
+            "white lambertian",
+            Box::new(Lambertian::new(Color::new(1.0, 1.0, 1.0))),
+            true,
+        ),
+        case(
+            "lambertian",
+            Box::new(Lambertian::new(Color::new(0.25, 0.75, 0.5))),
+            false,
+        ),
+        case(
+            "mirror",
+            Box::new(Mirror::new(Color::new(1.0, 1.0, 1.0))),
+            true,
+        ),
+        case("interface", Box::new(Interface), true),
+        case(
+            "smooth silver",
+            Box::new(Conductor::from_metal(Metal::Silver, 0.0, 0.0)),
+            false,
+        ),
+        case(
+            "rough gold",
+            Box::new(Conductor::from_metal(Metal::Gold, 0.4, 0.4)),
+            false,
+        ),
+        case(
+            "anisotropic aluminum",
+            Box::new(Conductor::from_metal(Metal::Aluminum, 0.3, 0.6)),
+            false,
+        ),
+        case("glass", Box::new(RoughDielectric::new(IOR, 0.0)), true),
+        case(
+            "tinted frosted glass",
+            Box::new(RoughDielectric::new(IOR, 0.4).with_tint(Color::new(0.99, 0.5, 0.25))),
+            false,
+        ),
+        case(
+            "frosted glass",
+            Box::new(RoughDielectric::new(IOR, 0.4)),
+            false,
+        ),
+        case("principled", Box::<Principled>::default(), false),
+        case(
+            "principled clearcoat metal",
+            Box::new(Principled {
+                metallic: 1.0.into(),
+                roughness: 0.4.into(),
+                clearcoat: 1.0,
+                clearcoat_gloss: 0.0,
+                ..Principled::default()
+            }),
+            false,
+        ),
+        case(
+            "principled glass",
+            Box::new(Principled {
+                roughness: 0.4.into(),
+                transmission: 1.0,
+                ..Principled::default()
+            }),
+            false,
+        ),
+    ]
+}
+
+fn context() -> ShadingContext {
+    ShadingContext::new(Vec3::new(0.0, 0.0, 0.0), (0.5, 0.5), 0.0)
+}
+
+/// Outgoing directions at normal, oblique and grazing angles on both sides of the surface.
+fn outgoing_directions() -> Vec<Vec3> {
+    [0.95, 0.6, 0.15, -0.5]
+        .into_iter()
+        .map(|z: f64| direction(z, 0.7))
+        .collect()
+}
+
+fn direction(z: f64, phi: f64) -> Vec3 {
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    Vec3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+fn cell(direction: Vec3) -> usize {
+    let z = ((direction.z + 1.0) / 2.0 * Z_CELLS as f64) as usize;
+    let phi = direction.y.atan2(direction.x).rem_euclid(2.0 * PI) / (2.0 * PI);
+    let phi = (phi * PHI_CELLS as f64) as usize;
+    z.min(Z_CELLS - 1) * PHI_CELLS + phi.min(PHI_CELLS - 1)
+}
+
+/// Integral of the pdf over each cell, by the midpoint rule.
+fn cell_probabilities(bsdf: &dyn Bsdf, context: &ShadingContext, wo: Vec3) -> Vec<f64> {
+    let dz = 2.0 / (Z_CELLS * SUBDIVISIONS) as f64;
+    let dphi = 2.0 * PI / (PHI_CELLS * SUBDIVISIONS) as f64;
+
+    let mut probabilities = vec![0.0; Z_CELLS * PHI_CELLS];
+    for i in 0..Z_CELLS * SUBDIVISIONS {
+        for j in 0..PHI_CELLS * SUBDIVISIONS {
+            let wi = direction(-1.0 + (i as f64 + 0.5) * dz, (j as f64 + 0.5) * dphi);
+            let cell = (i / SUBDIVISIONS) * PHI_CELLS + j / SUBDIVISIONS;
+            probabilities[cell] += bsdf.pdf(context, wo, wi) * dz * dphi;
+        }
+    }
+    probabilities
+}
+
+/// Wilson-Hilferty standard score of a chi-square statistic with `dof` degrees of freedom.
+fn z_score(statistic: f64, dof: usize) -> f64 {
+    let k = dof as f64;
+    let variance = 2.0 / (9.0 * k);
+    ((statistic / k).cbrt() - (1.0 - variance)) / variance.sqrt()
+}
+
+fn assert_close(a: f64, b: f64, message: &str) {
+    assert!(
+        (a - b).abs() <= 1e-6 * a.abs().max(b.abs()).max(1e-3),
+        "{message}: {a} != {b}"
+    );
+}
+
+#[test]
+fn sampled_directions_follow_pdf() {
+    let context = context();
+
+    for case in cases().iter().filter(|case| !case.bsdf.is_specular()) {
+        for (index, wo) in outgoing_directions().into_iter().enumerate() {
+            let message = format!("{} with wo.z = {}", case.name, wo.z);
+            let bsdf = case.bsdf.as_ref();
+            let mut rnd = XorShiftRandom::new(index as u32 + 1);
+
+            let mut observed = vec![0.0; Z_CELLS * PHI_CELLS];
+            for _ in 0..SAMPLES {
+                let Some(sample) = bsdf.sample(&context, wo, &mut rnd) else {
+                    continue;
+                };
+                let wi = sample.direction;
+
+                // What the integrator is handed must agree with evaluating the BSDF.
+                assert_close(sample.pdf, bsdf.pdf(&context, wo, wi), &message);
+                let weight = bsdf.eval(&context, wo, wi) * (wi.z.abs() / sample.pdf);
+                assert_close(sample.weight.x, weight.x, &message);
+                assert_close(sample.weight.y, weight.y, &message);
+                assert_close(sample.weight.z, weight.z, &message);
+
+                observed[cell(wi)] += 1.0;
+            }
+
+            let mut statistic = 0.0;
+            let mut dof = 0;
+            let (mut pooled_observed, mut pooled_expected) = (0.0, 0.0);
+            let probabilities = cell_probabilities(bsdf, &context, wo);
+            for (observed, probability) in observed.into_iter().zip(probabilities) {
+                let expected = probability * SAMPLES as f64;
+                if expected < MIN_EXPECTED {
+                    pooled_observed += observed;
+                    pooled_expected += expected;
+                } else {
+                    statistic += (observed - expected).powi(2) / expected;
+                    dof += 1;
+                }
+            }
+            if pooled_expected > 0.0 || pooled_observed > 0.0 {
+                statistic +=
+                    (pooled_observed - pooled_expected).powi(2) / pooled_expected.max(MIN_EXPECTED);
+                dof += 1;
+            }
+
+            let z = z_score(statistic, dof - 1);
+            assert!(
+                z < MAX_Z_SCORE,
+                "{message}: chi-square {statistic} with {} degrees of freedom",
+                dof - 1
+            );
+        }
+    }
+}
+
+#[test]
+fn white_furnace() {
+    let context = context().with_mode(TransportMode::Importance);
+
+    for case in cases() {
+        for (index, wo) in outgoing_directions().into_iter().enumerate() {
+            let message = format!("{} with wo.z = {}", case.name, wo.z);
+            let mut rnd = XorShiftRandom::new(index as u32 + 1);
+
+            // Importance sees the adjoint BSDF, so its albedo is the fraction of the energy
+            // arriving along `wo` that is scattered.
+            let (mut sum, mut squares) = (Color::new(0.0, 0.0, 0.0), Color::new(0.0, 0.0, 0.0));
+            for _ in 0..SAMPLES {
+                if let Some(sample) = case.bsdf.sample(&context, wo, &mut rnd) {
+                    sum = sum + sample.weight;
+                    squares = squares + sample.weight * sample.weight;
+                }
+            }
+
+            let n = SAMPLES as f64;
+            let albedo = sum / n;
+            for (albedo, squares) in [
+                (albedo.x, squares.x),
+                (albedo.y, squares.y),
+                (albedo.z, squares.z),
+            ] {
+                let error = ((squares / n - albedo * albedo).max(0.0) / n).sqrt();
+                let tolerance = 4.0 * error + 1e-3;
+                assert!(albedo <= 1.0 + tolerance, "{message}: albedo {albedo} > 1");
+                if case.lossless {
+                    assert!(
+                        (albedo - 1.0).abs() <= tolerance,
+                        "{message}: albedo {albedo} != 1"
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn reciprocity() {
+    let radiance = context();
+    let importance = context().with_mode(TransportMode::Importance);
+    let mut rnd = XorShiftRandom::new(1);
+
+    for case in cases().iter().filter(|case| !case.bsdf.is_specular()) {
+        for _ in 0..1000 {
+            let wo = direction(2.0 * rnd.next_f64() - 1.0, 2.0 * PI * rnd.next_f64());
+            let wi = direction(2.0 * rnd.next_f64() - 1.0, 2.0 * PI * rnd.next_f64());
+
+            // Swapping the directions of the BSDF gives its adjoint.
+            let f = case.bsdf.eval(&radiance, wo, wi);
+            let adjoint = case.bsdf.eval(&importance, wi, wo);
+            let message = format!("{} with wo = {:?}, wi = {:?}", case.name, wo, wi);
+            assert_close(f.x, adjoint.x, &message);
+            assert_close(f.y, adjoint.y, &message);
+            assert_close(f.z, adjoint.z, &message);
+        }
+    }
+}
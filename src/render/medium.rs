@@ -0,0 +1,282 @@
+use std::{f64::consts::PI, sync::Arc};
+
+use super::{
+    frame::Frame,
+    material::Color,
+    random::Sampler,
+    ray::Ray,
+    spectrum::{self, Wavelengths},
+    vec3::Vec3,
+};
+
+mod grid;
+
+use grid::DensityGrid;
+pub use grid::VoxelGrid;
+
+fn per_channel(color: Color, f: impl Fn(f64) -> f64) -> Color {
+    Color::new(f(color.x), f(color.y), f(color.z))
+}
+
+fn average(color: Color) -> f64 {
+    (color.x + color.y + color.z) / 3.0
+}
+
+fn homogeneous_transmittance(sigma_t: Color, distance: f64) -> Color {
+    per_channel(sigma_t, |sigma_t| {
+        if sigma_t > 0.0 {
+            (-sigma_t * distance).exp()
+        } else {
+            1.0
+        }
+    })
+}
+
+/// Henyey-Greenstein phase function over the angle between the incoming and scattered
+/// propagation directions, so positive `g` scatters forward.
+#[derive(Debug, Clone, Copy)]
+pub struct HenyeyGreenstein {
+    g: f64,
+}
+
+impl HenyeyGreenstein {
+    pub fn new(g: f64) -> HenyeyGreenstein {
+        HenyeyGreenstein {
+            g: g.clamp(-0.99, 0.99),
+        }
+    }
+
+    pub fn eval(&self, cos_theta: f64) -> f64 {
+        let g = self.g;
+        let denominator = 1.0 + g * g - 2.0 * g * cos_theta;
+        (1.0 - g * g) / (4.0 * PI * denominator * denominator.sqrt())
+    }
+
+    /// Samples a scattered direction for light travelling along `direction`, returning it with
+    /// its solid angle pdf.
+    pub fn sample(&self, direction: Vec3, u0: f64, u1: f64) -> (Vec3, f64) {
+        let g = self.g;
+        let cos_theta = if g.abs() < 1e-3 {
+            1.0 - 2.0 * u0
+        } else {
+            let s = (1.0 - g * g) / (1.0 - g + 2.0 * g * u0);
+            ((1.0 + g * g - s * s) / (2.0 * g)).clamp(-1.0, 1.0)
+        };
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * u1;
+
+        let scattered = Frame::from_normal(direction).to_world(Vec3::new(
+            sin_theta * phi.cos(),
+            sin_theta * phi.sin(),
+            cos_theta,
+        ));
+
+        (scattered, self.eval(cos_theta))
+    }
+}
+
+pub struct MediumSample {
+    /// Distance to the sampled scattering event, or `None` if the ray passed through.
+    pub distance: Option<f64>,
+    pub weight: Color,
+}
+
+/// Medium with absorption and scattering coefficients per unit distance, scaled by a voxel
+/// grid of densities when the medium is heterogeneous.
+#[derive(Debug, Clone)]
+pub struct Medium {
+    sigma_a: Color,
+    sigma_s: Color,
+    phase: HenyeyGreenstein,
+    density: Option<Arc<DensityGrid>>,
+}
+
+impl Medium {
+    pub fn new(sigma_a: Color, sigma_s: Color, g: f64) -> Medium {
+        Medium {
+            sigma_a,
+            sigma_s,
+            phase: HenyeyGreenstein::new(g),
+            density: None,
+        }
+    }
+
+    /// Heterogeneous medium with `voxels` stretched over the box from `min` to `max` and
+    /// empty outside of it.
+    pub fn grid(
+        voxels: VoxelGrid,
+        min: Vec3,
+        max: Vec3,
+        sigma_a: Color,
+        sigma_s: Color,
+        g: f64,
+    ) -> Medium {
+        Medium {
+            density: Some(Arc::new(DensityGrid::new(voxels, min, max))),
+            ..Medium::new(sigma_a, sigma_s, g)
+        }
+    }
+
+    /// Grey, non-absorbing medium scattering `density` per unit distance.
+    pub fn fog(density: f64, g: f64) -> Medium {
+        Medium::new(
+            Color::new(0.0, 0.0, 0.0),
+            Color::new(density, density, density),
+            g,
+        )
+    }
+
+    /// Medium for random-walk subsurface scattering, from the per-channel mean free path and
+    /// the multiple-scattering `albedo` the surface should appear to have. The albedo is
+    /// inverted to a single-scattering albedo with the fit from Chiang et al. 2016.
+    pub fn subsurface(albedo: Color, mean_free_path: Color, g: f64) -> Medium {
+        let single_scattering = per_channel(albedo, |albedo| {
+            let albedo = albedo.clamp(0.0, 1.0);
+            let a = 4.09712 + 4.20863 * albedo
+                - (9.59217 + 41.6808 * albedo + 17.7126 * albedo * albedo).sqrt();
+            1.0 - a * a
+        });
+        let sigma_t = per_channel(mean_free_path, |distance| 1.0 / distance.max(1e-6));
+        let sigma_s = sigma_t * single_scattering;
+
+        Medium::new(sigma_t - sigma_s, sigma_s, g)
+    }
+
+    pub fn phase(&self) -> HenyeyGreenstein {
+        self.phase
+    }
+
+    fn sigma_s(&self, wavelengths: Option<Wavelengths>) -> Color {
+        spectrum::reflectance(wavelengths, self.sigma_s)
+    }
+
+    fn sigma_t(&self, wavelengths: Option<Wavelengths>) -> Color {
+        spectrum::reflectance(wavelengths, self.sigma_a) + self.sigma_s(wavelengths)
+    }
+
+    /// Scattering and extinction coefficients at `position`.
+    pub fn coefficients(&self, position: Vec3, wavelengths: Option<Wavelengths>) -> (Color, Color) {
+        let density = self
+            .density
+            .as_ref()
+            .map_or(1.0, |density| density.density(position));
+        (
+            self.sigma_s(wavelengths) * density,
+            self.sigma_t(wavelengths) * density,
+        )
+    }
+
+    /// Transmittance along `ray` over `distance`, estimated by ratio tracking in
+    /// heterogeneous media.
+    pub fn transmittance(&self, ray: &Ray, distance: f64, rnd: &mut dyn Sampler) -> Color {
+        let sigma_t = self.sigma_t(ray.wavelengths);
+        let Some(density) = &self.density else {
+            return homogeneous_transmittance(sigma_t, distance);
+        };
+
+        let mut transmittance = Color::new(1.0, 1.0, 1.0);
+        density.track(
+            ray,
+            distance,
+            sigma_t.max(),
+            rnd,
+            |_, density, majorant, _| {
+                let sigma_n = per_channel(sigma_t * density, |sigma_t| majorant - sigma_t);
+                transmittance = transmittance * sigma_n / majorant;
+                transmittance.max() > 0.0
+            },
+        );
+
+        transmittance
+    }
+
+    /// Samples a free-flight distance along `ray`, which reaches a surface at `max_distance`.
+    pub fn sample(&self, ray: &Ray, max_distance: f64, rnd: &mut dyn Sampler) -> MediumSample {
+        match &self.density {
+            Some(density) => self.delta_tracking(density, ray, max_distance, rnd),
+            None => self.sample_homogeneous(ray, max_distance, rnd),
+        }
+    }
+
+    /// Delta tracking against the majorant grid. Real and null collisions are chosen by their
+    /// average probability over the color channels and weighted by the per-channel ratio.
+    fn delta_tracking(
+        &self,
+        density: &DensityGrid,
+        ray: &Ray,
+        max_distance: f64,
+        rnd: &mut dyn Sampler,
+    ) -> MediumSample {
+        let sigma_s = self.sigma_s(ray.wavelengths);
+        let sigma_t = self.sigma_t(ray.wavelengths);
+        let mut weight = Color::new(1.0, 1.0, 1.0);
+        let mut distance = None;
+
+        density.track(
+            ray,
+            max_distance,
+            sigma_t.max(),
+            rnd,
+            |t, density, majorant, rnd| {
+                let real = sigma_t * density;
+                let null = per_channel(real, |sigma_t| majorant - sigma_t);
+
+                // Where the density reaches the majorant there are no null collisions, even
+                // when the draw comes out as exactly one.
+                if rnd.next_f64() * majorant < average(real) || average(null) <= 0.0 {
+                    weight = weight * sigma_s * density / average(real);
+                    distance = Some(t);
+                    false
+                } else {
+                    weight = weight * null / average(null);
+                    true
+                }
+            },
+        );
+
+        MediumSample { distance, weight }
+    }
+
+    /// Picks one color channel to drive the exponential and weights by the average pdf over
+    /// all three.
+    fn sample_homogeneous(
+        &self,
+        ray: &Ray,
+        max_distance: f64,
+        rnd: &mut dyn Sampler,
+    ) -> MediumSample {
+        let sigma_s = self.sigma_s(ray.wavelengths);
+        let sigma_t = self.sigma_t(ray.wavelengths);
+        let sigma = match (rnd.next_f64() * 3.0) as usize {
+            0 => sigma_t.x,
+            1 => sigma_t.y,
+            _ => sigma_t.z,
+        };
+
+        let distance = if sigma > 0.0 {
+            -(1.0 - rnd.next_f64()).ln() / sigma
+        } else {
+            f64::INFINITY
+        };
+
+        if distance < max_distance {
+            let transmittance = homogeneous_transmittance(sigma_t, distance);
+            let pdf = average(sigma_t * transmittance);
+            MediumSample {
+                distance: Some(distance),
+                weight: transmittance * sigma_s / pdf,
+            }
+        } else {
+            let transmittance = homogeneous_transmittance(sigma_t, max_distance);
+            let pdf = average(transmittance);
+            MediumSample {
+                distance: None,
+                weight: if pdf > 0.0 {
+                    transmittance / pdf
+                } else {
+                    Color::new(0.0, 0.0, 0.0)
+                },
+            }
+        }
+    }
+}
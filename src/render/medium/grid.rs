@@ -0,0 +1,245 @@
+use std::io::{Error, ErrorKind};
+
+use super::super::{random::Sampler, ray::Ray, vec3::Vec3};
+
+const MAGIC: &[u8; 4] = b"VOL1";
+const MAJORANT_CELL_SIZE: usize = 8;
+
+fn invalid_data(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message.to_string())
+}
+
+/// Dense grid of densities with voxel centers at `(i + 0.5) / resolution` in the unit cube.
+#[derive(Debug)]
+pub struct VoxelGrid {
+    resolution: [usize; 3],
+    values: Vec<f64>,
+}
+
+impl VoxelGrid {
+    /// `values` are ordered with x varying fastest and z slowest.
+    pub fn new(width: usize, height: usize, depth: usize, values: Vec<f64>) -> VoxelGrid {
+        assert_eq!(values.len(), width * height * depth);
+
+        VoxelGrid {
+            resolution: [width, height, depth],
+            values,
+        }
+    }
+
+    /// Loads a raw voxel grid: the bytes `VOL1`, the x, y and z resolutions as little-endian
+    /// `u32`s, then one little-endian `f32` density per voxel with x varying fastest and z
+    /// slowest.
+    pub fn load(file_name: &str) -> std::io::Result<VoxelGrid> {
+        let bytes = std::fs::read(file_name)?;
+
+        if bytes.len() < 16 || &bytes[0..4] != MAGIC {
+            return Err(invalid_data("Not a VOL1 voxel grid"));
+        }
+
+        let dimension =
+            |i: usize| u32::from_le_bytes(bytes[4 + 4 * i..8 + 4 * i].try_into().unwrap()) as usize;
+        let (width, height, depth) = (dimension(0), dimension(1), dimension(2));
+        if width == 0 || height == 0 || depth == 0 {
+            return Err(invalid_data("Empty voxel grid"));
+        }
+
+        let count = width
+            .checked_mul(height)
+            .and_then(|count| count.checked_mul(depth))
+            .ok_or_else(|| invalid_data("Voxel grid too large"))?;
+        let data = &bytes[16..];
+        if Some(data.len()) != count.checked_mul(4) {
+            return Err(invalid_data(
+                "Voxel grid size does not match its resolution",
+            ));
+        }
+
+        let values = data
+            .chunks_exact(4)
+            .map(|chunk| f32::from_le_bytes(chunk.try_into().unwrap()).max(0.0) as f64)
+            .collect();
+
+        Ok(VoxelGrid::new(width, height, depth, values))
+    }
+
+    fn value(&self, x: i64, y: i64, z: i64) -> f64 {
+        let [width, height, depth] = self.resolution;
+        if x < 0 || y < 0 || z < 0 || x >= width as i64 || y >= height as i64 || z >= depth as i64 {
+            return 0.0;
+        }
+
+        self.values[(z as usize * height + y as usize) * width + x as usize]
+    }
+
+    /// Trilinearly interpolated density at `p` in the unit cube, zero outside of it.
+    fn density(&self, p: [f64; 3]) -> f64 {
+        let x = p[0] * self.resolution[0] as f64 - 0.5;
+        let y = p[1] * self.resolution[1] as f64 - 0.5;
+        let z = p[2] * self.resolution[2] as f64 - 0.5;
+        let (x0, y0, z0) = (x.floor(), y.floor(), z.floor());
+        let (tx, ty, tz) = (x - x0, y - y0, z - z0);
+        let (x0, y0, z0) = (x0 as i64, y0 as i64, z0 as i64);
+
+        let lerp = |a: f64, b: f64, t: f64| a + (b - a) * t;
+        let row = |y: i64, z: i64| lerp(self.value(x0, y, z), self.value(x0 + 1, y, z), tx);
+        let slice = |z: i64| lerp(row(y0, z), row(y0 + 1, z), ty);
+
+        lerp(slice(z0), slice(z0 + 1), tz)
+    }
+}
+
+/// Voxel grid placed in an axis-aligned box, with a coarse grid of maximum densities bounding
+/// each block of voxels for tracking.
+#[derive(Debug)]
+pub struct DensityGrid {
+    voxels: VoxelGrid,
+    min: Vec3,
+    size: Vec3,
+    resolution: [usize; 3],
+    majorants: Vec<f64>,
+}
+
+impl DensityGrid {
+    pub fn new(voxels: VoxelGrid, min: Vec3, max: Vec3) -> DensityGrid {
+        let resolution = voxels
+            .resolution
+            .map(|n| n.div_ceil(MAJORANT_CELL_SIZE).max(1));
+
+        // Voxels interpolated anywhere inside a cell, including its border neighbours.
+        let voxel_range = |axis: usize, cell: usize| {
+            let scale = voxels.resolution[axis] as f64 / resolution[axis] as f64;
+            let first = (cell as f64 * scale - 0.5).floor() as i64;
+            let last = ((cell + 1) as f64 * scale - 0.5).floor() as i64 + 1;
+            first..=last
+        };
+
+        let mut majorants = Vec::with_capacity(resolution.iter().product());
+        for k in 0..resolution[2] {
+            for j in 0..resolution[1] {
+                for i in 0..resolution[0] {
+                    let mut majorant: f64 = 0.0;
+                    for z in voxel_range(2, k) {
+                        for y in voxel_range(1, j) {
+                            for x in voxel_range(0, i) {
+                                majorant = majorant.max(voxels.value(x, y, z));
+                            }
+                        }
+                    }
+                    majorants.push(majorant);
+                }
+            }
+        }
+
+        DensityGrid {
+            voxels,
+            min,
+            size: max - min,
+            resolution,
+            majorants,
+        }
+    }
+
+    fn to_grid(&self, p: Vec3) -> [f64; 3] {
+        [
+            (p.x - self.min.x) / self.size.x,
+            (p.y - self.min.y) / self.size.y,
+            (p.z - self.min.z) / self.size.z,
+        ]
+    }
+
+    /// Density at `p`, zero outside of the box.
+    pub fn density(&self, p: Vec3) -> f64 {
+        self.voxels.density(self.to_grid(p))
+    }
+
+    /// Draws tentative collisions along `ray` up to `max_distance` from the majorant `scale`
+    /// times the maximum density of each cell crossed. `collide` receives the distance, the
+    /// density there and the majorant, and returns whether to keep tracking.
+    pub fn track(
+        &self,
+        ray: &Ray,
+        max_distance: f64,
+        scale: f64,
+        rnd: &mut dyn Sampler,
+        mut collide: impl FnMut(f64, f64, f64, &mut dyn Sampler) -> bool,
+    ) {
+        let origin = self.to_grid(ray.origin);
+        let direction = [
+            ray.direction.x / self.size.x,
+            ray.direction.y / self.size.y,
+            ray.direction.z / self.size.z,
+        ];
+
+        let mut t_min: f64 = 0.0;
+        let mut t_max = max_distance;
+        for axis in 0..3 {
+            if direction[axis] == 0.0 {
+                if !(0.0..=1.0).contains(&origin[axis]) {
+                    return;
+                }
+            } else {
+                let t0 = -origin[axis] / direction[axis];
+                let t1 = (1.0 - origin[axis]) / direction[axis];
+                t_min = t_min.max(t0.min(t1));
+                t_max = t_max.min(t0.max(t1));
+            }
+        }
+        if t_min >= t_max {
+            return;
+        }
+
+        let mut cell = [0i64; 3];
+        let mut next = [f64::INFINITY; 3];
+        let mut delta = [f64::INFINITY; 3];
+        let mut step = [0i64; 3];
+        for axis in 0..3 {
+            let n = self.resolution[axis];
+            let p = (origin[axis] + direction[axis] * t_min) * n as f64;
+            cell[axis] = (p.floor() as i64).clamp(0, n as i64 - 1);
+
+            if direction[axis] != 0.0 {
+                step[axis] = if direction[axis] > 0.0 { 1 } else { -1 };
+                let boundary = (cell[axis] + (step[axis] > 0) as i64) as f64 / n as f64;
+                next[axis] = (boundary - origin[axis]) / direction[axis];
+                delta[axis] = 1.0 / (n as f64 * direction[axis].abs());
+            }
+        }
+
+        let mut t = t_min;
+        loop {
+            let axis = (0..3).min_by(|a, b| next[*a].total_cmp(&next[*b])).unwrap();
+            let t_exit = next[axis].min(t_max);
+
+            let index = (cell[2] as usize * self.resolution[1] + cell[1] as usize)
+                * self.resolution[0]
+                + cell[0] as usize;
+            let majorant = scale * self.majorants[index];
+
+            if majorant > 0.0 {
+                loop {
+                    t -= (1.0 - rnd.next_f64()).ln() / majorant;
+                    if t >= t_exit {
+                        break;
+                    }
+
+                    let density = self.density(ray.origin + ray.direction * t);
+                    if !collide(t, density, majorant, rnd) {
+                        return;
+                    }
+                }
+            }
+
+            if t_exit >= t_max {
+                return;
+            }
+
+            t = t_exit;
+            cell[axis] += step[axis];
+            if cell[axis] < 0 || cell[axis] >= self.resolution[axis] as i64 {
+                return;
+            }
+            next[axis] += delta[axis];
+        }
+    }
+}
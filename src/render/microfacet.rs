@@ -0,0 +1,85 @@
+use std::f64::consts::PI;
+
+use super::vec3::Vec3;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Ggx {
+    pub alpha_x: f64,
+    pub alpha_y: f64,
+}
+
+impl Ggx {
+    pub fn new(alpha_x: f64, alpha_y: f64) -> Ggx {
+        Ggx {
+            alpha_x: alpha_x.max(1e-4),
+            alpha_y: alpha_y.max(1e-4),
+        }
+    }
+
+    pub fn is_smooth(&self) -> bool {
+        self.alpha_x.max(self.alpha_y) < 1e-3
+    }
+
+    pub fn d(&self, h: Vec3) -> f64 {
+        if h.z <= 0.0 {
+            return 0.0;
+        }
+
+        let x = h.x / self.alpha_x;
+        let y = h.y / self.alpha_y;
+        let d = x * x + y * y + h.z * h.z;
+
+        1.0 / (PI * self.alpha_x * self.alpha_y * d * d)
+    }
+
+    pub fn lambda(&self, w: Vec3) -> f64 {
+        if w.z == 0.0 {
+            return f64::INFINITY;
+        }
+
+        let a2 = (self.alpha_x * w.x).powi(2) + (self.alpha_y * w.y).powi(2);
+        ((1.0 + a2 / (w.z * w.z)).sqrt() - 1.0) / 2.0
+    }
+
+    pub fn g1(&self, w: Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    pub fn g(&self, wo: Vec3, wi: Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    pub fn sample_visible_normal(&self, wo: Vec3, u0: f64, u1: f64) -> Vec3 {
+        let flip = if wo.z < 0.0 { -1.0 } else { 1.0 };
+        let vh = (Vec3::new(self.alpha_x * wo.x, self.alpha_y * wo.y, wo.z) * flip).normalize();
+
+        let length_squared = vh.x * vh.x + vh.y * vh.y;
+        let t1 = if length_squared > 0.0 {
+            Vec3::new(-vh.y, vh.x, 0.0) / length_squared.sqrt()
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let t2 = vh.cross(t1);
+
+        let r = u0.sqrt();
+        let phi = 2.0 * PI * u1;
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1.0 + vh.z);
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).max(0.0).sqrt() + s * r * phi.sin();
+        let nh = t1 * p1 + t2 * p2 + vh * (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt();
+
+        Vec3::new(self.alpha_x * nh.x, self.alpha_y * nh.y, nh.z.max(1e-6)).normalize()
+    }
+
+    pub fn visible_pdf(&self, wo: Vec3, h: Vec3) -> f64 {
+        if wo.z == 0.0 {
+            return 0.0;
+        }
+
+        self.g1(wo) * wo.dot(h).abs() * self.d(h) / wo.z.abs()
+    }
+}
+
+pub fn reflect(wo: Vec3, n: Vec3) -> Vec3 {
+    n * (2.0 * wo.dot(n)) - wo
+}
@@ -0,0 +1,232 @@
+use std::{f64::consts::PI, thread};
+
+use super::{
+    distribution::Distribution1D,
+    film::Film,
+    material::Color,
+    random::{Sampler, XorShiftRandom},
+    spectrum::{self, Wavelengths},
+    Render,
+};
+
+/// Paths traced with fresh samples to estimate the image brightness and seed the chains.
+const BOOTSTRAP_SAMPLES: u32 = 100_000;
+const CHAINS: u32 = 1000;
+/// Standard deviation of the small step perturbation of each primary sample.
+const SIGMA: f64 = 0.01;
+const LARGE_STEP_PROBABILITY: f64 = 0.3;
+
+#[derive(Clone, Copy)]
+struct PrimarySample {
+    value: f64,
+    /// Iteration the value was last changed in.
+    modified: u64,
+    backup: (f64, u64),
+}
+
+/// Point in primary sample space, made up lazily as a path asks for random numbers and
+/// mutated one coordinate at a time when it is next read.
+pub struct PrimarySamples {
+    rnd: XorShiftRandom,
+    samples: Vec<PrimarySample>,
+    index: usize,
+    iteration: u64,
+    large_step: bool,
+    last_large_step: u64,
+}
+
+impl PrimarySamples {
+    pub fn new(seed: u32) -> PrimarySamples {
+        PrimarySamples {
+            rnd: XorShiftRandom::new(seed),
+            samples: Vec::new(),
+            index: 0,
+            iteration: 0,
+            large_step: true,
+            last_large_step: 0,
+        }
+    }
+
+    /// Starts a proposal, either independent of the current point or close to it.
+    pub fn start_iteration(&mut self) {
+        self.iteration += 1;
+        self.large_step = self.rnd.next_f64() < LARGE_STEP_PROBABILITY;
+        self.index = 0;
+    }
+
+    pub fn accept(&mut self) {
+        if self.large_step {
+            self.last_large_step = self.iteration;
+        }
+    }
+
+    pub fn reject(&mut self) {
+        for sample in self.samples.iter_mut() {
+            if sample.modified == self.iteration {
+                (sample.value, sample.modified) = sample.backup;
+            }
+        }
+        self.iteration -= 1;
+    }
+}
+
+impl Sampler for PrimarySamples {
+    fn next_f64(&mut self) -> f64 {
+        if self.index == self.samples.len() {
+            self.samples.push(PrimarySample {
+                value: 0.0,
+                modified: 0,
+                backup: (0.0, 0),
+            });
+        }
+
+        let sample = &mut self.samples[self.index];
+        self.index += 1;
+
+        // Catch up on the mutations accepted since the coordinate was last read.
+        if sample.modified < self.last_large_step {
+            sample.value = self.rnd.next_f64();
+            sample.modified = self.last_large_step;
+        }
+
+        sample.backup = (sample.value, sample.modified);
+        if self.large_step {
+            sample.value = self.rnd.next_f64();
+        } else {
+            // Successive small steps add up to a single one with a wider spread.
+            let small_steps = (self.iteration - sample.modified) as f64;
+            let (u0, u1) = (self.rnd.next_f64(), self.rnd.next_f64());
+            let normal = (-2.0 * (1.0 - u0).ln()).sqrt() * (2.0 * PI * u1).cos();
+
+            sample.value += normal * SIGMA * small_steps.sqrt();
+            sample.value -= sample.value.floor();
+        }
+        sample.modified = self.iteration;
+
+        sample.value
+    }
+}
+
+/// Primary sample space Metropolis light transport (Kelemen et al.), running Markov chains
+/// over the random numbers fed to the path tracer with the luminance of the resulting path
+/// as the target function.
+pub struct Mlt<'a> {
+    render: &'a Render,
+}
+
+impl<'a> Mlt<'a> {
+    pub fn new(render: &'a Render) -> Mlt<'a> {
+        Mlt { render }
+    }
+
+    pub fn render(&self) -> Vec<Color> {
+        let config = self.render.config;
+        let tasks = config.tasks.max(1);
+        let pixel_count = config.width as u64 * config.height as u64;
+        let mutations =
+            pixel_count * (config.samples * config.super_samples * config.super_samples) as u64;
+
+        println!("Bootstrapping ({} samples)", BOOTSTRAP_SAMPLES);
+        let weights: Vec<f64> = (0..BOOTSTRAP_SAMPLES)
+            .map(|i| {
+                let mut rnd = PrimarySamples::new(i + 1);
+                let (radiance, _) = self.path(&mut rnd);
+                target(radiance)
+            })
+            .collect();
+        let bootstrap = Distribution1D::new(&weights);
+        let brightness = bootstrap.integral();
+
+        let film = Film::new(config.width, config.height);
+        if brightness > 0.0 {
+            let film = &film;
+            let bootstrap = &bootstrap;
+            thread::scope(|s| {
+                for task in 0..tasks {
+                    s.spawn(move || {
+                        for chain in (task..CHAINS).step_by(tasks as usize) {
+                            println!("Rendering (chain = {} / {})", chain, CHAINS);
+                            // The first chains take the remainder, so the chains run exactly
+                            // as many mutations as the film is developed for.
+                            let extra = (chain as u64) < mutations % CHAINS as u64;
+                            let count = mutations / CHAINS as u64 + extra as u64;
+                            self.run_chain(chain, bootstrap, count, film);
+                        }
+                    });
+                }
+            });
+        }
+
+        // Every mutation splats one unit of the target function.
+        film.develop(brightness * pixel_count as f64 / mutations as f64)
+    }
+
+    fn run_chain(&self, chain: u32, bootstrap: &Distribution1D, mutations: u64, film: &Film) {
+        let mut rnd = XorShiftRandom::new(chain + 1);
+
+        // Starting from a bootstrap path replays it, as its samples come from the same seed.
+        let (index, _) = bootstrap.sample_discrete(rnd.next_f64());
+        let mut samples = PrimarySamples::new(index as u32 + 1);
+        let (mut current, mut current_raster) = self.path(&mut samples);
+
+        for _ in 0..mutations {
+            samples.start_iteration();
+            let (proposed, proposed_raster) = self.path(&mut samples);
+
+            let current_target = target(current);
+            let proposed_target = target(proposed);
+            let acceptance = if current_target > 0.0 {
+                (proposed_target / current_target).min(1.0)
+            } else {
+                1.0
+            };
+
+            // Both states are splatted by their expected share, which keeps rejected
+            // proposals from being wasted.
+            if acceptance > 0.0 {
+                let (x, y) = proposed_raster;
+                film.splat(x, y, proposed * (acceptance / proposed_target));
+            }
+            if acceptance < 1.0 {
+                let (x, y) = current_raster;
+                film.splat(x, y, current * ((1.0 - acceptance) / current_target));
+            }
+
+            if rnd.next_f64() < acceptance {
+                samples.accept();
+                current = proposed;
+                current_raster = proposed_raster;
+            } else {
+                samples.reject();
+            }
+        }
+    }
+
+    /// Path traced from the camera through a raster position taken from the first two
+    /// samples, returning its RGB radiance and that position.
+    fn path(&self, rnd: &mut dyn Sampler) -> (Color, (f64, f64)) {
+        let render = self.render;
+        let x = rnd.next_f64() * render.config.width as f64;
+        let y = rnd.next_f64() * render.config.height as f64;
+
+        let wavelengths = render
+            .config
+            .spectral
+            .then(|| Wavelengths::sample(rnd.next_f64()));
+        let ray = render.camera.ray(x, y).with_wavelengths(wavelengths);
+        let radiance = render.radiance(
+            &ray,
+            rnd,
+            render.camera.cone(),
+            render.scene.global_medium(),
+            None,
+        );
+
+        (spectrum::to_rgb(wavelengths, radiance), (x, y))
+    }
+}
+
+/// Target function of the chains, which must be positive wherever a path contributes.
+fn target(radiance: Color) -> f64 {
+    radiance.luminance().max(0.0)
+}
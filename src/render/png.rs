@@ -0,0 +1,714 @@
+use super::material::Color;
+
+use std::io::{Error, ErrorKind};
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a];
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+fn invalid_data(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message.to_string())
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    bit: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> BitReader<'a> {
+        BitReader {
+            data,
+            position: 0,
+            bit: 0,
+        }
+    }
+
+    fn read_bit(&mut self) -> std::io::Result<u32> {
+        let byte = *self
+            .data
+            .get(self.position)
+            .ok_or_else(|| invalid_data("Unexpected end of deflate stream"))?;
+        let value = (byte >> self.bit) & 1;
+
+        self.bit += 1;
+        if self.bit == 8 {
+            self.bit = 0;
+            self.position += 1;
+        }
+
+        Ok(value as u32)
+    }
+
+    fn read_bits(&mut self, count: u8) -> std::io::Result<u32> {
+        let mut value = 0;
+        for i in 0..count {
+            value |= self.read_bit()? << i;
+        }
+        Ok(value)
+    }
+
+    fn align_to_byte(&mut self) {
+        if self.bit != 0 {
+            self.bit = 0;
+            self.position += 1;
+        }
+    }
+}
+
+/// Canonical Huffman decoder built from code lengths, decoded one bit at a time.
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Huffman {
+        let mut counts = [0u16; 16];
+        for &length in lengths {
+            counts[length as usize] += 1;
+        }
+        counts[0] = 0;
+
+        let mut offsets = [0u16; 16];
+        for i in 1..16 {
+            offsets[i] = offsets[i - 1] + counts[i - 1];
+        }
+
+        let mut symbols = vec![0u16; lengths.len()];
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length != 0 {
+                symbols[offsets[length as usize] as usize] = symbol as u16;
+                offsets[length as usize] += 1;
+            }
+        }
+
+        Huffman { counts, symbols }
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> std::io::Result<u16> {
+        let mut code: i32 = 0;
+        let mut first: i32 = 0;
+        let mut index: i32 = 0;
+
+        for length in 1..16 {
+            code |= reader.read_bit()? as i32;
+            let count = self.counts[length] as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+
+        Err(invalid_data("Invalid Huffman code"))
+    }
+}
+
+fn fixed_tables() -> (Huffman, Huffman) {
+    let mut lengths = [0u8; 288];
+    lengths[..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..].fill(8);
+
+    (Huffman::new(&lengths), Huffman::new(&[5u8; 30]))
+}
+
+fn dynamic_tables(reader: &mut BitReader) -> std::io::Result<(Huffman, Huffman)> {
+    let literal_count = reader.read_bits(5)? as usize + 257;
+    let distance_count = reader.read_bits(5)? as usize + 1;
+    let code_length_count = reader.read_bits(4)? as usize + 4;
+
+    let mut code_lengths = [0u8; 19];
+    for &index in CODE_LENGTH_ORDER.iter().take(code_length_count) {
+        code_lengths[index] = reader.read_bits(3)? as u8;
+    }
+    let code_length_huffman = Huffman::new(&code_lengths);
+
+    let mut lengths = Vec::with_capacity(literal_count + distance_count);
+    while lengths.len() < literal_count + distance_count {
+        let symbol = code_length_huffman.decode(reader)?;
+        match symbol {
+            0..=15 => lengths.push(symbol as u8),
+            16 => {
+                let previous = *lengths
+                    .last()
+                    .ok_or_else(|| invalid_data("Repeat without previous code length"))?;
+                let repeat = 3 + reader.read_bits(2)?;
+                lengths.extend(std::iter::repeat_n(previous, repeat as usize));
+            }
+            17 => {
+                let repeat = 3 + reader.read_bits(3)?;
+                lengths.extend(std::iter::repeat_n(0, repeat as usize));
+            }
+            _ => {
+                let repeat = 11 + reader.read_bits(7)?;
+                lengths.extend(std::iter::repeat_n(0, repeat as usize));
+            }
+        }
+    }
+
+    if lengths.len() > literal_count + distance_count {
+        return Err(invalid_data("Code lengths overflow"));
+    }
+
+    Ok((
+        Huffman::new(&lengths[..literal_count]),
+        Huffman::new(&lengths[literal_count..]),
+    ))
+}
+
+fn inflate_block(
+    reader: &mut BitReader,
+    output: &mut Vec<u8>,
+    literals: &Huffman,
+    distances: &Huffman,
+) -> std::io::Result<()> {
+    loop {
+        let symbol = literals.decode(reader)? as usize;
+        if symbol < 256 {
+            output.push(symbol as u8);
+            continue;
+        }
+        if symbol == 256 {
+            return Ok(());
+        }
+
+        let index = symbol - 257;
+        if index >= LENGTH_BASE.len() {
+            return Err(invalid_data("Invalid length symbol"));
+        }
+        let length = LENGTH_BASE[index] as usize + reader.read_bits(LENGTH_EXTRA[index])? as usize;
+
+        let index = distances.decode(reader)? as usize;
+        if index >= DISTANCE_BASE.len() {
+            return Err(invalid_data("Invalid distance symbol"));
+        }
+        let distance =
+            DISTANCE_BASE[index] as usize + reader.read_bits(DISTANCE_EXTRA[index])? as usize;
+
+        if distance > output.len() {
+            return Err(invalid_data("Distance exceeds output"));
+        }
+
+        let start = output.len() - distance;
+        for i in 0..length {
+            output.push(output[start + i]);
+        }
+    }
+}
+
+fn inflate(data: &[u8]) -> std::io::Result<Vec<u8>> {
+    if data.len() < 2
+        || data[0] & 0x0f != 8
+        || !(data[0] as u16 * 256 + data[1] as u16).is_multiple_of(31)
+    {
+        return Err(invalid_data("Invalid zlib header"));
+    }
+    if data[1] & 0x20 != 0 {
+        return Err(invalid_data("Preset zlib dictionaries are not supported"));
+    }
+
+    let mut reader = BitReader::new(&data[2..]);
+    let mut output = Vec::new();
+
+    loop {
+        let last = reader.read_bit()? == 1;
+        match reader.read_bits(2)? {
+            0 => {
+                reader.align_to_byte();
+                let header = reader
+                    .data
+                    .get(reader.position..reader.position + 4)
+                    .ok_or_else(|| invalid_data("Truncated stored block"))?;
+                let length = u16::from_le_bytes([header[0], header[1]]);
+                if u16::from_le_bytes([header[2], header[3]]) != !length {
+                    return Err(invalid_data("Corrupt stored block length"));
+                }
+                let length = length as usize;
+                let start = reader.position + 4;
+                let block = reader
+                    .data
+                    .get(start..start + length)
+                    .ok_or_else(|| invalid_data("Truncated stored block"))?;
+                output.extend_from_slice(block);
+                reader.position = start + length;
+            }
+            1 => {
+                let (literals, distances) = fixed_tables();
+                inflate_block(&mut reader, &mut output, &literals, &distances)?;
+            }
+            2 => {
+                let (literals, distances) = dynamic_tables(&mut reader)?;
+                inflate_block(&mut reader, &mut output, &literals, &distances)?;
+            }
+            _ => return Err(invalid_data("Invalid deflate block type")),
+        }
+
+        if last {
+            return Ok(output);
+        }
+    }
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let pa = (p - a as i16).abs();
+    let pb = (p - b as i16).abs();
+    let pc = (p - c as i16).abs();
+
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+fn unfilter(data: &[u8], height: usize, stride: usize, bpp: usize) -> std::io::Result<Vec<u8>> {
+    let size = (stride + 1)
+        .checked_mul(height)
+        .ok_or_else(|| invalid_data("PNG image too large"))?;
+    if data.len() < size {
+        return Err(invalid_data("Truncated PNG image data"));
+    }
+
+    let mut image = vec![0u8; height * stride];
+    for y in 0..height {
+        let filter = data[y * (stride + 1)];
+        let line = &data[y * (stride + 1) + 1..(y + 1) * (stride + 1)];
+
+        for x in 0..stride {
+            let a = if x >= bpp {
+                image[y * stride + x - bpp]
+            } else {
+                0
+            };
+            let b = if y > 0 {
+                image[(y - 1) * stride + x]
+            } else {
+                0
+            };
+            let c = if x >= bpp && y > 0 {
+                image[(y - 1) * stride + x - bpp]
+            } else {
+                0
+            };
+
+            image[y * stride + x] = match filter {
+                0 => line[x],
+                1 => line[x].wrapping_add(a),
+                2 => line[x].wrapping_add(b),
+                3 => line[x].wrapping_add(((a as u16 + b as u16) / 2) as u8),
+                4 => line[x].wrapping_add(paeth(a, b, c)),
+                _ => return Err(invalid_data("Invalid PNG filter type")),
+            };
+        }
+    }
+
+    Ok(image)
+}
+
+/// Decodes a non-interlaced PNG into un-gamma-corrected values in `[0, 1]`.
+fn decode_png(bytes: &[u8]) -> std::io::Result<(Vec<Color>, u32, u32)> {
+    if bytes.len() < 8 || bytes[..8] != SIGNATURE {
+        return Err(invalid_data("Missing PNG signature"));
+    }
+
+    let mut position = 8;
+    let mut header = None;
+    let mut palette = Vec::new();
+    let mut compressed = Vec::new();
+
+    while position + 8 <= bytes.len() {
+        let length = u32::from_be_bytes(bytes[position..position + 4].try_into().unwrap()) as usize;
+        let kind = &bytes[position + 4..position + 8];
+        let data = bytes
+            .get(position + 8..position + 8 + length)
+            .ok_or_else(|| invalid_data("Truncated PNG chunk"))?;
+        position += 12 + length;
+
+        match kind {
+            b"IHDR" => {
+                if data.len() != 13 {
+                    return Err(invalid_data("Invalid IHDR chunk"));
+                }
+                header = Some((
+                    u32::from_be_bytes(data[0..4].try_into().unwrap()),
+                    u32::from_be_bytes(data[4..8].try_into().unwrap()),
+                    data[8],
+                    data[9],
+                    data[12],
+                ));
+            }
+            b"PLTE" => {
+                palette = data
+                    .chunks_exact(3)
+                    .map(|rgb| {
+                        Color::new(
+                            rgb[0] as f64 / 255.0,
+                            rgb[1] as f64 / 255.0,
+                            rgb[2] as f64 / 255.0,
+                        )
+                    })
+                    .collect();
+            }
+            b"IDAT" => compressed.extend_from_slice(data),
+            b"IEND" => break,
+            _ => {}
+        }
+    }
+
+    let (width, height, bit_depth, color_type, interlace) =
+        header.ok_or_else(|| invalid_data("Missing IHDR chunk"))?;
+    if interlace != 0 {
+        return Err(invalid_data("Interlaced PNGs are not supported"));
+    }
+    if width == 0 || height == 0 {
+        return Err(invalid_data("Empty PNG image"));
+    }
+    let size = (width as usize)
+        .checked_mul(height as usize)
+        .ok_or_else(|| invalid_data("PNG image too large"))?;
+
+    let channels = match color_type {
+        0 => 1,
+        2 => 3,
+        3 => 1,
+        4 => 2,
+        6 => 4,
+        _ => return Err(invalid_data("Invalid PNG color type")),
+    };
+    let supported = match color_type {
+        3 => matches!(bit_depth, 1 | 2 | 4 | 8),
+        _ => matches!(bit_depth, 8 | 16),
+    };
+    if !supported {
+        return Err(invalid_data("Unsupported PNG bit depth"));
+    }
+
+    let bits_per_pixel = channels * bit_depth as usize;
+    let stride = (width as usize)
+        .checked_mul(bits_per_pixel)
+        .ok_or_else(|| invalid_data("PNG image too large"))?
+        .div_ceil(8);
+    let bpp = bits_per_pixel.div_ceil(8);
+    let image = unfilter(&inflate(&compressed)?, height as usize, stride, bpp)?;
+
+    let max_value = ((1u32 << bit_depth) - 1) as f64;
+    let raw = |row: &[u8], index: usize| -> u32 {
+        match bit_depth {
+            16 => u16::from_be_bytes([row[index * 2], row[index * 2 + 1]]) as u32,
+            8 => row[index] as u32,
+            _ => {
+                let bit = index * bit_depth as usize;
+                let shift = 8 - bit_depth as usize - bit % 8;
+                (row[bit / 8] >> shift) as u32 & ((1 << bit_depth) - 1)
+            }
+        }
+    };
+    let sample = |row: &[u8], index: usize| raw(row, index) as f64 / max_value;
+
+    let mut pixels = Vec::with_capacity(size);
+    for row in image.chunks_exact(stride) {
+        for x in 0..width as usize {
+            let color = match color_type {
+                0 | 4 => {
+                    let gray = sample(row, x * channels);
+                    Color::new(gray, gray, gray)
+                }
+                3 => *palette
+                    .get(raw(row, x) as usize)
+                    .ok_or_else(|| invalid_data("PNG palette index out of range"))?,
+                _ => Color::new(
+                    sample(row, x * channels),
+                    sample(row, x * channels + 1),
+                    sample(row, x * channels + 2),
+                ),
+            };
+            pixels.push(color);
+        }
+    }
+
+    Ok((pixels, width, height))
+}
+
+pub fn load_png(file_name: &str) -> std::io::Result<(Vec<Color>, u32, u32)> {
+    decode_png(&std::fs::read(file_name)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Zlib stream holding `blocks` as stored deflate blocks.
+    fn stored(blocks: &[&[u8]]) -> Vec<u8> {
+        let mut stream = vec![0x78, 0x01];
+        for (index, block) in blocks.iter().enumerate() {
+            let length = block.len() as u16;
+            stream.push((index + 1 == blocks.len()) as u8);
+            stream.extend(length.to_le_bytes());
+            stream.extend((!length).to_le_bytes());
+            stream.extend(*block);
+        }
+        stream
+    }
+
+    fn hex(text: &str) -> Vec<u8> {
+        (0..text.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&text[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    fn chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+        png.extend((data.len() as u32).to_be_bytes());
+        png.extend(kind);
+        png.extend(data);
+        // The decoder does not verify checksums.
+        png.extend([0; 4]);
+    }
+
+    /// PNG of `rows` of unfiltered bytes, each filtered with the filter type that follows it.
+    fn encode(
+        width: u32,
+        bit_depth: u8,
+        color_type: u8,
+        palette: &[u8],
+        rows: &[(Vec<u8>, u8)],
+    ) -> Vec<u8> {
+        let channels = match color_type {
+            2 => 3,
+            4 => 2,
+            6 => 4,
+            _ => 1,
+        };
+        let bpp = (channels * bit_depth as usize).div_ceil(8);
+
+        let mut data = Vec::new();
+        for (y, (row, filter)) in rows.iter().enumerate() {
+            data.push(*filter);
+            for x in 0..row.len() {
+                let a = if x >= bpp { row[x - bpp] } else { 0 };
+                let b = if y > 0 { rows[y - 1].0[x] } else { 0 };
+                let c = if x >= bpp && y > 0 {
+                    rows[y - 1].0[x - bpp]
+                } else {
+                    0
+                };
+                let prediction = match filter {
+                    0 => 0,
+                    1 => a,
+                    2 => b,
+                    3 => ((a as u16 + b as u16) / 2) as u8,
+                    _ => paeth(a, b, c),
+                };
+                data.push(row[x].wrapping_sub(prediction));
+            }
+        }
+
+        let mut header = Vec::new();
+        header.extend(width.to_be_bytes());
+        header.extend((rows.len() as u32).to_be_bytes());
+        header.extend([bit_depth, color_type, 0, 0, 0]);
+
+        let mut png = SIGNATURE.to_vec();
+        chunk(&mut png, b"IHDR", &header);
+        if !palette.is_empty() {
+            chunk(&mut png, b"PLTE", palette);
+        }
+        chunk(&mut png, b"IDAT", &stored(&[&data]));
+        chunk(&mut png, b"IEND", &[]);
+        png
+    }
+
+    fn rgb_rows(filter: u8) -> Vec<(Vec<u8>, u8)> {
+        (0..3u8)
+            .map(|y| {
+                let row = (0..9u8).map(|i| i.wrapping_mul(37) ^ (y * 91)).collect();
+                (row, filter)
+            })
+            .collect()
+    }
+
+    fn assert_rgb(pixels: &[Color], rows: &[(Vec<u8>, u8)]) {
+        let expected: Vec<Color> = rows
+            .iter()
+            .flat_map(|(row, _)| row.chunks_exact(3))
+            .map(|rgb| {
+                Color::new(
+                    rgb[0] as f64 / 255.0,
+                    rgb[1] as f64 / 255.0,
+                    rgb[2] as f64 / 255.0,
+                )
+            })
+            .collect();
+        assert_eq!(pixels.len(), expected.len());
+        for (&pixel, &expected) in pixels.iter().zip(&expected) {
+            assert!(
+                (pixel - expected).length() < 1e-12,
+                "{pixel:?} != {expected:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn inflates_stored_blocks() {
+        let stream = stored(&[b"stored ", b"", b"blocks"]);
+        assert_eq!(inflate(&stream).unwrap(), b"stored blocks");
+    }
+
+    #[test]
+    fn inflates_fixed_huffman_blocks() {
+        // zlib.compressobj(strategy=zlib.Z_FIXED), with back references into the repeats.
+        let stream = hex("78014b4c4a4e44423a0a105a11006eab0878");
+        assert_eq!(inflate(&stream).unwrap(), b"abcabcabcabcabc, abcabc!");
+    }
+
+    #[test]
+    fn inflates_dynamic_huffman_blocks() {
+        // zlib.compress at level 9, which builds its own code for the skewed letters.
+        let stream =
+            hex("78da1d8a81090040108266356fff19fe0b42c88a0488c54f70c5d9e36addd0431ec0f51251");
+        assert_eq!(
+            inflate(&stream).unwrap(),
+            b"bcabaaabcaaabaabbacabcaacbaaabdadabaacaabbaaabab"
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_deflate_streams() {
+        let mut corrupt_length = stored(&[b"data"]);
+        corrupt_length[5] ^= 1;
+        let fixed = hex("78014b4c4a4e44423a0a105a11006eab0878");
+
+        for (name, stream) in [
+            ("empty", vec![]),
+            ("bad header", vec![0x78, 0x00, 0x01]),
+            ("reserved block type", vec![0x78, 0x01, 0x07]),
+            ("corrupt stored length", corrupt_length),
+            ("truncated stored block", stored(&[b"data"])[..8].to_vec()),
+            ("truncated fixed block", fixed[..8].to_vec()),
+            // A fixed block opening with a match of length 3 at distance 1.
+            ("distance before start", vec![0x78, 0x01, 0x03, 0x02, 0x00]),
+        ] {
+            assert!(inflate(&stream).is_err(), "{name}");
+        }
+    }
+
+    #[test]
+    fn unfilters_every_filter_type() {
+        for filter in 0..=4 {
+            let rows = rgb_rows(filter);
+            let (pixels, width, height) = decode_png(&encode(3, 8, 2, &[], &rows)).unwrap();
+            assert_eq!((width, height), (3, 3));
+            assert_rgb(&pixels, &rows);
+        }
+
+        // Filters may change from one row to the next.
+        let rows: Vec<_> = rgb_rows(0)
+            .into_iter()
+            .zip([4, 3, 1])
+            .map(|((row, _), filter)| (row, filter))
+            .collect();
+        assert_rgb(&decode_png(&encode(3, 8, 2, &[], &rows)).unwrap().0, &rows);
+    }
+
+    #[test]
+    fn decodes_palette_images() {
+        let palette = [255, 0, 0, 0, 255, 0, 0, 0, 255, 255, 255, 255];
+        // Five two-bit indices per row, packed from the high bits: 0 1 2 3 | 2.
+        let rows = vec![(vec![0b0001_1011, 0b1000_0000], 2), (vec![0xff, 0x40], 4)];
+        let (pixels, width, height) = decode_png(&encode(5, 2, 3, &palette, &rows)).unwrap();
+        assert_eq!((width, height), (5, 2));
+
+        let color = |index: usize| {
+            let rgb = &palette[index * 3..index * 3 + 3];
+            Color::new(rgb[0] as f64, rgb[1] as f64, rgb[2] as f64) / 255.0
+        };
+        let expected: Vec<Color> = [0, 1, 2, 3, 2, 3, 3, 3, 3, 1]
+            .into_iter()
+            .map(color)
+            .collect();
+        assert_eq!(pixels, expected);
+    }
+
+    #[test]
+    fn decodes_sixteen_bit_gray_with_alpha() {
+        let rows = vec![(vec![0x80, 0x00, 0xff, 0xff, 0xff, 0xff, 0x00, 0x00], 1)];
+        let (pixels, _, _) = decode_png(&encode(2, 16, 4, &[], &rows)).unwrap();
+
+        let gray = 0x8000 as f64 / 65535.0;
+        assert_eq!(
+            pixels,
+            vec![Color::new(gray, gray, gray), Color::new(1.0, 1.0, 1.0)]
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_pngs() {
+        let valid = encode(3, 8, 2, &[], &rgb_rows(1));
+        let with_header = |header: [u8; 13]| {
+            let mut png = SIGNATURE.to_vec();
+            chunk(&mut png, b"IHDR", &header);
+            chunk(&mut png, b"IDAT", &stored(&[&[0; 64]]));
+            png
+        };
+        let header = |width: u32, height: u32, bit_depth: u8, color_type: u8, interlace: u8| {
+            let mut header = [0; 13];
+            header[0..4].copy_from_slice(&width.to_be_bytes());
+            header[4..8].copy_from_slice(&height.to_be_bytes());
+            header[8] = bit_depth;
+            header[9] = color_type;
+            header[12] = interlace;
+            header
+        };
+        let mut bad_filter = valid.clone();
+        // The first filter type byte follows the zlib and stored block headers.
+        let idat = valid.windows(4).position(|kind| kind == b"IDAT").unwrap();
+        bad_filter[idat + 4 + 7] = 5;
+
+        for (name, png) in [
+            ("missing signature", valid[1..].to_vec()),
+            ("missing header", SIGNATURE.to_vec()),
+            ("truncated chunk", valid[..valid.len() - 20].to_vec()),
+            ("invalid filter type", bad_filter),
+            ("empty image", with_header(header(0, 3, 8, 2, 0))),
+            ("interlaced", with_header(header(1, 1, 8, 2, 1))),
+            ("unsupported bit depth", with_header(header(1, 1, 4, 2, 0))),
+            ("invalid color type", with_header(header(1, 1, 8, 5, 0))),
+            ("truncated image data", with_header(header(16, 16, 8, 6, 0))),
+            (
+                "oversized image",
+                with_header(header(u32::MAX, u32::MAX, 16, 6, 0)),
+            ),
+            (
+                "palette index out of range",
+                encode(1, 8, 3, &[0, 0, 0], &[(vec![1], 0)]),
+            ),
+        ] {
+            assert!(decode_png(&png).is_err(), "{name}");
+        }
+    }
+}
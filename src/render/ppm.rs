@@ -21,3 +21,90 @@ pub fn save_ppm(file_name: &str, image: &[Color], width: u32, height: u32) {
         .unwrap();
     }
 }
+
+fn invalid_data(message: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Loads a binary (P6) or ASCII (P3) PPM into un-gamma-corrected values in `[0, 1]`.
+pub fn load_ppm(file_name: &str) -> std::io::Result<(Vec<Color>, u32, u32)> {
+    let bytes = std::fs::read(file_name)?;
+
+    let mut position = 0;
+    let mut next_token = || -> std::io::Result<String> {
+        loop {
+            while position < bytes.len() && bytes[position].is_ascii_whitespace() {
+                position += 1;
+            }
+            if position < bytes.len() && bytes[position] == b'#' {
+                while position < bytes.len() && bytes[position] != b'\n' {
+                    position += 1;
+                }
+                continue;
+            }
+            break;
+        }
+
+        let start = position;
+        while position < bytes.len() && !bytes[position].is_ascii_whitespace() {
+            position += 1;
+        }
+        if start == position {
+            return Err(invalid_data("Unexpected end of PPM file"));
+        }
+
+        Ok(String::from_utf8_lossy(&bytes[start..position]).into_owned())
+    };
+
+    let magic = next_token()?;
+    let mut number = |name: &str| -> std::io::Result<u32> {
+        next_token()?
+            .parse()
+            .map_err(|_| invalid_data(&format!("Invalid PPM {}", name)))
+    };
+    let width = number("width")?;
+    let height = number("height")?;
+    let max_value = number("max value")?;
+    if max_value == 0 || max_value > 65535 {
+        return Err(invalid_data("Invalid PPM max value"));
+    }
+
+    let count = (width as usize)
+        .checked_mul(height as usize)
+        .and_then(|pixels| pixels.checked_mul(3))
+        .ok_or_else(|| invalid_data("PPM image too large"))?;
+    let values: Vec<u32> = match magic.as_str() {
+        "P3" => (0..count)
+            .map(|_| number("sample"))
+            .collect::<std::io::Result<_>>()?,
+        "P6" => {
+            let start = position + 1;
+            let size = if max_value < 256 { 1 } else { 2 };
+            let data = count
+                .checked_mul(size)
+                .and_then(|length| bytes.get(start..)?.get(..length))
+                .ok_or_else(|| invalid_data("Truncated PPM pixel data"))?;
+            if size == 1 {
+                data.iter().map(|&v| v as u32).collect()
+            } else {
+                data.chunks_exact(2)
+                    .map(|v| u16::from_be_bytes([v[0], v[1]]) as u32)
+                    .collect()
+            }
+        }
+        _ => return Err(invalid_data("Unsupported PPM format")),
+    };
+
+    let pixels = values
+        .chunks_exact(3)
+        .map(|rgb| {
+            Color::new(
+                rgb[0] as f64 / max_value as f64,
+                rgb[1] as f64 / max_value as f64,
+                rgb[2] as f64 / max_value as f64,
+            )
+        })
+        .collect();
+
+    Ok((pixels, width, height))
+}
@@ -1,3 +1,9 @@
+/// Source of the uniform random numbers that paths are built from. Path construction only
+/// sees this trait, so a Metropolis chain can hand out its own primary samples instead.
+pub trait Sampler {
+    fn next_f64(&mut self) -> f64;
+}
+
 pub struct XorShiftRandom {
     state: u32,
 }
@@ -15,8 +21,10 @@ impl XorShiftRandom {
         self.state = x;
         x
     }
+}
 
-    pub fn next_f64(&mut self) -> f64 {
-        self.next() as f64 / std::u32::MAX as f64
+impl Sampler for XorShiftRandom {
+    fn next_f64(&mut self) -> f64 {
+        self.next() as f64 / u32::MAX as f64
     }
 }
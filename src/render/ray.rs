@@ -1,12 +1,67 @@
-use super::vec3::Vec3;
+use super::{spectrum::Wavelengths, vec3::Vec3};
 
+/// Closest hit distance accepted along a ray, so rays spawned on a surface do not hit it again.
+pub const RAY_EPSILON: f64 = 1e-4;
+
+#[derive(Debug, Clone, Copy)]
 pub struct Ray {
     pub origin: Vec3,
     pub direction: Vec3,
+    /// Wavelengths of the path in spectral mode.
+    pub wavelengths: Option<Wavelengths>,
 }
 
 impl Ray {
     pub fn new(origin: Vec3, direction: Vec3) -> Ray {
-        Ray { origin, direction }
+        Ray {
+            origin,
+            direction,
+            wavelengths: None,
+        }
+    }
+
+    pub fn with_wavelengths(self, wavelengths: Option<Wavelengths>) -> Ray {
+        Ray {
+            wavelengths,
+            ..self
+        }
+    }
+
+    /// Next ray of the same path, carrying its wavelengths.
+    pub fn spawn(&self, origin: Vec3, direction: Vec3) -> Ray {
+        Ray::new(origin, direction).with_wavelengths(self.wavelengths)
+    }
+
+    /// Same ray starting `distance` further along.
+    pub fn advance(self, distance: f64) -> Ray {
+        self.spawn(self.origin + self.direction * distance, self.direction)
+    }
+}
+
+/// Cone traced alongside a ray for texture filtering: its width grows linearly with distance.
+#[derive(Debug, Clone, Copy)]
+pub struct RayCone {
+    pub width: f64,
+    pub spread: f64,
+}
+
+impl RayCone {
+    pub fn new(width: f64, spread: f64) -> RayCone {
+        RayCone { width, spread }
+    }
+
+    pub fn width_at(self, distance: f64) -> f64 {
+        self.width + self.spread * distance
+    }
+
+    /// Continues the cone from a hit at `distance`. Specular bounces keep the spread, other
+    /// lobes widen it by the angle subtending the solid angle `1 / pdf`.
+    pub fn scatter(self, distance: f64, pdf: Option<f64>) -> RayCone {
+        let spread = match pdf {
+            Some(pdf) => self.spread + (1.0 / (std::f64::consts::PI * pdf)).sqrt(),
+            None => self.spread,
+        };
+
+        RayCone::new(self.width_at(distance), spread)
     }
 }
@@ -0,0 +1,297 @@
+use std::io::{Error, ErrorKind, Write};
+
+use super::{
+    debug::heatmap, material::Color, ppm, scene::Scene, vec3::Vec3, Integrator, Render,
+    RenderConfig,
+};
+
+const WIDTH: u32 = 64;
+const HEIGHT: u32 = 48;
+const SAMPLES: u32 = 8;
+
+/// Tolerances a render must stay within of its reference. Renders are seeded per row, so the
+/// same code reproduces the reference up to the order light subpaths splat in.
+const MAX_RMSE: f64 = 1e-3;
+const MAX_REL_MSE: f64 = 1e-4;
+const MAX_PERCEPTUAL_ERROR: f64 = 1e-3;
+/// Keeps the relative error of dark pixels bounded.
+const REL_MSE_EPSILON: f64 = 1e-2;
+
+fn references() -> String {
+    format!("{}/tests/references", env!("CARGO_MANIFEST_DIR"))
+}
+
+fn failures() -> String {
+    format!("{}/target/regression", env!("CARGO_MANIFEST_DIR"))
+}
+
+/// Renders `preset` and compares it against the stored reference `name`, which is
+/// rewritten instead when `UPDATE_REFERENCES` is set.
+fn check(name: &str, preset: &str, integrator: Integrator, spectral: bool) {
+    let config = RenderConfig {
+        width: WIDTH,
+        height: HEIGHT,
+        tasks: HEIGHT,
+        samples: SAMPLES,
+        super_samples: 1,
+        spectral,
+        integrator,
+        max_depth: 64,
+        russian_roulette_depth: 5,
+        guiding: false,
+        merge_radius: 1.0,
+        merge_radius_alpha: 0.75,
+        ao_radius: 30.0,
+    };
+    let scene = Scene::preset(preset).expect("Failed to find scene");
+    let image = Render::new(config, scene).render();
+
+    let reference = format!("{}/{}.pfm", references(), name);
+    if std::env::var("UPDATE_REFERENCES").is_ok() {
+        save_pfm(&reference, &image, WIDTH, HEIGHT).expect("Failed to save reference");
+        return;
+    }
+
+    let (expected, width, height) = load_pfm(&reference).unwrap_or_else(|error| {
+        panic!("Failed to load {reference} ({error}), rerun with UPDATE_REFERENCES set")
+    });
+    assert_eq!(
+        (width, height),
+        (WIDTH, HEIGHT),
+        "{name}: resolution changed"
+    );
+
+    let rmse = rmse(&image, &expected);
+    let rel_mse = rel_mse(&image, &expected);
+    let errors = perceptual_errors(&image, &expected);
+    let perceptual_error = errors.iter().sum::<f64>() / errors.len() as f64;
+
+    if rmse > MAX_RMSE || rel_mse > MAX_REL_MSE || perceptual_error > MAX_PERCEPTUAL_ERROR {
+        let directory = failures();
+        std::fs::create_dir_all(&directory).expect("Failed to create output directory");
+        let diff: Vec<Color> = errors.iter().map(|&error| heatmap(error)).collect();
+        ppm::save_ppm(&format!("{directory}/{name}.ppm"), &image, WIDTH, HEIGHT);
+        ppm::save_ppm(
+            &format!("{directory}/{name}_reference.ppm"),
+            &expected,
+            WIDTH,
+            HEIGHT,
+        );
+        ppm::save_ppm(
+            &format!("{directory}/{name}_diff.ppm"),
+            &diff,
+            WIDTH,
+            HEIGHT,
+        );
+
+        panic!(
+            "{name} differs from its reference: RMSE {rmse:.3e}, relMSE {rel_mse:.3e}, \
+             perceptual error {perceptual_error:.3e}; images written to {directory}"
+        );
+    }
+}
+
+fn rmse(image: &[Color], reference: &[Color]) -> f64 {
+    let sum: f64 = image
+        .iter()
+        .zip(reference)
+        .map(|(&a, &b)| (a - b).squared_length())
+        .sum();
+    (sum / (3 * image.len()) as f64).sqrt()
+}
+
+fn rel_mse(image: &[Color], reference: &[Color]) -> f64 {
+    let sum: f64 = image
+        .iter()
+        .zip(reference)
+        .flat_map(|(&a, &b)| [(a.x, b.x), (a.y, b.y), (a.z, b.z)])
+        .map(|(a, b)| (a - b).powi(2) / (b * b + REL_MSE_EPSILON))
+        .sum();
+    sum / (3 * image.len()) as f64
+}
+
+/// Per-pixel error in `[0, 1]` following the color pipeline of FLIP (Andersson et al. 2020)
+/// in simplified form: displayed colors are blurred as the eye would, compared in L*a*b*
+/// with the HyAB distance and compressed, with black against white an error of one.
+fn perceptual_errors(image: &[Color], reference: &[Color]) -> Vec<f64> {
+    let image = blur(&image.iter().map(|&c| lab(c)).collect::<Vec<_>>());
+    let reference = blur(&reference.iter().map(|&c| lab(c)).collect::<Vec<_>>());
+
+    image
+        .iter()
+        .zip(&reference)
+        .map(|(&a, &b)| {
+            let hyab = (a.x - b.x).abs() + ((a.y - b.y).powi(2) + (a.z - b.z).powi(2)).sqrt();
+            (hyab / 100.0).min(1.0).powf(0.7)
+        })
+        .collect()
+}
+
+/// CIE L*a*b* of a linear sRGB color clamped to what a display shows.
+fn lab(color: Color) -> Vec3 {
+    let (r, g, b) = (
+        color.x.clamp(0.0, 1.0),
+        color.y.clamp(0.0, 1.0),
+        color.z.clamp(0.0, 1.0),
+    );
+    // Relative to the D65 white point.
+    let x = (0.4124 * r + 0.3576 * g + 0.1805 * b) / 0.9505;
+    let y = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+    let z = (0.0193 * r + 0.1192 * g + 0.9505 * b) / 1.089;
+
+    let f = |t: f64| {
+        if t > 0.008856 {
+            t.cbrt()
+        } else {
+            7.787 * t + 16.0 / 116.0
+        }
+    };
+    let (fx, fy, fz) = (f(x), f(y), f(z));
+    Vec3::new(116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz))
+}
+
+/// Binomial 3x3 filter standing in for the contrast sensitivity of the eye.
+fn blur(image: &[Vec3]) -> Vec<Vec3> {
+    let (width, height) = (WIDTH as i32, HEIGHT as i32);
+    let weights = [1.0, 2.0, 1.0];
+
+    (0..height)
+        .flat_map(|y| (0..width).map(move |x| (x, y)))
+        .map(|(x, y)| {
+            let mut sum = Vec3::new(0.0, 0.0, 0.0);
+            for (dy, wy) in (-1..=1).zip(weights) {
+                for (dx, wx) in (-1..=1).zip(weights) {
+                    let sx = (x + dx).clamp(0, width - 1);
+                    let sy = (y + dy).clamp(0, height - 1);
+                    sum = sum + image[(sy * width + sx) as usize] * (wx * wy / 16.0);
+                }
+            }
+            sum
+        })
+        .collect()
+}
+
+/// Writes `image` as a little-endian Portable Float Map, so references keep radiance exactly,
+/// including the negative components spectral rendering may produce.
+fn save_pfm(file_name: &str, image: &[Color], width: u32, height: u32) -> std::io::Result<()> {
+    let mut file = std::io::BufWriter::new(std::fs::File::create(file_name)?);
+    write!(file, "PF\n{} {}\n-1.0\n", width, height)?;
+
+    // Rows are stored from the bottom up.
+    for row in image.chunks_exact(width as usize).rev() {
+        for color in row {
+            for value in [color.x, color.y, color.z] {
+                file.write_all(&(value as f32).to_le_bytes())?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn load_pfm(file_name: &str) -> std::io::Result<(Vec<Color>, u32, u32)> {
+    let invalid_data = |message: &str| Error::new(ErrorKind::InvalidData, message.to_string());
+    let bytes = std::fs::read(file_name)?;
+
+    // The header is three lines: the format, the resolution and the scale.
+    let mut lines = bytes.splitn(4, |&byte| byte == b'\n');
+    let mut line = || {
+        lines
+            .next()
+            .map(|line| String::from_utf8_lossy(line).into_owned())
+            .ok_or_else(|| invalid_data("Unexpected end of PFM header"))
+    };
+    if line()?.trim() != "PF" {
+        return Err(invalid_data("Unsupported PFM format"));
+    }
+    let resolution = line()?;
+    let (width, height) = match resolution.split_whitespace().collect::<Vec<_>>().as_slice() {
+        [width, height] => (
+            width
+                .parse::<u32>()
+                .map_err(|_| invalid_data("Invalid PFM width"))?,
+            height
+                .parse::<u32>()
+                .map_err(|_| invalid_data("Invalid PFM height"))?,
+        ),
+        _ => return Err(invalid_data("Invalid PFM resolution")),
+    };
+    let little_endian = line()?
+        .trim()
+        .parse::<f64>()
+        .map_err(|_| invalid_data("Invalid PFM scale"))?
+        < 0.0;
+
+    let data = lines.next().unwrap_or_default();
+    if data.len() < (width * height * 12) as usize {
+        return Err(invalid_data("Truncated PFM pixel data"));
+    }
+    let values: Vec<f64> = data
+        .chunks_exact(4)
+        .map(|value| {
+            let value = [value[0], value[1], value[2], value[3]];
+            if little_endian {
+                f32::from_le_bytes(value) as f64
+            } else {
+                f32::from_be_bytes(value) as f64
+            }
+        })
+        .collect();
+
+    let image = values
+        .chunks_exact(3 * width as usize)
+        .take(height as usize)
+        .rev()
+        .flat_map(|row| {
+            row.chunks_exact(3)
+                .map(|rgb| Color::new(rgb[0], rgb[1], rgb[2]))
+        })
+        .collect();
+
+    Ok((image, width, height))
+}
+
+#[test]
+fn cornell_path() {
+    check("cornell_path", "cornell", Integrator::Path, false);
+}
+
+#[test]
+fn cornell_bidirectional() {
+    check("cornell_bdpt", "cornell", Integrator::Bidirectional, false);
+}
+
+#[test]
+fn frosted_path() {
+    check("frosted_path", "frosted", Integrator::Path, false);
+}
+
+#[test]
+fn foggy_path() {
+    check("foggy_path", "foggy", Integrator::Path, false);
+}
+
+#[test]
+fn dispersion_spectral() {
+    check("dispersion_spectral", "dispersion", Integrator::Path, true);
+}
+
+#[test]
+fn cornell_photon_mapping() {
+    check("cornell_sppm", "cornell", Integrator::PhotonMapping, false);
+}
+
+#[test]
+fn cornell_vertex_merging() {
+    check("cornell_vcm", "cornell", Integrator::VertexMerging, false);
+}
+
+#[test]
+fn foggy_vertex_merging() {
+    check("foggy_vcm", "foggy", Integrator::VertexMerging, false);
+}
+
+#[test]
+fn cornell_metropolis() {
+    check("cornell_mlt", "cornell", Integrator::Metropolis, false);
+}
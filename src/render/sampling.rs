@@ -0,0 +1,35 @@
+use std::f64::consts::PI;
+
+use super::vec3::Vec3;
+
+/// Maps the unit square onto the unit disk, keeping neighbouring samples close.
+pub fn concentric_disk(u0: f64, u1: f64) -> (f64, f64) {
+    let x = 2.0 * u0 - 1.0;
+    let y = 2.0 * u1 - 1.0;
+
+    if x == 0.0 && y == 0.0 {
+        return (0.0, 0.0);
+    }
+
+    let (r, theta) = if x.abs() > y.abs() {
+        (x, PI / 4.0 * (y / x))
+    } else {
+        (y, PI / 2.0 - PI / 4.0 * (x / y))
+    };
+
+    (r * theta.cos(), r * theta.sin())
+}
+
+pub fn uniform_sphere(u0: f64, u1: f64) -> Vec3 {
+    let z = 1.0 - 2.0 * u0;
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * PI * u1;
+
+    Vec3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+/// Direction about `+z` with density `cos(theta) / π`.
+pub fn cosine_hemisphere(u0: f64, u1: f64) -> Vec3 {
+    let (x, y) = concentric_disk(u0, u1);
+    Vec3::new(x, y, (1.0 - x * x - y * y).max(0.0).sqrt())
+}
@@ -1,101 +1,863 @@
 use super::{
+    distribution::Distribution1D,
+    environment::Environment,
     intersection::{HitPoint, Intersection},
-    material::RefrectionType,
-    ray::Ray,
+    light::{self, EmissionSample, IntensityProfile, Light},
+    material::{
+        Bump, Color, Conductor, Interface, Ior, Lambertian, Material, Metal, Mirror, Principled,
+        RoughDielectric, ShadingContext, IOR,
+    },
+    medium::{Medium, VoxelGrid},
+    random::Sampler,
+    ray::{Ray, RAY_EPSILON},
+    spectrum::{self, Spectrum, StandardIlluminant, Wavelengths},
     sphere::Sphere,
+    texture::{Filter, GradientKind, ImageTexture, MathOp, Node, Texture, WrapMode},
     vec3::Vec3,
 };
 
+use std::sync::Arc;
+
+const SHADOW_EPSILON: f64 = 1e-4;
+/// Spheres at least this large stand in for walls and ground planes.
+const PLANE_RADIUS: f64 = 1e4;
+
+fn cornell_walls(scene: &mut Scene) {
+    let walls = [
+        (
+            Vec3::new(1e5 + 1.0, 40.8, 81.6),
+            Color::new(0.75, 0.25, 0.25),
+        ),
+        (
+            Vec3::new(-1e5 + 99.0, 40.8, 81.6),
+            Color::new(0.25, 0.25, 0.75),
+        ),
+        (Vec3::new(50.0, 40.8, 1e5), Color::new(0.75, 0.75, 0.75)),
+        (
+            Vec3::new(50.0, 40.8, -1e5 + 250.0),
+            Color::new(0.0, 0.0, 0.0),
+        ),
+        (Vec3::new(50.0, 1e5, 81.6), Color::new(0.75, 0.75, 0.75)),
+        (
+            Vec3::new(50.0, -1e5 + 81.6, 81.6),
+            Color::new(0.75, 0.75, 0.75),
+        ),
+    ];
+
+    for (position, albedo) in walls {
+        let material = scene.add_material(Material::new(Lambertian::new(albedo)));
+        scene.add_sphere(Sphere::new(1e5, position, material));
+    }
+}
+
+fn cornell_light(scene: &mut Scene) {
+    let material = scene.add_material(Material::emissive(
+        Lambertian::new(Color::new(0.0, 0.0, 0.0)),
+        Color::new(36.0, 36.0, 36.0),
+    ));
+    scene.add_sphere(Sphere::new(15.0, Vec3::new(50.0, 90.0, 81.6), material));
+}
+
+/// Tangent-space normal map of a grid of hemispherical dimples, one per tile.
+fn dimple_normal_map(size: u32) -> ImageTexture {
+    let pixels = (0..size * size)
+        .map(|i| {
+            let x = ((i % size) as f64 + 0.5) / size as f64 * 2.0 - 1.0;
+            let y = ((i / size) as f64 + 0.5) / size as f64 * 2.0 - 1.0;
+            let r2 = x * x + y * y;
+            let normal = if r2 < 0.64 {
+                Vec3::new(x, y, (1.0 - r2).sqrt()).normalize()
+            } else {
+                Vec3::new(0.0, 0.0, 1.0)
+            };
+            normal * 0.5 + Vec3::new(0.5, 0.5, 0.5)
+        })
+        .collect();
+
+    ImageTexture::new(pixels, size, size, WrapMode::Repeat, Filter::Trilinear)
+}
+
+/// Billowy cloud of fractal noise inside a soft spherical falloff.
+fn procedural_cloud(resolution: usize) -> VoxelGrid {
+    let noise = Node::Fbm {
+        input: Arc::new(Node::Position),
+        octaves: 5,
+        lacunarity: 2.0,
+        gain: 0.5,
+    };
+
+    let mut values = Vec::with_capacity(resolution * resolution * resolution);
+    for z in 0..resolution {
+        for y in 0..resolution {
+            for x in 0..resolution {
+                let p = Vec3::new(x as f64, y as f64, z as f64) / resolution as f64 * 2.0
+                    - Vec3::new(1.0, 1.0, 1.0);
+                let context = ShadingContext::new(p * 3.0, (0.0, 0.0), 0.0);
+                let falloff = 1.0 - p.length() * 1.2;
+                let density = falloff + 0.6 * noise.evaluate(&context).x;
+                values.push((density * 3.0).clamp(0.0, 1.0));
+            }
+        }
+    }
+
+    VoxelGrid::new(resolution, resolution, resolution, values)
+}
+
+/// What a ray runs into first, once it has walked through interface surfaces and sampled the
+/// media on its way.
+pub enum Event {
+    Surface(Intersection),
+    /// Area light at a distance along the final segment.
+    Light(usize, f64),
+    /// Scattering in a medium at a distance along the final segment.
+    Medium(u32, f64),
+    Escape,
+}
+
+/// Source of light: one of the scene lights, or the environment.
+#[derive(Debug, Clone, Copy)]
+pub enum Emitter {
+    Light(usize),
+    Environment,
+}
+
+/// Light leaving an emitter, picked in proportion to its power, that starts a light subpath.
+pub struct EmitterSample {
+    pub emitter: Emitter,
+    pub selection_pdf: f64,
+    pub emission: EmissionSample,
+    /// Emitted radiance times the cosine at the emitter over the density of the ray.
+    pub beta: Color,
+}
+
+pub struct Trace {
+    pub event: Event,
+    /// Last segment of the ray, starting past the interfaces crossed on the way.
+    pub segment: Ray,
+    /// Distance along the ray to the start of `segment`.
+    pub offset: f64,
+    /// Medium `segment` travels through.
+    pub medium: Option<u32>,
+    /// Free-flight sampling weight of the media crossed.
+    pub weight: Color,
+}
+
 pub struct Scene {
     spheres: Vec<Sphere>,
+    materials: Vec<Material>,
+    media: Vec<Medium>,
+    global_medium: Option<u32>,
+    lights: Vec<Light>,
+    light_distribution: Option<Distribution1D>,
+    environment: Option<Environment>,
 }
 
 impl Scene {
+    pub fn empty() -> Scene {
+        Scene {
+            spheres: Vec::new(),
+            materials: Vec::new(),
+            media: Vec::new(),
+            global_medium: None,
+            lights: Vec::new(),
+            light_distribution: None,
+            environment: None,
+        }
+    }
+
     pub fn new() -> Scene {
-        let spheres = [
-            Sphere::new(
-                1e5,
-                Vec3::new(1e5 + 1.0, 40.8, 81.6),
-                Vec3::new(0.0, 0.0, 0.0),
-                Vec3::new(0.75, 0.25, 0.25),
-                RefrectionType::Diffuse,
+        let mut scene = Scene::empty();
+        cornell_walls(&mut scene);
+
+        let green =
+            scene.add_material(Material::new(Lambertian::new(Color::new(0.25, 0.75, 0.25))));
+        let mirror = scene.add_material(Material::new(Mirror::new(Color::new(0.99, 0.99, 0.99))));
+        let glass = scene.add_material(Material::new(
+            RoughDielectric::new(IOR, 0.0).with_tint(Color::new(0.99, 0.99, 0.99)),
+        ));
+
+        scene.add_sphere(Sphere::new(20.0, Vec3::new(65.0, 20.0, 20.0), green));
+        scene.add_sphere(Sphere::new(16.5, Vec3::new(27.0, 16.5, 47.0), mirror));
+        scene.add_sphere(Sphere::new(16.5, Vec3::new(77.0, 16.5, 78.0), glass));
+        cornell_light(&mut scene);
+
+        scene
+    }
+
+    pub fn metals() -> Scene {
+        let metals = [
+            (Metal::Gold, 0.3, 0.3),
+            (Metal::Copper, 0.2, 0.2),
+            (Metal::Aluminum, 0.6, 0.1),
+            (Metal::Silver, 0.05, 0.05),
+            (Metal::Chrome, 0.0, 0.0),
+        ];
+
+        let mut scene = Scene::empty();
+        cornell_walls(&mut scene);
+
+        for (i, (metal, roughness_u, roughness_v)) in metals.into_iter().enumerate() {
+            let material = scene.add_material(Material::new(Conductor::from_metal(
+                metal,
+                roughness_u,
+                roughness_v,
+            )));
+            scene.add_sphere(Sphere::new(
+                9.0,
+                Vec3::new(14.0 + i as f64 * 18.0, 9.0, 60.0 + (i % 2) as f64 * 20.0),
+                material,
+            ));
+        }
+        cornell_light(&mut scene);
+
+        scene
+    }
+
+    pub fn frosted() -> Scene {
+        let mut scene = Scene::empty();
+        cornell_walls(&mut scene);
+
+        let frosted = scene.add_material(Material::new(RoughDielectric::new(1.5, 0.3)));
+        let satin = scene.add_material(Material::new(RoughDielectric::new(1.49, 0.12)));
+        let clear = scene.add_material(Material::new(RoughDielectric::new(1.5, 0.0)));
+
+        scene.add_sphere(Sphere::new(16.5, Vec3::new(27.0, 16.5, 60.0), frosted));
+        scene.add_sphere(Sphere::new(16.5, Vec3::new(73.0, 16.5, 78.0), satin));
+        scene.add_sphere(Sphere::new(10.0, Vec3::new(50.0, 10.0, 30.0), clear));
+        cornell_light(&mut scene);
+
+        scene
+    }
+
+    pub fn principled() -> Scene {
+        let materials = [
+            Principled {
+                base_color: Vec3::new(0.8, 0.1, 0.1).into(),
+                roughness: 0.4.into(),
+                ..Default::default()
+            },
+            Principled {
+                base_color: Vec3::new(0.9, 0.6, 0.3).into(),
+                metallic: 1.0.into(),
+                roughness: 0.25.into(),
+                ..Default::default()
+            },
+            Principled {
+                base_color: Vec3::new(0.05, 0.2, 0.6).into(),
+                metallic: 0.5.into(),
+                roughness: 0.5.into(),
+                clearcoat: 1.0,
+                clearcoat_gloss: 0.9,
+                ..Default::default()
+            },
+            Principled {
+                base_color: Vec3::new(0.3, 0.2, 0.5).into(),
+                roughness: 0.9.into(),
+                sheen: 1.0,
+                sheen_tint: 0.8,
+                ..Default::default()
+            },
+            Principled {
+                base_color: Vec3::new(0.9, 1.0, 0.95).into(),
+                roughness: 0.1.into(),
+                transmission: 1.0,
+                ..Default::default()
+            },
+            Principled {
+                base_color: Vec3::new(0.9, 0.8, 0.6).into(),
+                roughness: 0.6.into(),
+                subsurface: 1.0,
+                specular_tint: 0.5,
+                ..Default::default()
+            },
+        ];
+
+        let mut scene = Scene::empty();
+        cornell_walls(&mut scene);
+
+        for (i, principled) in materials.into_iter().enumerate() {
+            let material = scene.add_material(Material::new(principled));
+            scene.add_sphere(Sphere::new(
+                8.0,
+                Vec3::new(12.0 + i as f64 * 15.0, 8.0, 60.0 + (i % 2) as f64 * 20.0),
+                material,
+            ));
+        }
+        cornell_light(&mut scene);
+
+        scene
+    }
+
+    pub fn textured(albedo: Texture) -> Scene {
+        let mut scene = Scene::empty();
+        cornell_walls(&mut scene);
+
+        let checker = ImageTexture::checkerboard(
+            64,
+            8,
+            Color::new(0.8, 0.8, 0.8),
+            Color::new(0.1, 0.1, 0.1),
+            WrapMode::Repeat,
+            Filter::Trilinear,
+        )
+        .with_scale(8.0, 4.0);
+        let stripes = ImageTexture::gradient(
+            32,
+            Color::new(0.1, 0.2, 0.8),
+            Color::new(0.9, 0.8, 0.1),
+            WrapMode::Mirror,
+            Filter::Bilinear,
+        )
+        .with_scale(6.0, 1.0);
+        let roughness = ImageTexture::gradient(
+            32,
+            Color::new(0.05, 0.05, 0.05),
+            Color::new(0.6, 0.6, 0.6),
+            WrapMode::Clamp,
+            Filter::Trilinear,
+        )
+        .with_scale(2.0, 1.0);
+        let metallic = ImageTexture::checkerboard(
+            32,
+            4,
+            Color::new(1.0, 1.0, 1.0),
+            Color::new(0.0, 0.0, 0.0),
+            WrapMode::Repeat,
+            Filter::Trilinear,
+        )
+        .with_scale(4.0, 2.0);
+        let emission = ImageTexture::checkerboard(
+            16,
+            2,
+            Color::new(12.0, 9.0, 4.0),
+            Color::new(0.0, 0.0, 0.0),
+            WrapMode::Repeat,
+            Filter::Bilinear,
+        )
+        .with_scale(6.0, 3.0);
+
+        let albedo = scene.add_material(Material::new(Lambertian::new(albedo)));
+        let checker = scene.add_material(Material::new(Lambertian::new(checker)));
+        let stripes = scene.add_material(Material::new(Lambertian::new(stripes)));
+        let principled = scene.add_material(Material::new(Principled {
+            base_color: Color::new(0.9, 0.6, 0.3).into(),
+            metallic: metallic.into(),
+            roughness: roughness.into(),
+            ..Default::default()
+        }));
+        let lantern = scene.add_material(Material::emissive(
+            Lambertian::new(Color::new(0.2, 0.2, 0.2)),
+            emission,
+        ));
+
+        scene.add_sphere(Sphere::new(16.5, Vec3::new(27.0, 16.5, 47.0), albedo));
+        scene.add_sphere(Sphere::new(14.0, Vec3::new(73.0, 14.0, 78.0), checker));
+        scene.add_sphere(Sphere::new(10.0, Vec3::new(50.0, 10.0, 100.0), stripes));
+        scene.add_sphere(Sphere::new(10.0, Vec3::new(80.0, 45.0, 40.0), principled));
+        scene.add_sphere(Sphere::new(5.0, Vec3::new(20.0, 55.0, 70.0), lantern));
+        cornell_light(&mut scene);
+
+        scene
+    }
+
+    pub fn procedural() -> Scene {
+        let mut scene = Scene::empty();
+        cornell_walls(&mut scene);
+
+        let position = Arc::new(Node::Position);
+        let scaled =
+            |scale: f64| Node::math(MathOp::Multiply, position.clone(), Node::constant(scale));
+
+        let turbulence = Arc::new(Node::Turbulence {
+            input: scaled(0.08),
+            octaves: 5,
+            lacunarity: 2.0,
+            gain: 0.5,
+        });
+        let veins = Node::math(
+            MathOp::Abs,
+            Node::math(
+                MathOp::Sine,
+                Node::math(
+                    MathOp::Add,
+                    Arc::new(Node::Gradient {
+                        input: scaled(0.3),
+                        kind: GradientKind::Linear,
+                    }),
+                    Node::math(MathOp::Multiply, turbulence, Node::constant(6.0)),
+                ),
+                Node::constant(0.0),
             ),
-            Sphere::new(
-                1e5,
-                Vec3::new(-1e5 + 99.0, 40.8, 81.6),
-                Vec3::new(0.0, 0.0, 0.0),
-                Vec3::new(0.25, 0.25, 0.75),
-                RefrectionType::Diffuse,
+            Node::constant(0.0),
+        );
+        let marble = Node::mix(
+            Node::color(Color::new(0.05, 0.05, 0.08)),
+            Node::color(Color::new(0.9, 0.88, 0.85)),
+            Node::math(MathOp::Power, veins, Node::constant(0.5)),
+        );
+
+        let grain = Arc::new(Node::Fbm {
+            input: scaled(0.1),
+            octaves: 4,
+            lacunarity: 2.0,
+            gain: 0.5,
+        });
+        let rings = Node::math(
+            MathOp::Fract,
+            Node::math(
+                MathOp::Multiply,
+                Node::math(
+                    MathOp::Add,
+                    Arc::new(Node::Gradient {
+                        input: position.clone(),
+                        kind: GradientKind::Radial,
+                    }),
+                    Node::math(MathOp::Multiply, grain, Node::constant(2.0)),
+                ),
+                Node::constant(0.15),
             ),
-            Sphere::new(
-                1e5,
-                Vec3::new(50.0, 40.8, 1e5),
-                Vec3::new(0.0, 0.0, 0.0),
-                Vec3::new(0.75, 0.75, 0.75),
-                RefrectionType::Diffuse,
+            Node::constant(0.0),
+        );
+        let wood = Node::mix(
+            Node::color(Color::new(0.6, 0.4, 0.2)),
+            Node::color(Color::new(0.3, 0.15, 0.05)),
+            Node::math(MathOp::Power, rings, Node::constant(3.0)),
+        );
+        let wood_roughness = Node::mix(
+            Node::constant(0.25),
+            Node::constant(0.6),
+            Node::math(
+                MathOp::Abs,
+                Arc::new(Node::Noise { input: scaled(0.5) }),
+                Node::constant(0.0),
             ),
-            Sphere::new(
-                1e5,
-                Vec3::new(50.0, 40.8, -1e5 + 250.0),
-                Vec3::new(0.0, 0.0, 0.0),
-                Vec3::new(0.0, 0.0, 0.0),
-                RefrectionType::Diffuse,
+        );
+
+        let uv = Arc::new(Node::Uv);
+        let spot = Arc::new(Node::Gradient {
+            input: Node::math(
+                MathOp::Multiply,
+                Node::math(
+                    MathOp::Subtract,
+                    Arc::new(Node::Uv),
+                    Node::color(Color::new(0.5, 0.5, 0.0)),
+                ),
+                Node::constant(2.0),
             ),
-            Sphere::new(
-                1e5,
-                Vec3::new(50.0, 1e5, 81.6),
-                Vec3::new(0.0, 0.0, 0.0),
-                Vec3::new(0.75, 0.75, 0.75),
-                RefrectionType::Diffuse,
+            kind: GradientKind::Spherical,
+        });
+        let pattern = Arc::new(Node::Checkerboard {
+            input: Node::math(
+                MathOp::Multiply,
+                uv,
+                Node::color(Color::new(16.0, 8.0, 0.0)),
             ),
-            Sphere::new(
-                1e5,
-                Vec3::new(50.0, -1e5 + 81.6, 81.6),
-                Vec3::new(0.0, 0.0, 0.0),
-                Vec3::new(0.75, 0.75, 0.75),
-                RefrectionType::Diffuse,
+            a: Node::mix(
+                Node::color(Color::new(0.8, 0.1, 0.1)),
+                Node::color(Color::new(0.9, 0.8, 0.1)),
+                spot,
             ),
-            Sphere::new(
-                20.0,
-                Vec3::new(65.0, 20.0, 20.0),
-                Vec3::new(0.0, 0.0, 0.0),
-                Vec3::new(0.25, 0.75, 0.25),
-                RefrectionType::Diffuse,
+            b: Node::constant(0.8),
+        });
+
+        let cells = Node::math(
+            MathOp::Power,
+            Node::math(
+                MathOp::Subtract,
+                Node::constant(1.0),
+                Arc::new(Node::Voronoi { input: scaled(0.4) }),
             ),
-            Sphere::new(
+            Node::constant(4.0),
+        );
+        let cells = Node::mix(
+            Node::color(Color::new(0.05, 0.1, 0.3)),
+            Node::color(Color::new(0.2, 0.8, 0.9)),
+            cells,
+        );
+
+        let marble = scene.add_material(Material::new(Lambertian::new(marble)));
+        let wood = scene.add_material(Material::new(Principled {
+            base_color: wood.into(),
+            roughness: wood_roughness.into(),
+            clearcoat: 0.5,
+            ..Default::default()
+        }));
+        let pattern = scene.add_material(Material::new(Lambertian::new(pattern)));
+        let cells = scene.add_material(Material::new(Lambertian::new(cells)));
+
+        scene.add_sphere(Sphere::new(16.5, Vec3::new(27.0, 16.5, 47.0), marble));
+        scene.add_sphere(Sphere::new(16.5, Vec3::new(73.0, 16.5, 78.0), wood));
+        scene.add_sphere(Sphere::new(10.0, Vec3::new(45.0, 10.0, 100.0), pattern));
+        scene.add_sphere(Sphere::new(10.0, Vec3::new(80.0, 45.0, 40.0), cells));
+        cornell_light(&mut scene);
+
+        scene
+    }
+
+    pub fn bumpy() -> Scene {
+        let mut scene = Scene::empty();
+        cornell_walls(&mut scene);
+
+        let position = Arc::new(Node::Position);
+        let scaled =
+            |scale: f64| Node::math(MathOp::Multiply, position.clone(), Node::constant(scale));
+
+        let hammered = scene.add_material(
+            Material::new(Conductor::from_metal(Metal::Gold, 0.15, 0.15)).with_bump(Bump::Height {
+                texture: Arc::new(Node::Voronoi { input: scaled(0.5) }).into(),
+                scale: 1.5,
+            }),
+        );
+        let dimpled = scene.add_material(
+            Material::new(Lambertian::new(Color::new(0.8, 0.8, 0.8))).with_bump(Bump::NormalMap(
+                dimple_normal_map(128).with_scale(8.0, 4.0).into(),
+            )),
+        );
+        let bumpy = scene.add_material(
+            Material::new(Principled {
+                base_color: Color::new(0.2, 0.4, 0.8).into(),
+                roughness: 0.3.into(),
+                clearcoat: 1.0,
+                ..Default::default()
+            })
+            .with_bump(Bump::Height {
+                texture: Arc::new(Node::Fbm {
+                    input: scaled(0.3),
+                    octaves: 4,
+                    lacunarity: 2.0,
+                    gain: 0.5,
+                })
+                .into(),
+                scale: 1.0,
+            }),
+        );
+        let rippled = scene.add_material(Material::new(RoughDielectric::new(IOR, 0.0)).with_bump(
+            Bump::Height {
+                texture: Arc::new(Node::Noise { input: scaled(0.4) }).into(),
+                scale: 1.0,
+            },
+        ));
+
+        scene.add_sphere(Sphere::new(16.5, Vec3::new(27.0, 16.5, 47.0), hammered));
+        scene.add_sphere(Sphere::new(16.5, Vec3::new(73.0, 16.5, 78.0), rippled));
+        scene.add_sphere(Sphere::new(10.0, Vec3::new(45.0, 10.0, 100.0), dimpled));
+        scene.add_sphere(Sphere::new(10.0, Vec3::new(80.0, 45.0, 40.0), bumpy));
+        cornell_light(&mut scene);
+
+        scene
+    }
+
+    pub fn foggy() -> Scene {
+        let mut scene = Scene::empty();
+        cornell_walls(&mut scene);
+
+        let fog = scene.add_medium(Medium::new(
+            Color::new(0.0005, 0.0005, 0.0005),
+            Color::new(0.004, 0.004, 0.004),
+            0.3,
+        ));
+        scene.set_global_medium(fog);
+
+        let murky = scene.add_medium(Medium::new(
+            Color::new(0.02, 0.008, 0.003),
+            Color::new(0.03, 0.03, 0.03),
+            0.5,
+        ));
+        let smoke = scene.add_medium(Medium::new(
+            Color::new(0.01, 0.01, 0.01),
+            Color::new(0.15, 0.15, 0.15),
+            -0.2,
+        ));
+
+        let murky_glass =
+            scene.add_material(Material::new(RoughDielectric::new(1.33, 0.0)).with_interior(murky));
+        let smoke = scene.add_material(Material::new(Interface).with_interior(smoke));
+        let white =
+            scene.add_material(Material::new(Lambertian::new(Color::new(0.75, 0.75, 0.75))));
+
+        scene.add_sphere(Sphere::new(16.5, Vec3::new(27.0, 16.5, 47.0), murky_glass));
+        scene.add_sphere(Sphere::new(16.5, Vec3::new(73.0, 16.5, 78.0), smoke));
+        scene.add_sphere(Sphere::new(6.0, Vec3::new(50.0, 50.0, 70.0), white));
+        cornell_light(&mut scene);
+
+        scene.add_light(Light::Spot {
+            position: Vec3::new(50.0, 72.0, 70.0),
+            direction: Vec3::new(0.0, -1.0, 0.0),
+            intensity: Vec3::new(6000.0, 5500.0, 4500.0).into(),
+            cone_angle: 40.0f64.to_radians(),
+            falloff_start: 30.0f64.to_radians(),
+            profile: None,
+        });
+
+        scene
+    }
+
+    pub fn subsurface() -> Scene {
+        let mut scene = Scene::empty();
+        cornell_walls(&mut scene);
+
+        let spheres = [
+            (
                 16.5,
                 Vec3::new(27.0, 16.5, 47.0),
-                Vec3::new(0.0, 0.0, 0.0),
-                Vec3::new(0.99, 0.99, 0.99),
-                RefrectionType::Specular,
+                Color::new(0.83, 0.79, 0.75),
+                Color::new(10.0, 8.0, 6.0),
             ),
-            Sphere::new(
+            (
                 16.5,
-                Vec3::new(77.0, 16.5, 78.0),
-                Vec3::new(0.0, 0.0, 0.0),
-                Vec3::new(0.99, 0.99, 0.99),
-                RefrectionType::Refraction,
+                Vec3::new(73.0, 16.5, 78.0),
+                Color::new(0.9, 0.6, 0.45),
+                Color::new(9.0, 5.0, 3.5),
             ),
-            Sphere::new(
-                15.0,
-                Vec3::new(50.0, 90.0, 81.6),
-                Vec3::new(36.0, 36.0, 36.0),
-                Vec3::new(0.0, 0.0, 0.0),
-                RefrectionType::Diffuse,
+            (
+                10.0,
+                Vec3::new(40.0, 10.0, 100.0),
+                Color::new(0.95, 0.95, 0.93),
+                Color::new(4.0, 4.0, 4.0),
             ),
         ];
 
-        Scene {
-            spheres: spheres.to_vec(),
+        for (radius, position, albedo, mean_free_path) in spheres {
+            let medium = scene.add_medium(Medium::subsurface(albedo, mean_free_path, 0.0));
+            let material = scene
+                .add_material(Material::new(RoughDielectric::new(1.4, 0.3)).with_interior(medium));
+            scene.add_sphere(Sphere::new(radius, position, material));
         }
+        cornell_light(&mut scene);
+
+        scene
+    }
+
+    pub fn volume(voxels: VoxelGrid) -> Scene {
+        let mut scene = Scene::empty();
+        cornell_walls(&mut scene);
+
+        let center = Vec3::new(50.0, 38.0, 75.0);
+        let half_size = Vec3::new(18.0, 18.0, 18.0);
+        let cloud = scene.add_medium(Medium::grid(
+            voxels,
+            center - half_size,
+            center + half_size,
+            Color::new(0.005, 0.005, 0.005),
+            Color::new(0.3, 0.3, 0.3),
+            0.5,
+        ));
+
+        let bounds = scene.add_material(Material::new(Interface).with_interior(cloud));
+        scene.add_sphere(Sphere::new(half_size.length(), center, bounds));
+        cornell_light(&mut scene);
+
+        scene
+    }
+
+    pub fn outdoor() -> Scene {
+        let mut scene = Scene::empty();
+
+        let ground = scene.add_material(Material::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))));
+        let mirror = scene.add_material(Material::new(Mirror::new(Color::new(0.99, 0.99, 0.99))));
+        let glass = scene.add_material(Material::new(RoughDielectric::new(IOR, 0.0)));
+        let red = scene.add_material(Material::new(Lambertian::new(Color::new(0.75, 0.25, 0.25))));
+
+        scene.add_sphere(Sphere::new(1e5, Vec3::new(50.0, -1e5, 81.6), ground));
+        scene.add_sphere(Sphere::new(16.5, Vec3::new(20.0, 16.5, 60.0), mirror));
+        scene.add_sphere(Sphere::new(16.5, Vec3::new(50.0, 16.5, 90.0), glass));
+        scene.add_sphere(Sphere::new(16.5, Vec3::new(80.0, 16.5, 60.0), red));
+
+        scene
+    }
+
+    pub fn studio() -> Scene {
+        let mut scene = Scene::empty();
+
+        let ground =
+            scene.add_material(Material::new(Lambertian::new(Color::new(0.75, 0.75, 0.75))));
+        let mirror = scene.add_material(Material::new(Mirror::new(Color::new(0.99, 0.99, 0.99))));
+        let green =
+            scene.add_material(Material::new(Lambertian::new(Color::new(0.25, 0.75, 0.25))));
+
+        scene.add_sphere(Sphere::new(1e5, Vec3::new(50.0, -1e5, 81.6), ground));
+        scene.add_sphere(Sphere::new(16.5, Vec3::new(27.0, 16.5, 60.0), mirror));
+        scene.add_sphere(Sphere::new(16.5, Vec3::new(73.0, 16.5, 78.0), green));
+
+        scene.add_light(Light::Quad {
+            corner: Vec3::new(35.0, 80.0, 65.0),
+            edge_u: Vec3::new(30.0, 0.0, 0.0),
+            edge_v: Vec3::new(0.0, 0.0, 30.0),
+            emission: Vec3::new(4.0, 4.0, 4.0).into(),
+        });
+        scene.add_light(Light::Disk {
+            center: Vec3::new(0.0, 40.0, 100.0),
+            normal: Vec3::new(1.0, -0.3, 0.0).normalize(),
+            radius: 6.0,
+            emission: Vec3::new(6.0, 4.0, 2.0).into(),
+        });
+        scene.add_light(Light::Spot {
+            position: Vec3::new(90.0, 70.0, 140.0),
+            direction: Vec3::new(-17.0, -53.5, -62.0).normalize(),
+            intensity: Vec3::new(3000.0, 3000.0, 4000.0).into(),
+            cone_angle: 15.0f64.to_radians(),
+            falloff_start: 10.0f64.to_radians(),
+            profile: None,
+        });
+        scene.add_light(Light::Point {
+            position: Vec3::new(50.0, 60.0, 40.0),
+            intensity: Vec3::new(800.0, 700.0, 600.0).into(),
+            profile: Some(IntensityProfile::new(
+                Vec3::new(0.0, -1.0, 0.0),
+                vec![1.0, 0.95, 0.8, 0.5, 0.2, 0.05, 0.0],
+            )),
+        });
+        scene.add_light(Light::Directional {
+            direction: Vec3::new(-0.3, -1.0, -0.2).normalize(),
+            irradiance: Vec3::new(0.05, 0.05, 0.08).into(),
+        });
+
+        scene
+    }
+
+    /// White spheres under lamps of different color temperatures, best rendered spectrally.
+    pub fn lamps() -> Scene {
+        let mut scene = Scene::empty();
+
+        let white =
+            scene.add_material(Material::new(Lambertian::new(Color::new(0.75, 0.75, 0.75))));
+        let glass = scene.add_material(Material::new(RoughDielectric::new(IOR, 0.0)));
+
+        scene.add_sphere(Sphere::new(1e5, Vec3::new(50.0, -1e5, 81.6), white));
+        scene.add_sphere(Sphere::new(1e5, Vec3::new(50.0, 40.8, -1e5), white));
+        scene.add_sphere(Sphere::new(14.0, Vec3::new(22.0, 14.0, 60.0), white));
+        scene.add_sphere(Sphere::new(14.0, Vec3::new(50.0, 14.0, 80.0), glass));
+        scene.add_sphere(Sphere::new(14.0, Vec3::new(78.0, 14.0, 60.0), white));
+
+        let lamps = [
+            Spectrum::blackbody(1900.0, 6.0),
+            Spectrum::illuminant(StandardIlluminant::A, 6.0),
+            Spectrum::illuminant(StandardIlluminant::E, 6.0),
+            Spectrum::illuminant(StandardIlluminant::D65, 6.0),
+        ];
+        for (i, emission) in lamps.into_iter().enumerate() {
+            scene.add_light(Light::Quad {
+                corner: Vec3::new(4.0 + 24.0 * i as f64, 70.0, 50.0),
+                edge_u: Vec3::new(20.0, 0.0, 0.0),
+                edge_v: Vec3::new(0.0, 0.0, 20.0),
+                emission,
+            });
+        }
+        scene.add_light(Light::Spot {
+            position: Vec3::new(50.0, 60.0, 140.0),
+            direction: Vec3::new(0.0, -46.0, -60.0).normalize(),
+            intensity: Spectrum::blackbody(9000.0, 2000.0),
+            cone_angle: 12.0f64.to_radians(),
+            falloff_start: 8.0f64.to_radians(),
+            profile: None,
+        });
+
+        scene
+    }
+
+    /// Dispersive glass and diamond under a small, bright lamp that casts colored caustics.
+    pub fn dispersion() -> Scene {
+        let mut scene = Scene::empty();
+
+        let white =
+            scene.add_material(Material::new(Lambertian::new(Color::new(0.75, 0.75, 0.75))));
+        let glasses = [Ior::FUSED_SILICA, Ior::BK7, Ior::FLINT, Ior::DIAMOND]
+            .map(|ior| scene.add_material(Material::new(RoughDielectric::new(ior, 0.0))));
+
+        scene.add_sphere(Sphere::new(1e5, Vec3::new(50.0, -1e5, 81.6), white));
+        scene.add_sphere(Sphere::new(1e5, Vec3::new(50.0, 40.8, -1e5), white));
+        for (i, glass) in glasses.into_iter().enumerate() {
+            let x = 14.0 + 24.0 * i as f64;
+            scene.add_sphere(Sphere::new(10.0, Vec3::new(x, 10.0, 70.0), glass));
+        }
+
+        scene.add_light(Light::Quad {
+            corner: Vec3::new(47.0, 90.0, 67.0),
+            edge_u: Vec3::new(6.0, 0.0, 0.0),
+            edge_v: Vec3::new(0.0, 0.0, 6.0),
+            emission: Spectrum::illuminant(StandardIlluminant::E, 300.0),
+        });
+
+        scene
+    }
+
+    pub fn preset(name: &str) -> Option<Scene> {
+        match name {
+            "cornell" => Some(Scene::new()),
+            "outdoor" => Some(Scene::outdoor()),
+            "studio" => Some(Scene::studio()),
+            "lamps" => Some(Scene::lamps()),
+            "dispersion" => Some(Scene::dispersion()),
+            "metals" => Some(Scene::metals()),
+            "frosted" => Some(Scene::frosted()),
+            "principled" => Some(Scene::principled()),
+            "procedural" => Some(Scene::procedural()),
+            "bumpy" => Some(Scene::bumpy()),
+            "foggy" => Some(Scene::foggy()),
+            "subsurface" => Some(Scene::subsurface()),
+            "cloud" => Some(Scene::volume(procedural_cloud(64))),
+            "textured" => Some(Scene::textured(
+                ImageTexture::checkerboard(
+                    256,
+                    16,
+                    Color::new(0.75, 0.25, 0.25),
+                    Color::new(0.9, 0.9, 0.9),
+                    WrapMode::Repeat,
+                    Filter::Trilinear,
+                )
+                .into(),
+            )),
+            _ => None,
+        }
+    }
+
+    pub fn add_material(&mut self, material: Material) -> u32 {
+        self.materials.push(material);
+        (self.materials.len() - 1) as u32
+    }
+
+    pub fn add_sphere(&mut self, sphere: Sphere) -> u32 {
+        let object_id = self.spheres.len() as u32;
+        let emissive = !self.material(sphere.material_id).emission.is_black();
+        self.spheres.push(sphere);
+
+        if emissive {
+            self.add_light(Light::Sphere { object_id });
+        } else {
+            self.update_light_distribution();
+        }
+
+        object_id
+    }
+
+    pub fn add_light(&mut self, light: Light) {
+        self.lights.push(light);
+        self.update_light_distribution();
+    }
+
+    /// Rebuilds the distribution lights are picked from. The power of directional lights
+    /// depends on the bounding sphere, so this runs whenever a sphere is added too.
+    fn update_light_distribution(&mut self) {
+        if self.lights.is_empty() {
+            return;
+        }
+
+        let power: Vec<f64> = self.lights.iter().map(|light| light.power(self)).collect();
+        self.light_distribution = Some(Distribution1D::new(&power));
     }
 
     pub fn intersect(&self, ray: &Ray) -> Option<Intersection> {
+        self.intersect_counted(ray, &mut 0)
+    }
+
+    /// `intersect`, adding the number of primitives tested against `ray` to `tests`.
+    pub fn intersect_counted(&self, ray: &Ray, tests: &mut usize) -> Option<Intersection> {
         let mut hit_point: Option<HitPoint> = None;
         let mut object_id: Option<usize> = None;
         let mut distance = f64::MAX;
 
         for (i, sphere) in self.spheres.iter().enumerate() {
+            *tests += 1;
             if let Some(hit) = sphere.intersect(ray) {
                 if hit.distance < distance {
                     distance = hit.distance;
@@ -105,17 +867,383 @@ impl Scene {
             }
         }
 
-        if let Some(hit) = hit_point {
-            Some(Intersection {
-                hit_point: hit,
-                object_id: object_id.unwrap() as u32,
-            })
-        } else {
-            None
+        hit_point.map(|hit| Intersection::new(hit, object_id.unwrap() as u32))
+    }
+
+    pub fn intersect_area_light(&self, ray: &Ray) -> Option<(usize, f64)> {
+        self.intersect_area_light_counted(ray, &mut 0)
+    }
+
+    /// `intersect_area_light`, adding the number of light surfaces tested against `ray` to
+    /// `tests`.
+    pub fn intersect_area_light_counted(
+        &self,
+        ray: &Ray,
+        tests: &mut usize,
+    ) -> Option<(usize, f64)> {
+        let mut nearest: Option<(usize, f64)> = None;
+
+        for (i, light) in self.lights.iter().enumerate() {
+            if !light.has_surface() {
+                continue;
+            }
+
+            *tests += 1;
+            if let Some(distance) = light.intersect(ray) {
+                if nearest.is_none_or(|(_, d)| distance < d) {
+                    nearest = Some((i, distance));
+                }
+            }
         }
+
+        nearest
+    }
+
+    /// Follows `ray`, starting in `medium`, through interface surfaces and media to the first
+    /// surface, area light or scattering event.
+    pub fn trace(&self, ray: &Ray, medium: Option<u32>, rnd: &mut dyn Sampler) -> Trace {
+        let mut segment = *ray;
+        let mut offset = 0.0;
+        let mut medium = medium;
+        let mut weight = Color::new(1.0, 1.0, 1.0);
+
+        loop {
+            let intersection = self.intersect(&segment);
+            let light_hit = self.intersect_area_light(&segment).filter(|(_, distance)| {
+                intersection
+                    .as_ref()
+                    .is_none_or(|i| *distance < i.hit_point.distance)
+            });
+
+            if let Some(medium_id) = medium {
+                let distance = match (&light_hit, &intersection) {
+                    (Some((_, distance)), _) => *distance,
+                    (None, Some(intersection)) => intersection.hit_point.distance,
+                    (None, None) => f64::INFINITY,
+                };
+
+                let sample = self.medium(medium_id).sample(&segment, distance, rnd);
+                weight = weight * sample.weight;
+
+                if let Some(distance) = sample.distance {
+                    return Trace {
+                        event: Event::Medium(medium_id, distance),
+                        segment,
+                        offset,
+                        medium,
+                        weight,
+                    };
+                }
+            }
+
+            let event = match (light_hit, intersection) {
+                (Some((light_index, distance)), _) => Event::Light(light_index, distance),
+                (None, Some(intersection)) => {
+                    let material =
+                        self.material(self.spheres[intersection.object_id as usize].material_id);
+                    if material.bsdf.is_interface() {
+                        let distance = intersection.hit_point.distance + RAY_EPSILON;
+                        medium = self.medium_across(
+                            material,
+                            intersection.hit_point.normal,
+                            segment.direction,
+                            medium,
+                        );
+                        segment = segment.advance(distance);
+                        offset += distance;
+                        continue;
+                    }
+
+                    Event::Surface(intersection)
+                }
+                (None, None) => Event::Escape,
+            };
+
+            return Trace {
+                event,
+                segment,
+                offset,
+                medium,
+                weight,
+            };
+        }
+    }
+
+    /// Fraction of light carried along `ray` over `distance`, starting in `medium`. Interface
+    /// surfaces only switch the medium; anything else blocks.
+    pub fn transmittance(
+        &self,
+        ray: &Ray,
+        distance: f64,
+        medium: Option<u32>,
+        rnd: &mut dyn Sampler,
+    ) -> Color {
+        let max_distance = distance * (1.0 - SHADOW_EPSILON);
+
+        if self
+            .lights
+            .iter()
+            .any(|light| light.intersect(ray).is_some_and(|d| d < max_distance))
+        {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+
+        let mut transmittance = Color::new(1.0, 1.0, 1.0);
+        let mut segment = *ray;
+        let mut remaining = max_distance;
+        let mut medium = medium;
+
+        loop {
+            let hit = self
+                .intersect(&segment)
+                .filter(|intersection| intersection.hit_point.distance < remaining);
+            let length = hit.as_ref().map_or(remaining.max(0.0), |intersection| {
+                intersection.hit_point.distance
+            });
+
+            if let Some(medium_id) = medium {
+                transmittance =
+                    transmittance * self.medium(medium_id).transmittance(&segment, length, rnd);
+            }
+
+            let Some(intersection) = hit else {
+                return transmittance;
+            };
+
+            let material = self.material(self.spheres[intersection.object_id as usize].material_id);
+            if !material.bsdf.is_interface() {
+                return Color::new(0.0, 0.0, 0.0);
+            }
+
+            medium = self.medium_across(
+                material,
+                intersection.hit_point.normal,
+                ray.direction,
+                medium,
+            );
+            segment = segment.advance(length + RAY_EPSILON);
+            remaining -= length + RAY_EPSILON;
+        }
+    }
+
+    /// Medium entered by crossing a surface along `direction`. Only interiors are tracked, so
+    /// leaving any object returns to the global medium and nested media are not supported.
+    pub fn medium_across(
+        &self,
+        material: &Material,
+        normal: Vec3,
+        direction: Vec3,
+        current: Option<u32>,
+    ) -> Option<u32> {
+        match material.interior {
+            Some(interior) if direction.dot(normal) < 0.0 => Some(interior),
+            Some(_) => self.global_medium,
+            None => current,
+        }
+    }
+
+    /// Picks a light proportionally to its power, returning its index and probability.
+    pub fn sample_light(&self, u: f64) -> Option<(usize, f64)> {
+        let distribution = self.light_distribution.as_ref()?;
+        Some(distribution.sample_discrete(u))
+    }
+
+    pub fn light_selection_pdf(&self, light_index: usize) -> f64 {
+        self.light_distribution
+            .as_ref()
+            .map_or(0.0, |distribution| distribution.discrete_pdf(light_index))
+    }
+
+    pub fn light_pdf(&self, light_index: usize, ray: &Ray) -> f64 {
+        self.light_selection_pdf(light_index) * self.lights[light_index].pdf(self, ray)
+    }
+
+    /// Probability of picking the environment rather than one of the lights when emitting
+    /// light.
+    fn environment_selection(&self) -> f64 {
+        match self.environment {
+            None => 0.0,
+            Some(_) if self.lights.is_empty() => 1.0,
+            Some(_) => 0.5,
+        }
+    }
+
+    /// Picks a light or the environment to emit light from, returning it and its probability.
+    pub fn sample_emitter(&self, u: f64) -> Option<(Emitter, f64)> {
+        let environment = self.environment_selection();
+        if u < environment {
+            return Some((Emitter::Environment, environment));
+        }
+
+        let (light_index, pdf) = self.sample_light((u - environment) / (1.0 - environment))?;
+        Some((Emitter::Light(light_index), pdf * (1.0 - environment)))
+    }
+
+    pub fn emitter_pdf(&self, emitter: Emitter) -> f64 {
+        match emitter {
+            Emitter::Environment => self.environment_selection(),
+            Emitter::Light(light_index) => {
+                (1.0 - self.environment_selection()) * self.light_selection_pdf(light_index)
+            }
+        }
+    }
+
+    /// Picks an emitter and samples a ray leaving it, or `None` if no light is emitted.
+    pub fn sample_emitted_ray(
+        &self,
+        wavelengths: Option<Wavelengths>,
+        rnd: &mut dyn Sampler,
+    ) -> Option<EmitterSample> {
+        let (emitter, selection_pdf) = self.sample_emitter(rnd.next_f64())?;
+        let u = [
+            rnd.next_f64(),
+            rnd.next_f64(),
+            rnd.next_f64(),
+            rnd.next_f64(),
+        ];
+        let emission = self.sample_emission(emitter, wavelengths, u)?;
+        if emission.pdf_position == 0.0
+            || emission.pdf_direction == 0.0
+            || emission.radiance.max() <= 0.0
+        {
+            return None;
+        }
+
+        let cos_light = emission
+            .normal
+            .map_or(1.0, |normal| normal.dot(emission.ray.direction).abs());
+        let beta = emission.radiance * cos_light
+            / (selection_pdf * emission.pdf_position * emission.pdf_direction);
+
+        Some(EmitterSample {
+            emitter,
+            selection_pdf,
+            emission,
+            beta,
+        })
+    }
+
+    pub fn sample_emission(
+        &self,
+        emitter: Emitter,
+        wavelengths: Option<Wavelengths>,
+        u: [f64; 4],
+    ) -> Option<EmissionSample> {
+        match emitter {
+            Emitter::Light(light_index) => {
+                self.lights[light_index].sample_emission(self, wavelengths, u)
+            }
+            Emitter::Environment => {
+                let environment = self.environment.as_ref()?;
+                let (direction, radiance, pdf) = environment.sample(u[0], u[1]);
+                if pdf == 0.0 {
+                    return None;
+                }
+
+                let (origin, pdf_position) =
+                    light::sample_infinite_origin(self, -direction, u[2], u[3]);
+                Some(EmissionSample {
+                    ray: Ray::new(origin, -direction),
+                    normal: Some(-direction),
+                    radiance: spectrum::illuminant(wavelengths, radiance),
+                    pdf_position,
+                    pdf_direction: pdf,
+                })
+            }
+        }
+    }
+
+    /// Sphere bounding the objects and lights, leaving out emissive spheres and the huge spheres
+    /// used as walls and ground planes.
+    pub fn bounding_sphere(&self) -> (Vec3, f64) {
+        let mut points = Vec::new();
+        for (i, sphere) in self.spheres.iter().enumerate() {
+            if sphere.radius >= PLANE_RADIUS || self.sphere_light(i as u32).is_some() {
+                continue;
+            }
+
+            let extent = Vec3::new(sphere.radius, sphere.radius, sphere.radius);
+            points.push(sphere.position - extent);
+            points.push(sphere.position + extent);
+        }
+        for light in &self.lights {
+            match light {
+                Light::Point { position, .. } | Light::Spot { position, .. } => {
+                    points.push(*position)
+                }
+                Light::Quad {
+                    corner,
+                    edge_u,
+                    edge_v,
+                    ..
+                } => {
+                    points.push(*corner);
+                    points.push(*corner + *edge_u + *edge_v);
+                }
+                Light::Disk { center, radius, .. } => {
+                    let extent = Vec3::new(*radius, *radius, *radius);
+                    points.push(*center - extent);
+                    points.push(*center + extent);
+                }
+                Light::Directional { .. } | Light::Sphere { .. } => {}
+            }
+        }
+
+        let Some(first) = points.first() else {
+            return (Vec3::new(0.0, 0.0, 0.0), 1.0);
+        };
+
+        let (min, max) = points.iter().fold((*first, *first), |(min, max), p| {
+            (
+                Vec3::new(min.x.min(p.x), min.y.min(p.y), min.z.min(p.z)),
+                Vec3::new(max.x.max(p.x), max.y.max(p.y), max.z.max(p.z)),
+            )
+        });
+
+        ((min + max) / 2.0, ((max - min).length() / 2.0).max(1.0))
+    }
+
+    pub fn sphere_light(&self, object_id: u32) -> Option<usize> {
+        self.lights
+            .iter()
+            .position(|light| matches!(light, Light::Sphere { object_id: id } if *id == object_id))
+    }
+
+    pub fn material(&self, material_id: u32) -> &Material {
+        &self.materials[material_id as usize]
+    }
+
+    pub fn lights(&self) -> &[Light] {
+        &self.lights
     }
 
     pub fn spheres(&self) -> &[Sphere] {
         &self.spheres
     }
+
+    pub fn add_medium(&mut self, medium: Medium) -> u32 {
+        self.media.push(medium);
+        (self.media.len() - 1) as u32
+    }
+
+    pub fn medium(&self, medium_id: u32) -> &Medium {
+        &self.media[medium_id as usize]
+    }
+
+    pub fn global_medium(&self) -> Option<u32> {
+        self.global_medium
+    }
+
+    /// Fills all space outside of objects with an interior medium.
+    pub fn set_global_medium(&mut self, medium_id: u32) {
+        self.global_medium = Some(medium_id);
+    }
+
+    pub fn environment(&self) -> Option<&Environment> {
+        self.environment.as_ref()
+    }
+
+    pub fn set_environment(&mut self, environment: Environment) {
+        self.environment = Some(environment);
+    }
 }
@@ -0,0 +1,206 @@
+use std::f64::consts::PI;
+
+use super::{frame::Frame, material::Color, vec3::Vec3};
+
+const SUN_ANGULAR_RADIUS: f64 = 0.00465;
+const SUN_SAMPLING_PROBABILITY: f64 = 0.5;
+const SUN_LUMINANCE: f64 = 1.6e6;
+
+#[derive(Debug, Clone, Copy)]
+struct Perez {
+    a: f64,
+    b: f64,
+    c: f64,
+    d: f64,
+    e: f64,
+}
+
+impl Perez {
+    fn evaluate(&self, cos_theta: f64, gamma: f64) -> f64 {
+        (1.0 + self.a * (self.b / cos_theta.max(1e-3)).exp())
+            * (1.0 + self.c * (self.d * gamma).exp() + self.e * gamma.cos().powi(2))
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Sky {
+    sun_direction: Vec3,
+    intensity: f64,
+    perez_y: Perez,
+    perez_x: Perez,
+    perez_yy: Perez,
+    zenith: (f64, f64, f64),
+    sun_radiance: Color,
+    sun_cos_max: f64,
+}
+
+fn xyy_to_rgb(x: f64, y: f64, luminance: f64) -> Color {
+    if y <= 0.0 {
+        return Color::new(0.0, 0.0, 0.0);
+    }
+
+    let cx = x / y * luminance;
+    let cz = (1.0 - x - y) / y * luminance;
+
+    Color::new(
+        (3.2406 * cx - 1.5372 * luminance - 0.4986 * cz).max(0.0),
+        (-0.9689 * cx + 1.8758 * luminance + 0.0415 * cz).max(0.0),
+        (0.0557 * cx - 0.2040 * luminance + 1.0570 * cz).max(0.0),
+    )
+}
+
+fn sun_transmittance(theta_s: f64, turbidity: f64, wavelength: f64) -> f64 {
+    let optical_mass = 1.0 / (theta_s.cos() + 0.15 * (93.885 - theta_s.to_degrees()).powf(-1.253));
+    let beta = 0.04608365822050 * turbidity - 0.04586025928522;
+
+    let rayleigh = (-0.008735 * wavelength.powf(-4.08) * optical_mass).exp();
+    let aerosol = (-beta * wavelength.powf(-1.3) * optical_mass).exp();
+
+    rayleigh * aerosol
+}
+
+impl Sky {
+    pub fn new(elevation: f64, azimuth: f64, turbidity: f64, intensity: f64) -> Sky {
+        let sun_direction = Vec3::new(
+            elevation.cos() * azimuth.cos(),
+            elevation.sin(),
+            elevation.cos() * azimuth.sin(),
+        );
+
+        let t = turbidity;
+        let theta_s = (PI / 2.0 - elevation).clamp(0.0, PI / 2.0);
+
+        let perez_y = Perez {
+            a: 0.1787 * t - 1.4630,
+            b: -0.3554 * t + 0.4275,
+            c: -0.0227 * t + 5.3251,
+            d: 0.1206 * t - 2.5771,
+            e: -0.0670 * t + 0.3703,
+        };
+        let perez_x = Perez {
+            a: -0.0193 * t - 0.2592,
+            b: -0.0665 * t + 0.0008,
+            c: -0.0004 * t + 0.2125,
+            d: -0.0641 * t - 0.8989,
+            e: -0.0033 * t + 0.0452,
+        };
+        let perez_yy = Perez {
+            a: -0.0167 * t - 0.2608,
+            b: -0.0950 * t + 0.0092,
+            c: -0.0079 * t + 0.2102,
+            d: -0.0441 * t - 1.6537,
+            e: -0.0109 * t + 0.0529,
+        };
+
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_s);
+        let zenith_luminance = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+
+        let theta2 = theta_s * theta_s;
+        let theta3 = theta2 * theta_s;
+        let zenith_x = t * t * (0.00166 * theta3 - 0.00375 * theta2 + 0.00209 * theta_s)
+            + t * (-0.02903 * theta3 + 0.06377 * theta2 - 0.03202 * theta_s + 0.00394)
+            + (0.11693 * theta3 - 0.21196 * theta2 + 0.06052 * theta_s + 0.25886);
+        let zenith_y = t * t * (0.00275 * theta3 - 0.00610 * theta2 + 0.00317 * theta_s)
+            + t * (-0.04214 * theta3 + 0.08970 * theta2 - 0.04153 * theta_s + 0.00516)
+            + (0.15346 * theta3 - 0.26756 * theta2 + 0.06670 * theta_s + 0.26688);
+
+        let sun_radiance = if elevation > 0.0 {
+            Color::new(
+                sun_transmittance(theta_s, t, 0.68),
+                sun_transmittance(theta_s, t, 0.55),
+                sun_transmittance(theta_s, t, 0.44),
+            ) * SUN_LUMINANCE
+        } else {
+            Color::new(0.0, 0.0, 0.0)
+        };
+
+        Sky {
+            sun_direction,
+            intensity,
+            perez_y,
+            perez_x,
+            perez_yy,
+            zenith: (
+                zenith_luminance.max(0.0) / perez_y.evaluate(1.0, theta_s),
+                zenith_x / perez_x.evaluate(1.0, theta_s),
+                zenith_y / perez_yy.evaluate(1.0, theta_s),
+            ),
+            sun_radiance,
+            sun_cos_max: SUN_ANGULAR_RADIUS.cos(),
+        }
+    }
+
+    fn sun_visible(&self) -> bool {
+        self.sun_direction.y > 0.0
+    }
+
+    fn sky_radiance(&self, direction: Vec3) -> Color {
+        if direction.y <= 0.0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+
+        let cos_theta = direction.y;
+        let gamma = direction.dot(self.sun_direction).clamp(-1.0, 1.0).acos();
+
+        let luminance = self.zenith.0 * self.perez_y.evaluate(cos_theta, gamma);
+        let x = self.zenith.1 * self.perez_x.evaluate(cos_theta, gamma);
+        let y = self.zenith.2 * self.perez_yy.evaluate(cos_theta, gamma);
+
+        xyy_to_rgb(x, y, luminance)
+    }
+
+    pub fn radiance(&self, direction: Vec3) -> Color {
+        let direction = direction.normalize();
+        let mut radiance = self.sky_radiance(direction);
+
+        if self.sun_visible() && direction.dot(self.sun_direction) >= self.sun_cos_max {
+            radiance = radiance + self.sun_radiance;
+        }
+
+        radiance * self.intensity
+    }
+
+    pub fn sample(&self, u0: f64, u1: f64) -> (Vec3, Color, f64) {
+        let direction = if self.sun_visible() && u0 < SUN_SAMPLING_PROBABILITY {
+            let u0 = u0 / SUN_SAMPLING_PROBABILITY;
+            let cos_theta = 1.0 - u0 * (1.0 - self.sun_cos_max);
+            let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+            let phi = 2.0 * PI * u1;
+
+            Frame::from_normal(self.sun_direction).to_world(Vec3::new(
+                phi.cos() * sin_theta,
+                phi.sin() * sin_theta,
+                cos_theta,
+            ))
+        } else {
+            let u0 = if self.sun_visible() {
+                (u0 - SUN_SAMPLING_PROBABILITY) / (1.0 - SUN_SAMPLING_PROBABILITY)
+            } else {
+                u0
+            };
+            let r = u0.sqrt();
+            let phi = 2.0 * PI * u1;
+
+            Vec3::new(r * phi.cos(), (1.0 - u0).max(0.0).sqrt(), r * phi.sin())
+        };
+
+        (direction, self.radiance(direction), self.pdf(direction))
+    }
+
+    pub fn pdf(&self, direction: Vec3) -> f64 {
+        let direction = direction.normalize();
+        let hemisphere_pdf = direction.y.max(0.0) / PI;
+
+        if !self.sun_visible() {
+            return hemisphere_pdf;
+        }
+
+        let sun_pdf = if direction.dot(self.sun_direction) >= self.sun_cos_max {
+            1.0 / (2.0 * PI * (1.0 - self.sun_cos_max))
+        } else {
+            0.0
+        };
+
+        SUN_SAMPLING_PROBABILITY * sun_pdf + (1.0 - SUN_SAMPLING_PROBABILITY) * hemisphere_pdf
+    }
+}
@@ -0,0 +1,299 @@
+use std::sync::OnceLock;
+
+use super::{material::Color, vec3::Vec3};
+
+pub const LAMBDA_MIN: f64 = 360.0;
+pub const LAMBDA_MAX: f64 = 830.0;
+
+/// CIE D65 relative spectral power from 360 to 830 nm in 10 nm steps.
+const D65: [f64; 48] = [
+    46.6383, 52.0891, 49.9755, 54.6482, 82.7549, 91.486, 93.4318, 86.6823, 104.865, 117.008,
+    117.812, 114.861, 115.923, 108.811, 109.354, 107.802, 104.79, 107.689, 104.405, 104.046, 100.0,
+    96.3342, 95.788, 88.6856, 90.0062, 89.5991, 87.6987, 83.2886, 83.6992, 80.0268, 80.2146,
+    82.2778, 78.2842, 69.7213, 71.6091, 74.349, 61.604, 69.8856, 75.087, 63.5927, 46.4182, 66.8054,
+    63.3828, 64.304, 59.4519, 51.959, 57.4406, 60.3125,
+];
+
+/// Smits' basis spectra for RGB to reflectance conversion, in ten bins from 380 to 720 nm.
+const SMITS_WHITE: [f64; 10] = [
+    1.0000, 1.0000, 0.9999, 0.9993, 0.9992, 0.9998, 1.0000, 1.0000, 1.0000, 1.0000,
+];
+const SMITS_CYAN: [f64; 10] = [
+    0.9710, 0.9426, 1.0007, 1.0007, 1.0007, 1.0007, 0.1564, 0.0000, 0.0000, 0.0000,
+];
+const SMITS_MAGENTA: [f64; 10] = [
+    1.0000, 1.0000, 0.9685, 0.2229, 0.0000, 0.0458, 0.8369, 1.0000, 1.0000, 0.9959,
+];
+const SMITS_YELLOW: [f64; 10] = [
+    0.0001, 0.0000, 0.1088, 0.6651, 1.0000, 1.0000, 0.9996, 0.9586, 0.9685, 0.9840,
+];
+const SMITS_RED: [f64; 10] = [
+    0.1012, 0.0515, 0.0000, 0.0000, 0.0000, 0.0000, 0.8325, 1.0149, 1.0149, 1.0149,
+];
+const SMITS_GREEN: [f64; 10] = [
+    0.0000, 0.0000, 0.0273, 0.7937, 1.0000, 0.9418, 0.1719, 0.0000, 0.0000, 0.0025,
+];
+const SMITS_BLUE: [f64; 10] = [
+    1.0000, 1.0000, 0.8916, 0.3323, 0.0000, 0.0000, 0.0003, 0.0369, 0.0483, 0.0496,
+];
+
+fn lobe(lambda: f64, mean: f64, sigma_low: f64, sigma_high: f64) -> f64 {
+    let t = (lambda - mean) / if lambda < mean { sigma_low } else { sigma_high };
+    (-0.5 * t * t).exp()
+}
+
+/// CIE 1931 color matching functions, using the multi-lobe fit of Wyman et al. 2013.
+fn cie_xyz(lambda: f64) -> Vec3 {
+    Vec3::new(
+        1.056 * lobe(lambda, 599.8, 37.9, 31.0) + 0.362 * lobe(lambda, 442.0, 16.0, 26.7)
+            - 0.065 * lobe(lambda, 501.1, 20.4, 26.2),
+        0.821 * lobe(lambda, 568.8, 46.9, 40.5) + 0.286 * lobe(lambda, 530.9, 16.3, 31.1),
+        1.217 * lobe(lambda, 437.0, 11.8, 36.0) + 0.681 * lobe(lambda, 459.0, 26.0, 13.8),
+    )
+}
+
+fn xyz_to_rgb(xyz: Vec3) -> Color {
+    Color::new(
+        3.2404542 * xyz.x - 1.5371385 * xyz.y - 0.4985314 * xyz.z,
+        -0.9692660 * xyz.x + 1.8760108 * xyz.y + 0.0415560 * xyz.z,
+        0.0556434 * xyz.x - 0.2040259 * xyz.y + 1.0572252 * xyz.z,
+    )
+}
+
+fn integrate_xyz(spectrum: impl Fn(f64) -> f64) -> Vec3 {
+    let mut sum = Vec3::new(0.0, 0.0, 0.0);
+    let mut lambda = LAMBDA_MIN + 0.5;
+    while lambda < LAMBDA_MAX {
+        sum = sum + cie_xyz(lambda) * spectrum(lambda);
+        lambda += 1.0;
+    }
+    sum
+}
+
+fn d65(lambda: f64) -> f64 {
+    let position = ((lambda - LAMBDA_MIN) / 10.0).clamp(0.0, (D65.len() - 1) as f64);
+    let index = (position as usize).min(D65.len() - 2);
+    let t = position - index as f64;
+    D65[index] * (1.0 - t) + D65[index + 1] * t
+}
+
+/// D65 scaled to unit luminance, which is the spectrum of RGB white.
+fn white_illuminant(lambda: f64) -> f64 {
+    static NORMALIZATION: OnceLock<f64> = OnceLock::new();
+    d65(lambda) / NORMALIZATION.get_or_init(|| integrate_xyz(d65).y)
+}
+
+/// Planck's law for a wavelength in nanometers, up to a constant factor.
+fn planck(lambda: f64, temperature: f64) -> f64 {
+    const C2: f64 = 1.4387769e7;
+    let lambda = lambda * 1e-3;
+    1.0 / (lambda.powi(5) * ((C2 * 1e-3 / (lambda * temperature)).exp() - 1.0))
+}
+
+fn smits(basis: &[f64; 10], lambda: f64) -> f64 {
+    let position = ((lambda - 380.0) / 34.0 - 0.5).clamp(0.0, 9.0);
+    let index = (position as usize).min(8);
+    let t = position - index as f64;
+    basis[index] * (1.0 - t) + basis[index + 1] * t
+}
+
+/// Smooth reflectance spectrum for an RGB color, following Smits 1999. Colors above one are
+/// scaled down, converted, and scaled back up.
+fn rgb_to_spectrum(rgb: Color, lambda: f64) -> f64 {
+    let scale = rgb.max().max(1.0);
+    let (r, g, b) = (rgb.x / scale, rgb.y / scale, rgb.z / scale);
+    let basis = |spectrum: &[f64; 10]| smits(spectrum, lambda);
+
+    let value = if r <= g && r <= b {
+        r * basis(&SMITS_WHITE)
+            + if g <= b {
+                (g - r) * basis(&SMITS_CYAN) + (b - g) * basis(&SMITS_BLUE)
+            } else {
+                (b - r) * basis(&SMITS_CYAN) + (g - b) * basis(&SMITS_GREEN)
+            }
+    } else if g <= r && g <= b {
+        g * basis(&SMITS_WHITE)
+            + if r <= b {
+                (r - g) * basis(&SMITS_MAGENTA) + (b - r) * basis(&SMITS_BLUE)
+            } else {
+                (b - g) * basis(&SMITS_MAGENTA) + (r - b) * basis(&SMITS_RED)
+            }
+    } else {
+        b * basis(&SMITS_WHITE)
+            + if r <= g {
+                (r - b) * basis(&SMITS_YELLOW) + (g - r) * basis(&SMITS_GREEN)
+            } else {
+                (g - b) * basis(&SMITS_YELLOW) + (r - g) * basis(&SMITS_RED)
+            }
+    };
+
+    value.max(0.0) * scale
+}
+
+fn visible_pdf(lambda: f64) -> f64 {
+    let c = (0.0072 * (lambda - 538.0)).cosh();
+    0.0039398042 / (c * c)
+}
+
+fn sample_visible(u: f64) -> f64 {
+    (538.0 - 138.888889 * (0.85691062 - 1.82750197 * u).atanh()).clamp(LAMBDA_MIN, LAMBDA_MAX)
+}
+
+/// Three wavelengths in nanometers carried by a path, stored in the channels of a `Color`. The
+/// first is the hero wavelength and the others are stratified against it.
+#[derive(Debug, Clone, Copy)]
+pub struct Wavelengths {
+    lambda: [f64; 3],
+    secondary_terminated: bool,
+}
+
+impl Wavelengths {
+    /// Samples wavelengths proportionally to the visual response.
+    pub fn sample(u: f64) -> Wavelengths {
+        Wavelengths {
+            lambda: [0.0, 1.0, 2.0].map(|i| sample_visible((u + i / 3.0).fract())),
+            secondary_terminated: false,
+        }
+    }
+
+    pub fn hero(&self) -> f64 {
+        self.lambda[0]
+    }
+
+    /// Drops the secondary wavelengths at a wavelength-dependent scattering event, returning the
+    /// weight that moves their share of the estimate onto the hero wavelength.
+    pub fn terminate_secondary(&mut self) -> Color {
+        if self.secondary_terminated {
+            Color::new(1.0, 0.0, 0.0)
+        } else {
+            self.secondary_terminated = true;
+            Color::new(3.0, 0.0, 0.0)
+        }
+    }
+
+    fn map(&self, f: impl Fn(f64) -> f64) -> Color {
+        Color::new(f(self.lambda[0]), f(self.lambda[1]), f(self.lambda[2]))
+    }
+
+    pub fn reflectance(&self, rgb: Color) -> Color {
+        self.map(|lambda| rgb_to_spectrum(rgb, lambda))
+    }
+
+    /// Emission spectrum of an RGB color under the D65 white point.
+    pub fn illuminant(&self, rgb: Color) -> Color {
+        self.map(|lambda| rgb_to_spectrum(rgb, lambda) * white_illuminant(lambda))
+    }
+
+    /// Converts radiance at these wavelengths to an estimate of its linear sRGB color.
+    pub fn to_rgb(self, radiance: Color) -> Color {
+        let sample = |lambda: f64, value: f64| cie_xyz(lambda) * (value / visible_pdf(lambda));
+        let xyz = (sample(self.lambda[0], radiance.x)
+            + sample(self.lambda[1], radiance.y)
+            + sample(self.lambda[2], radiance.z))
+            / 3.0;
+
+        xyz_to_rgb(xyz)
+    }
+}
+
+/// Converts an RGB reflectance to the path's wavelengths, or keeps it in RGB mode.
+pub fn reflectance(wavelengths: Option<Wavelengths>, rgb: Color) -> Color {
+    wavelengths.map_or(rgb, |wavelengths| wavelengths.reflectance(rgb))
+}
+
+/// Converts RGB emission to the path's wavelengths, or keeps it in RGB mode.
+pub fn illuminant(wavelengths: Option<Wavelengths>, rgb: Color) -> Color {
+    wavelengths.map_or(rgb, |wavelengths| wavelengths.illuminant(rgb))
+}
+
+/// Converts radiance at the path's wavelengths to RGB, or keeps it in RGB mode.
+pub fn to_rgb(wavelengths: Option<Wavelengths>, radiance: Color) -> Color {
+    wavelengths.map_or(radiance, |wavelengths| wavelengths.to_rgb(radiance))
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum StandardIlluminant {
+    /// Incandescent tungsten, a 2856 K blackbody.
+    A,
+    D65,
+    /// Equal energy.
+    E,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Kind {
+    Rgb(Color),
+    Blackbody(f64),
+    Illuminant(StandardIlluminant),
+}
+
+/// Light emission given in RGB or as a physical spectrum. Spectra are normalized to unit
+/// luminance before `scale` is applied.
+#[derive(Debug, Clone, Copy)]
+pub struct Spectrum {
+    kind: Kind,
+    scale: f64,
+    rgb: Color,
+}
+
+impl Spectrum {
+    pub fn rgb(color: Color) -> Spectrum {
+        Spectrum {
+            kind: Kind::Rgb(color),
+            scale: 1.0,
+            rgb: color,
+        }
+    }
+
+    pub fn blackbody(temperature: f64, scale: f64) -> Spectrum {
+        Spectrum::physical(Kind::Blackbody(temperature), scale)
+    }
+
+    pub fn illuminant(illuminant: StandardIlluminant, scale: f64) -> Spectrum {
+        Spectrum::physical(Kind::Illuminant(illuminant), scale)
+    }
+
+    fn physical(kind: Kind, scale: f64) -> Spectrum {
+        let spectrum = Spectrum {
+            kind,
+            scale: 1.0,
+            rgb: Color::new(0.0, 0.0, 0.0),
+        };
+        let xyz = integrate_xyz(|lambda| spectrum.value(lambda));
+
+        Spectrum {
+            kind,
+            scale: scale / xyz.y,
+            rgb: xyz_to_rgb(xyz) * (scale / xyz.y),
+        }
+    }
+
+    fn value(&self, lambda: f64) -> f64 {
+        self.scale
+            * match self.kind {
+                Kind::Rgb(color) => rgb_to_spectrum(color, lambda) * white_illuminant(lambda),
+                Kind::Blackbody(temperature) => planck(lambda, temperature),
+                Kind::Illuminant(StandardIlluminant::A) => planck(lambda, 2856.0),
+                Kind::Illuminant(StandardIlluminant::D65) => d65(lambda),
+                Kind::Illuminant(StandardIlluminant::E) => 1.0,
+            }
+    }
+
+    /// Linear sRGB color of the spectrum.
+    pub fn to_rgb(self) -> Color {
+        self.rgb
+    }
+
+    pub fn evaluate(&self, wavelengths: Option<Wavelengths>) -> Color {
+        match wavelengths {
+            Some(wavelengths) => wavelengths.map(|lambda| self.value(lambda)),
+            None => self.rgb,
+        }
+    }
+}
+
+impl From<Color> for Spectrum {
+    fn from(color: Color) -> Spectrum {
+        Spectrum::rgb(color)
+    }
+}
@@ -1,28 +1,24 @@
-use super::{intersection::HitPoint, material::RefrectionType, ray::Ray, vec3::Vec3};
+use std::f64::consts::PI;
+
+use super::{
+    intersection::HitPoint,
+    ray::{Ray, RAY_EPSILON},
+    vec3::Vec3,
+};
 
 #[derive(Debug, Clone, Copy)]
 pub struct Sphere {
     pub radius: f64,
     pub position: Vec3,
-    pub emission: Vec3,
-    pub color: Vec3,
-    pub reflection_type: RefrectionType,
+    pub material_id: u32,
 }
 
 impl Sphere {
-    pub fn new(
-        radius: f64,
-        position: Vec3,
-        emission: Vec3,
-        color: Vec3,
-        reflection_type: RefrectionType,
-    ) -> Sphere {
+    pub fn new(radius: f64, position: Vec3, material_id: u32) -> Sphere {
         Sphere {
             radius,
             position,
-            emission,
-            color,
-            reflection_type,
+            material_id,
         }
     }
 
@@ -39,16 +35,31 @@ impl Sphere {
         let t1 = b - sqrt_d4;
         let t2 = b + sqrt_d4;
 
-        if t1 < f64::EPSILON && t2 < f64::EPSILON {
+        if t1 < RAY_EPSILON && t2 < RAY_EPSILON {
             return None;
         }
 
-        let distance = if t1 > f64::EPSILON { t1 } else { t2 };
+        let distance = if t1 > RAY_EPSILON { t1 } else { t2 };
+
+        Some(self.hit_point(ray.origin + ray.direction * distance, distance))
+    }
+
+    /// Surface data at `position` on the sphere, reached after `distance`.
+    pub fn hit_point(&self, position: Vec3, distance: f64) -> HitPoint {
+        let local = position - self.position;
+        let normal = local.normalize();
 
-        let position = ray.origin + ray.direction * distance;
+        let phi = normal.z.atan2(normal.x);
+        let theta = normal.y.clamp(-1.0, 1.0).acos();
+        let uv = (phi.rem_euclid(2.0 * PI) / (2.0 * PI), theta / PI);
 
-        let normal = (position - self.position).normalize();
+        let dpdu = Vec3::new(-local.z, 0.0, local.x) * (2.0 * PI);
+        let dpdv = Vec3::new(
+            theta.cos() * phi.cos(),
+            -theta.sin(),
+            theta.cos() * phi.sin(),
+        ) * (PI * self.radius);
 
-        Some(HitPoint::new(distance, normal, position))
+        HitPoint::new(distance, normal, position, uv, dpdu, dpdv)
     }
 }
@@ -0,0 +1,487 @@
+use std::{f64::consts::PI, sync::Mutex, thread};
+
+use super::{
+    frame::Frame,
+    hash_grid::HashGrid,
+    material::{self, Color, Material, ShadingContext, TransportMode},
+    random::{Sampler, XorShiftRandom},
+    ray::Ray,
+    russian_roulette,
+    scene::{EmitterSample, Event},
+    spectrum::{self, Wavelengths},
+    vec3::Vec3,
+    Render,
+};
+
+/// First non-specular surface seen through a pixel, where photons are gathered.
+#[derive(Clone, Copy)]
+struct VisiblePoint<'a> {
+    position: Vec3,
+    normal: Vec3,
+    wo: Vec3,
+    material: &'a Material,
+    frame: Frame,
+    context: ShadingContext,
+    beta: Color,
+    /// Wavelengths of the camera path, with the secondaries terminated past dispersion.
+    wavelengths: Option<Wavelengths>,
+}
+
+impl VisiblePoint<'_> {
+    fn f(&self, wi: Vec3) -> Color {
+        let wi_local = self.frame.to_local(wi);
+        if wi.dot(self.normal) * wi_local.z <= 0.0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+
+        let f = self
+            .material
+            .bsdf
+            .eval(&self.context, self.frame.to_local(self.wo), wi_local);
+        spectrum::reflectance(self.wavelengths, f)
+    }
+}
+
+struct Pixel<'a> {
+    visible_point: Option<VisiblePoint<'a>>,
+    /// Direct lighting summed over the iterations.
+    direct: Color,
+    radius: f64,
+    /// Number of photons kept so far, and their flux scaled to the current radius.
+    photons: f64,
+    tau: Color,
+}
+
+/// Photons gathered at a visible point during one iteration.
+#[derive(Clone, Copy)]
+struct Gathered {
+    flux: Color,
+    count: u32,
+}
+
+/// Stochastic progressive photon mapping. Each iteration traces a camera path through every
+/// pixel to its first non-specular surface, then shoots photons from the emitters and
+/// gathers them around those points within radii that shrink as the photons accumulate.
+pub struct Sppm<'a> {
+    render: &'a Render,
+}
+
+impl<'a> Sppm<'a> {
+    pub fn new(render: &'a Render) -> Sppm<'a> {
+        Sppm { render }
+    }
+
+    pub fn render(&self) -> Vec<Color> {
+        let config = self.render.config;
+        let width = config.width;
+        let tasks = config.tasks.max(1);
+        let iterations = config.samples * config.super_samples * config.super_samples;
+        let photon_count = config.width * config.height;
+
+        let mut pixels: Vec<Pixel> = (0..config.width * config.height)
+            .map(|_| Pixel {
+                visible_point: None,
+                direct: Color::new(0.0, 0.0, 0.0),
+                radius: config.merge_radius,
+                photons: 0.0,
+                tau: Color::new(0.0, 0.0, 0.0),
+            })
+            .collect();
+
+        let mut rnd = XorShiftRandom::new(1);
+        for iteration in 0..iterations {
+            println!("Rendering (iteration = {} / {})", iteration, iterations);
+
+            // All paths of an iteration share its wavelengths so photons match the pixels.
+            let wavelengths = config.spectral.then(|| Wavelengths::sample(rnd.next_f64()));
+            let seed = iteration * tasks * 2;
+
+            let rows = config.height.div_ceil(tasks);
+            thread::scope(|s| {
+                for (task, chunk) in pixels.chunks_mut((rows * width) as usize).enumerate() {
+                    s.spawn(move || {
+                        let mut rnd = XorShiftRandom::new(seed + task as u32 + 1);
+                        let start = task as u32 * rows * width;
+
+                        for (i, pixel) in chunk.iter_mut().enumerate() {
+                            let index = start + i as u32;
+                            let (direct, visible_point) = self.visible_point(
+                                (index % width) as f64 + rnd.next_f64(),
+                                (index / width) as f64 + rnd.next_f64(),
+                                wavelengths,
+                                &mut rnd,
+                            );
+                            pixel.direct = pixel.direct + spectrum::to_rgb(wavelengths, direct);
+                            pixel.visible_point = visible_point;
+                        }
+                    });
+                }
+            });
+
+            // Cells twice the largest radius keep each gathering sphere within two cells
+            // along each axis.
+            let mut grid = HashGrid::new(
+                pixels
+                    .iter()
+                    .filter(|pixel| pixel.visible_point.is_some())
+                    .map(|pixel| pixel.radius * 2.0)
+                    .fold(0.0, f64::max),
+            );
+            for (index, pixel) in pixels.iter().enumerate() {
+                if let Some(visible_point) = &pixel.visible_point {
+                    grid.insert(index as u32, visible_point.position, pixel.radius);
+                }
+            }
+            let gathered: Vec<Mutex<Gathered>> = (0..pixels.len())
+                .map(|_| {
+                    Mutex::new(Gathered {
+                        flux: Color::new(0.0, 0.0, 0.0),
+                        count: 0,
+                    })
+                })
+                .collect();
+
+            thread::scope(|s| {
+                for task in 0..tasks {
+                    let (grid, pixels, gathered) = (&grid, &pixels, &gathered);
+                    s.spawn(move || {
+                        let mut rnd = XorShiftRandom::new(seed + tasks + task + 1);
+                        for _ in (task..photon_count).step_by(tasks as usize) {
+                            self.trace_photon(grid, pixels, gathered, wavelengths, &mut rnd);
+                        }
+                    });
+                }
+            });
+
+            for (pixel, gathered) in pixels.iter_mut().zip(gathered) {
+                let Gathered { flux, count } = gathered.into_inner().unwrap();
+
+                if let (Some(visible_point), true) = (&pixel.visible_point, count > 0) {
+                    // Keeping a fraction alpha of the new photons shrinks the radius like VCM's.
+                    let photons = pixel.photons + config.merge_radius_alpha * count as f64;
+                    let radius = pixel.radius * (photons / (pixel.photons + count as f64)).sqrt();
+                    let flux = spectrum::to_rgb(wavelengths, visible_point.beta * flux);
+
+                    pixel.tau =
+                        (pixel.tau + flux) * (radius * radius) / (pixel.radius * pixel.radius);
+                    pixel.photons = photons;
+                    pixel.radius = radius;
+                }
+                pixel.visible_point = None;
+            }
+        }
+
+        let emitted = iterations as f64 * photon_count as f64;
+        pixels
+            .iter()
+            .map(|pixel| {
+                pixel.direct / iterations as f64
+                    + pixel.tau / (emitted * PI * pixel.radius * pixel.radius)
+            })
+            .collect()
+    }
+
+    /// Follows a camera ray through the raster position `(x, y)` past specular surfaces,
+    /// returning the emitted and direct radiance found on the way along with the surface where
+    /// it stops.
+    fn visible_point(
+        &self,
+        x: f64,
+        y: f64,
+        wavelengths: Option<Wavelengths>,
+        rnd: &mut dyn Sampler,
+    ) -> (Color, Option<VisiblePoint<'a>>) {
+        let scene = &self.render.scene;
+        let camera = &self.render.camera;
+
+        let mut ray = camera.ray(x, y).with_wavelengths(wavelengths);
+        let mut cone = camera.cone();
+        let mut medium = scene.global_medium();
+        let mut wavelengths = wavelengths;
+        let mut beta = Color::new(1.0, 1.0, 1.0);
+        let mut radiance = Color::new(0.0, 0.0, 0.0);
+
+        // Density the last direction was sampled with, or `None` after specular scattering.
+        let mut bsdf_pdf = None;
+
+        // Camera paths only continue through specular surfaces and media. Media gather direct
+        // lighting like the path tracer does, so emission found by phase sampling is weighted
+        // against it, while emission seen through specular chains is counted in full.
+        for depth in 0..=self.render.config.max_depth {
+            let trace = scene.trace(&ray, medium, rnd);
+            cone = cone.scatter(trace.offset, None);
+            medium = trace.medium;
+            beta = beta * trace.weight;
+
+            let intersection = match trace.event {
+                Event::Medium(medium_id, distance) => {
+                    if depth == self.render.config.max_depth {
+                        break;
+                    }
+
+                    let segment = trace.segment;
+                    let phase = scene.medium(medium_id).phase();
+                    let position = segment.origin + segment.direction * distance;
+
+                    radiance = radiance
+                        + beta
+                            * self.render.sample_direct_lighting(
+                                position,
+                                wavelengths,
+                                &|_| Some(medium_id),
+                                &|wi| {
+                                    let p = phase.eval(segment.direction.dot(wi));
+                                    (Color::new(p, p, p), p)
+                                },
+                                rnd,
+                            );
+
+                    let (direction, pdf) =
+                        phase.sample(segment.direction, rnd.next_f64(), rnd.next_f64());
+                    bsdf_pdf = Some(pdf);
+                    cone = cone.scatter(distance, Some(pdf));
+                    ray = segment.spawn(position, direction);
+                    continue;
+                }
+                Event::Light(light_index, _) => {
+                    let light = &scene.lights()[light_index];
+                    let emitted = self.render.emitted_radiance(
+                        light.emitted(&ray),
+                        Some(light_index),
+                        &ray,
+                        bsdf_pdf,
+                    );
+                    return (radiance + beta * emitted, None);
+                }
+                Event::Surface(intersection) => intersection,
+                Event::Escape => {
+                    return (
+                        radiance + beta * self.render.escaped_radiance(&ray, bsdf_pdf),
+                        None,
+                    );
+                }
+            };
+
+            let hitpoint = intersection.hit_point;
+            let sphere = &scene.spheres()[intersection.object_id as usize];
+            let material = scene.material(sphere.material_id);
+            let bsdf = material.bsdf.as_ref();
+
+            let dispersion = match &mut wavelengths {
+                Some(wavelengths) if bsdf.is_dispersive() => wavelengths.terminate_secondary(),
+                _ => Color::new(1.0, 1.0, 1.0),
+            };
+
+            let context = ShadingContext::new(
+                hitpoint.position,
+                hitpoint.uv,
+                hitpoint.uv_footprint(cone.width_at(hitpoint.distance), ray.direction),
+            )
+            .with_wavelength(wavelengths.map(|wavelengths| wavelengths.hero()));
+            radiance = radiance
+                + beta
+                    * self.render.emitted_radiance(
+                        spectrum::illuminant(wavelengths, material.emission.evaluate(&context)),
+                        scene.sphere_light(intersection.object_id),
+                        &ray,
+                        bsdf_pdf,
+                    );
+            if depth == self.render.config.max_depth {
+                break;
+            }
+
+            let geometric_normal = hitpoint.normal;
+            let wo_world = -ray.direction;
+            let frame = material.shading_frame(&hitpoint, &context, wo_world);
+            let wo = frame.to_local(wo_world);
+
+            let consistent = |wi_world: Vec3, wi: Vec3| wi_world.dot(geometric_normal) * wi.z > 0.0;
+            let medium_toward = |wi_world: Vec3| {
+                if wi_world.dot(geometric_normal) * wo_world.dot(geometric_normal) < 0.0 {
+                    scene.medium_across(material, geometric_normal, wi_world, medium)
+                } else {
+                    medium
+                }
+            };
+
+            beta = beta * dispersion;
+
+            if !bsdf.is_specular() {
+                // Photons skip their first hit, so light sampling alone accounts for direct
+                // lighting and takes the full weight.
+                let direct = self.render.sample_direct_lighting(
+                    hitpoint.position,
+                    wavelengths,
+                    &medium_toward,
+                    &|wi_world| {
+                        let wi = frame.to_local(wi_world);
+                        if !consistent(wi_world, wi) {
+                            return (Color::new(0.0, 0.0, 0.0), 0.0);
+                        }
+                        (
+                            spectrum::reflectance(wavelengths, bsdf.eval(&context, wo, wi))
+                                * wi.z.abs(),
+                            0.0,
+                        )
+                    },
+                    rnd,
+                );
+
+                let visible_point = VisiblePoint {
+                    position: hitpoint.position,
+                    normal: geometric_normal,
+                    wo: wo_world,
+                    material,
+                    frame,
+                    context,
+                    beta,
+                    wavelengths,
+                };
+                return (radiance + beta * direct, Some(visible_point));
+            }
+
+            let Some(sample) = bsdf.sample(&context, wo, rnd) else {
+                break;
+            };
+            let direction = frame.to_world(sample.direction);
+            if !consistent(direction, sample.direction) {
+                break;
+            }
+
+            beta = beta * spectrum::reflectance(wavelengths, sample.weight);
+            bsdf_pdf = None;
+            medium = medium_toward(direction);
+            cone = cone.scatter(hitpoint.distance, None);
+            ray = Ray::new(hitpoint.position, direction).with_wavelengths(wavelengths);
+        }
+
+        (radiance, None)
+    }
+
+    /// Shoots a photon from one of the emitters and adds it to the visible points it lands
+    /// near after the first bounce.
+    fn trace_photon(
+        &self,
+        grid: &HashGrid,
+        pixels: &[Pixel],
+        gathered: &[Mutex<Gathered>],
+        wavelengths: Option<Wavelengths>,
+        rnd: &mut dyn Sampler,
+    ) {
+        let scene = &self.render.scene;
+
+        let Some(EmitterSample {
+            emission, mut beta, ..
+        }) = scene.sample_emitted_ray(wavelengths, rnd)
+        else {
+            return;
+        };
+        let mut ray = emission.ray.with_wavelengths(wavelengths);
+        let mut medium = scene.global_medium();
+        let mut dispersive = false;
+        // Photons start out carrying the emitted power, so Russian roulette looks at the
+        // throughput relative to it.
+        let power = beta.max();
+
+        for depth in 0..self.render.config.max_depth {
+            let trace = scene.trace(&ray, medium, rnd);
+            medium = trace.medium;
+            beta = beta * trace.weight;
+            if beta.max() <= 0.0 {
+                return;
+            }
+
+            let intersection = match trace.event {
+                Event::Medium(medium_id, distance) => {
+                    let position = trace.segment.origin + trace.segment.direction * distance;
+                    let (direction, _) = scene.medium(medium_id).phase().sample(
+                        trace.segment.direction,
+                        rnd.next_f64(),
+                        rnd.next_f64(),
+                    );
+                    ray = trace.segment.spawn(position, direction);
+                    continue;
+                }
+                Event::Surface(intersection) => intersection,
+                Event::Light(..) | Event::Escape => return,
+            };
+
+            let hitpoint = intersection.hit_point;
+            let direction = ray.direction;
+
+            // Direct lighting is estimated at the visible points, so the first hit is skipped.
+            if depth > 0 {
+                for &index in grid.candidates(hitpoint.position) {
+                    let pixel = &pixels[index as usize];
+                    let Some(visible_point) = &pixel.visible_point else {
+                        continue;
+                    };
+                    if (visible_point.position - hitpoint.position).squared_length()
+                        > pixel.radius * pixel.radius
+                    {
+                        continue;
+                    }
+
+                    let f = visible_point.f(-direction);
+                    if f.max() <= 0.0 {
+                        continue;
+                    }
+
+                    let dispersion = match visible_point.wavelengths {
+                        Some(mut wavelengths) if dispersive => wavelengths.terminate_secondary(),
+                        _ => Color::new(1.0, 1.0, 1.0),
+                    };
+
+                    let mut gathered = gathered[index as usize].lock().unwrap();
+                    gathered.flux = gathered.flux + beta * f * dispersion;
+                    gathered.count += 1;
+                }
+            }
+
+            let sphere = &scene.spheres()[intersection.object_id as usize];
+            let material = scene.material(sphere.material_id);
+            let bsdf = material.bsdf.as_ref();
+            dispersive |= bsdf.is_dispersive() && wavelengths.is_some();
+
+            let context = ShadingContext::new(hitpoint.position, hitpoint.uv, 0.0)
+                .with_wavelength(wavelengths.map(|wavelengths| wavelengths.hero()))
+                .with_mode(TransportMode::Importance);
+
+            let geometric_normal = hitpoint.normal;
+            let wo_world = -direction;
+            let frame = material.shading_frame(&hitpoint, &context, wo_world);
+
+            let Some(sample) = bsdf.sample(&context, frame.to_local(wo_world), rnd) else {
+                return;
+            };
+            let wi_world = frame.to_world(sample.direction);
+            if wi_world.dot(geometric_normal) * sample.direction.z <= 0.0 {
+                return;
+            }
+
+            let weight = spectrum::reflectance(wavelengths, sample.weight)
+                * material::shading_correction(
+                    TransportMode::Importance,
+                    wo_world,
+                    wi_world,
+                    frame.normal,
+                    geometric_normal,
+                );
+            beta = beta * weight;
+            let Some(probability) = russian_roulette(
+                depth,
+                self.render.config.russian_roulette_depth,
+                beta / power,
+                rnd,
+            ) else {
+                return;
+            };
+            beta = beta / probability;
+
+            if wi_world.dot(geometric_normal) * wo_world.dot(geometric_normal) < 0.0 {
+                medium = scene.medium_across(material, geometric_normal, wi_world, medium);
+            }
+            ray = Ray::new(hitpoint.position, wi_world).with_wavelengths(wavelengths);
+        }
+    }
+}
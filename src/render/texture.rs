@@ -0,0 +1,346 @@
+use std::{f64::consts::PI, sync::Arc};
+
+use super::{
+    hdr,
+    material::{Color, ShadingContext},
+    png, ppm,
+    sphere::Sphere,
+    vec3::Vec3,
+};
+
+mod procedural;
+
+pub use procedural::{GradientKind, MathOp, Node};
+
+#[derive(Debug, Clone, Copy)]
+pub enum WrapMode {
+    Repeat,
+    Clamp,
+    Mirror,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Filter {
+    Bilinear,
+    Trilinear,
+}
+
+#[derive(Debug)]
+struct MipLevel {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>,
+}
+
+impl MipLevel {
+    fn downsample(&self) -> MipLevel {
+        let width = self.width.div_ceil(2);
+        let height = self.height.div_ceil(2);
+
+        let mut pixels = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                let x0 = (2 * x).min(self.width - 1);
+                let x1 = (2 * x + 1).min(self.width - 1);
+                let y0 = (2 * y).min(self.height - 1);
+                let y1 = (2 * y + 1).min(self.height - 1);
+
+                pixels.push(
+                    (self.pixels[y0 * self.width + x0]
+                        + self.pixels[y0 * self.width + x1]
+                        + self.pixels[y1 * self.width + x0]
+                        + self.pixels[y1 * self.width + x1])
+                        / 4.0,
+                );
+            }
+        }
+
+        MipLevel {
+            width,
+            height,
+            pixels,
+        }
+    }
+}
+
+fn wrap(i: i64, size: usize, mode: WrapMode) -> usize {
+    let size = size as i64;
+    match mode {
+        WrapMode::Repeat => i.rem_euclid(size) as usize,
+        WrapMode::Clamp => i.clamp(0, size - 1) as usize,
+        WrapMode::Mirror => {
+            let i = i.rem_euclid(2 * size);
+            (if i < size { i } else { 2 * size - 1 - i }) as usize
+        }
+    }
+}
+
+fn srgb_to_linear(value: f64) -> f64 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Mipmapped image addressed by `(u, v)` with `v = 0` on the first row.
+#[derive(Debug)]
+pub struct ImageTexture {
+    levels: Vec<MipLevel>,
+    wrap: WrapMode,
+    filter: Filter,
+    scale: (f64, f64),
+}
+
+impl ImageTexture {
+    pub fn new(
+        pixels: Vec<Color>,
+        width: u32,
+        height: u32,
+        wrap: WrapMode,
+        filter: Filter,
+    ) -> ImageTexture {
+        let mut levels = vec![MipLevel {
+            width: width as usize,
+            height: height as usize,
+            pixels,
+        }];
+
+        while levels
+            .last()
+            .is_some_and(|level| level.width > 1 || level.height > 1)
+        {
+            let next = levels.last().unwrap().downsample();
+            levels.push(next);
+        }
+
+        ImageTexture {
+            levels,
+            wrap,
+            filter,
+            scale: (1.0, 1.0),
+        }
+    }
+
+    /// Loads a PPM, PNG or Radiance HDR file. 8 and 16-bit images are decoded from sRGB when
+    /// `srgb` is set; HDR images are always linear.
+    pub fn load(
+        file_name: &str,
+        wrap: WrapMode,
+        filter: Filter,
+        srgb: bool,
+    ) -> std::io::Result<ImageTexture> {
+        let extension = file_name
+            .rsplit('.')
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+
+        let (pixels, width, height) = match extension.as_str() {
+            "hdr" => hdr::load_hdr(file_name)?,
+            "png" => png::load_png(file_name)?,
+            "ppm" => ppm::load_ppm(file_name)?,
+            _ => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("Unsupported texture format: {}", file_name),
+                ))
+            }
+        };
+
+        let pixels = if srgb && extension != "hdr" {
+            pixels
+                .into_iter()
+                .map(|c| {
+                    Color::new(
+                        srgb_to_linear(c.x),
+                        srgb_to_linear(c.y),
+                        srgb_to_linear(c.z),
+                    )
+                })
+                .collect()
+        } else {
+            pixels
+        };
+
+        Ok(ImageTexture::new(pixels, width, height, wrap, filter))
+    }
+
+    pub fn checkerboard(
+        size: u32,
+        squares: u32,
+        a: Color,
+        b: Color,
+        wrap: WrapMode,
+        filter: Filter,
+    ) -> ImageTexture {
+        let square = (size / squares).max(1);
+        let pixels = (0..size * size)
+            .map(|i| {
+                if ((i % size) / square + (i / size) / square).is_multiple_of(2) {
+                    a
+                } else {
+                    b
+                }
+            })
+            .collect();
+
+        ImageTexture::new(pixels, size, size, wrap, filter)
+    }
+
+    pub fn gradient(size: u32, a: Color, b: Color, wrap: WrapMode, filter: Filter) -> ImageTexture {
+        let pixels = (0..size * size)
+            .map(|i| {
+                let t = ((i % size) as f64 + 0.5) / size as f64;
+                a * (1.0 - t) + b * t
+            })
+            .collect();
+
+        ImageTexture::new(pixels, size, size, wrap, filter)
+    }
+
+    pub fn with_scale(mut self, u: f64, v: f64) -> ImageTexture {
+        self.scale = (u, v);
+        self
+    }
+
+    fn texel(&self, level: &MipLevel, x: i64, y: i64) -> Color {
+        let x = wrap(x, level.width, self.wrap);
+        let y = wrap(y, level.height, self.wrap);
+        level.pixels[y * level.width + x]
+    }
+
+    fn bilinear(&self, level: usize, u: f64, v: f64) -> Color {
+        let level = &self.levels[level];
+        let x = u * level.width as f64 - 0.5;
+        let y = v * level.height as f64 - 0.5;
+        let x0 = x.floor();
+        let y0 = y.floor();
+        let tx = x - x0;
+        let ty = y - y0;
+        let (x0, y0) = (x0 as i64, y0 as i64);
+
+        self.texel(level, x0, y0) * ((1.0 - tx) * (1.0 - ty))
+            + self.texel(level, x0 + 1, y0) * (tx * (1.0 - ty))
+            + self.texel(level, x0, y0 + 1) * ((1.0 - tx) * ty)
+            + self.texel(level, x0 + 1, y0 + 1) * (tx * ty)
+    }
+
+    /// `footprint` is the filter width in texture space, before `scale` is applied.
+    pub fn evaluate(&self, uv: (f64, f64), footprint: f64) -> Color {
+        let u = uv.0 * self.scale.0;
+        let v = uv.1 * self.scale.1;
+
+        match self.filter {
+            Filter::Bilinear => self.bilinear(0, u, v),
+            Filter::Trilinear => {
+                let base = &self.levels[0];
+                let texels = footprint
+                    * (self.scale.0 * self.scale.1).sqrt()
+                    * base.width.max(base.height) as f64;
+                let lod = texels
+                    .max(1.0)
+                    .log2()
+                    .clamp(0.0, (self.levels.len() - 1) as f64);
+
+                let level = lod.floor() as usize;
+                if level + 1 >= self.levels.len() {
+                    return self.bilinear(level, u, v);
+                }
+
+                let t = lod - level as f64;
+                self.bilinear(level, u, v) * (1.0 - t) + self.bilinear(level + 1, u, v) * t
+            }
+        }
+    }
+
+    pub fn average(&self) -> Color {
+        self.levels.last().unwrap().pixels[0]
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Texture {
+    Constant(Color),
+    Image(Arc<ImageTexture>),
+    Procedural(Arc<Node>),
+}
+
+impl Texture {
+    pub fn evaluate(&self, context: &ShadingContext) -> Color {
+        match self {
+            Texture::Constant(color) => *color,
+            Texture::Image(image) => image.evaluate(context.uv, context.footprint),
+            Texture::Procedural(node) => node.evaluate(context),
+        }
+    }
+
+    /// Scalar parameters read the average of the three channels.
+    pub fn evaluate_scalar(&self, context: &ShadingContext) -> f64 {
+        let color = self.evaluate(context);
+        (color.x + color.y + color.z) / 3.0
+    }
+
+    /// Whether the texture is black everywhere. Procedural nodes may be anywhere, so they are
+    /// never taken to be.
+    pub fn is_black(&self) -> bool {
+        match self {
+            Texture::Constant(color) => color.max() <= 0.0,
+            Texture::Image(image) => image.average().max() <= 0.0,
+            Texture::Procedural(_) => false,
+        }
+    }
+
+    /// Average over the surface of `sphere`, with negative values counted as zero.
+    pub fn average(&self, sphere: &Sphere) -> Color {
+        match self {
+            Texture::Constant(color) => *color,
+            Texture::Image(image) => image.average(),
+            Texture::Procedural(node) => {
+                // Estimated over a grid of equal areas, which is all that light selection
+                // needs.
+                let mut sum = Color::new(0.0, 0.0, 0.0);
+                for j in 0..16 {
+                    for i in 0..16 {
+                        let cos_theta = 1.0 - 2.0 * (j as f64 + 0.5) / 16.0;
+                        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+                        let phi = 2.0 * PI * (i as f64 + 0.5) / 16.0;
+                        let direction =
+                            Vec3::new(sin_theta * phi.cos(), cos_theta, sin_theta * phi.sin());
+
+                        let hit =
+                            sphere.hit_point(sphere.position + direction * sphere.radius, 0.0);
+                        let color = node.evaluate(&ShadingContext::new(hit.position, hit.uv, 0.0));
+                        sum =
+                            sum + Color::new(color.x.max(0.0), color.y.max(0.0), color.z.max(0.0));
+                    }
+                }
+                sum / 256.0
+            }
+        }
+    }
+}
+
+impl From<Color> for Texture {
+    fn from(color: Color) -> Texture {
+        Texture::Constant(color)
+    }
+}
+
+impl From<f64> for Texture {
+    fn from(value: f64) -> Texture {
+        Texture::Constant(Color::new(value, value, value))
+    }
+}
+
+impl From<Arc<Node>> for Texture {
+    fn from(node: Arc<Node>) -> Texture {
+        Texture::Procedural(node)
+    }
+}
+
+impl From<ImageTexture> for Texture {
+    fn from(image: ImageTexture) -> Texture {
+        Texture::Image(Arc::new(image))
+    }
+}
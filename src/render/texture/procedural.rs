@@ -0,0 +1,282 @@
+use std::sync::Arc;
+
+use super::super::{
+    material::{Color, ShadingContext},
+    vec3::Vec3,
+};
+
+const PERMUTATION: [u8; 256] = [
+    151, 160, 137, 91, 90, 15, 131, 13, 201, 95, 96, 53, 194, 233, 7, 225, 140, 36, 103, 30, 69,
+    142, 8, 99, 37, 240, 21, 10, 23, 190, 6, 148, 247, 120, 234, 75, 0, 26, 197, 62, 94, 252, 219,
+    203, 117, 35, 11, 32, 57, 177, 33, 88, 237, 149, 56, 87, 174, 20, 125, 136, 171, 168, 68, 175,
+    74, 165, 71, 134, 139, 48, 27, 166, 77, 146, 158, 231, 83, 111, 229, 122, 60, 211, 133, 230,
+    220, 105, 92, 41, 55, 46, 245, 40, 244, 102, 143, 54, 65, 25, 63, 161, 1, 216, 80, 73, 209, 76,
+    132, 187, 208, 89, 18, 169, 200, 196, 135, 130, 116, 188, 159, 86, 164, 100, 109, 198, 173,
+    186, 3, 64, 52, 217, 226, 250, 124, 123, 5, 202, 38, 147, 118, 126, 255, 82, 85, 212, 207, 206,
+    59, 227, 47, 16, 58, 17, 182, 189, 28, 42, 223, 183, 170, 213, 119, 248, 152, 2, 44, 154, 163,
+    70, 221, 153, 101, 155, 167, 43, 172, 9, 129, 22, 39, 253, 19, 98, 108, 110, 79, 113, 224, 232,
+    178, 185, 112, 104, 218, 246, 97, 228, 251, 34, 242, 193, 238, 210, 144, 12, 191, 179, 162,
+    241, 81, 51, 145, 235, 249, 14, 239, 107, 49, 192, 214, 31, 181, 199, 106, 157, 184, 84, 204,
+    176, 115, 121, 50, 45, 127, 4, 150, 254, 138, 236, 205, 93, 222, 114, 67, 29, 24, 72, 243, 141,
+    128, 195, 78, 66, 215, 61, 156, 180,
+];
+
+fn hash(x: i64, y: i64, z: i64) -> usize {
+    let p = |i: usize| PERMUTATION[i & 255] as usize;
+    p(p(p(x.rem_euclid(256) as usize) + y.rem_euclid(256) as usize) + z.rem_euclid(256) as usize)
+}
+
+fn fade(t: f64) -> f64 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(a: f64, b: f64, t: f64) -> f64 {
+    a + (b - a) * t
+}
+
+fn gradient(hash: usize, x: f64, y: f64, z: f64) -> f64 {
+    let h = hash & 15;
+    let u = if h < 8 { x } else { y };
+    let v = if h < 4 {
+        y
+    } else if h == 12 || h == 14 {
+        x
+    } else {
+        z
+    };
+
+    (if h & 1 == 0 { u } else { -u }) + (if h & 2 == 0 { v } else { -v })
+}
+
+/// Ken Perlin's improved noise, roughly in `[-1, 1]` and zero at integer lattice points.
+fn perlin(p: Vec3) -> f64 {
+    let (xi, yi, zi) = (p.x.floor(), p.y.floor(), p.z.floor());
+    let (x, y, z) = (p.x - xi, p.y - yi, p.z - zi);
+    let (xi, yi, zi) = (xi as i64, yi as i64, zi as i64);
+    let (u, v, w) = (fade(x), fade(y), fade(z));
+
+    let corner = |dx: i64, dy: i64, dz: i64| {
+        gradient(
+            hash(xi + dx, yi + dy, zi + dz),
+            x - dx as f64,
+            y - dy as f64,
+            z - dz as f64,
+        )
+    };
+
+    lerp(
+        lerp(
+            lerp(corner(0, 0, 0), corner(1, 0, 0), u),
+            lerp(corner(0, 1, 0), corner(1, 1, 0), u),
+            v,
+        ),
+        lerp(
+            lerp(corner(0, 0, 1), corner(1, 0, 1), u),
+            lerp(corner(0, 1, 1), corner(1, 1, 1), u),
+            v,
+        ),
+        w,
+    )
+}
+
+fn worley(p: Vec3) -> f64 {
+    let cell = (p.x.floor() as i64, p.y.floor() as i64, p.z.floor() as i64);
+    let mut nearest = f64::INFINITY;
+
+    for dz in -1..=1 {
+        for dy in -1..=1 {
+            for dx in -1..=1 {
+                let (x, y, z) = (cell.0 + dx, cell.1 + dy, cell.2 + dz);
+                let h = hash(x, y, z);
+                let feature = Vec3::new(
+                    x as f64 + PERMUTATION[h] as f64 / 256.0,
+                    y as f64 + PERMUTATION[(h + 1) & 255] as f64 / 256.0,
+                    z as f64 + PERMUTATION[(h + 2) & 255] as f64 / 256.0,
+                );
+                nearest = nearest.min((feature - p).length());
+            }
+        }
+    }
+
+    nearest
+}
+
+fn splat(value: f64) -> Color {
+    Color::new(value, value, value)
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum MathOp {
+    Add,
+    Subtract,
+    Multiply,
+    Power,
+    Sine,
+    Fract,
+    Abs,
+}
+
+/// Linear is the unclamped x coordinate, radial the distance from the z axis and spherical
+/// falls from one at the origin to zero at unit distance.
+#[derive(Debug, Clone, Copy)]
+pub enum GradientKind {
+    Linear,
+    Radial,
+    Spherical,
+}
+
+/// Node in a procedural texture graph. Inputs are shared so a subgraph can feed several nodes;
+/// every node outputs a color, with scalar results replicated across the channels.
+#[derive(Debug)]
+pub enum Node {
+    Constant(Color),
+    Position,
+    Uv,
+    Checkerboard {
+        input: Arc<Node>,
+        a: Arc<Node>,
+        b: Arc<Node>,
+    },
+    Noise {
+        input: Arc<Node>,
+    },
+    Fbm {
+        input: Arc<Node>,
+        octaves: u32,
+        lacunarity: f64,
+        gain: f64,
+    },
+    Turbulence {
+        input: Arc<Node>,
+        octaves: u32,
+        lacunarity: f64,
+        gain: f64,
+    },
+    Voronoi {
+        input: Arc<Node>,
+    },
+    Gradient {
+        input: Arc<Node>,
+        kind: GradientKind,
+    },
+    /// Unary operations ignore `b`.
+    Math {
+        op: MathOp,
+        a: Arc<Node>,
+        b: Arc<Node>,
+    },
+    Mix {
+        a: Arc<Node>,
+        b: Arc<Node>,
+        factor: Arc<Node>,
+    },
+}
+
+impl Node {
+    pub fn constant(value: f64) -> Arc<Node> {
+        Arc::new(Node::Constant(splat(value)))
+    }
+
+    pub fn color(color: Color) -> Arc<Node> {
+        Arc::new(Node::Constant(color))
+    }
+
+    pub fn math(op: MathOp, a: Arc<Node>, b: Arc<Node>) -> Arc<Node> {
+        Arc::new(Node::Math { op, a, b })
+    }
+
+    pub fn mix(a: Arc<Node>, b: Arc<Node>, factor: Arc<Node>) -> Arc<Node> {
+        Arc::new(Node::Mix { a, b, factor })
+    }
+
+    pub fn evaluate(&self, context: &ShadingContext) -> Color {
+        match self {
+            Node::Constant(color) => *color,
+            Node::Position => context.position,
+            Node::Uv => Color::new(context.uv.0, context.uv.1, 0.0),
+            Node::Checkerboard { input, a, b } => {
+                let p = input.evaluate(context);
+                let parity = p.x.floor() as i64 + p.y.floor() as i64 + p.z.floor() as i64;
+                if parity.rem_euclid(2) == 0 {
+                    a.evaluate(context)
+                } else {
+                    b.evaluate(context)
+                }
+            }
+            Node::Noise { input } => splat(perlin(input.evaluate(context))),
+            Node::Fbm {
+                input,
+                octaves,
+                lacunarity,
+                gain,
+            } => splat(fractal(
+                input.evaluate(context),
+                *octaves,
+                *lacunarity,
+                *gain,
+                perlin,
+            )),
+            Node::Turbulence {
+                input,
+                octaves,
+                lacunarity,
+                gain,
+            } => splat(fractal(
+                input.evaluate(context),
+                *octaves,
+                *lacunarity,
+                *gain,
+                |p| perlin(p).abs(),
+            )),
+            Node::Voronoi { input } => splat(worley(input.evaluate(context))),
+            Node::Gradient { input, kind } => {
+                let p = input.evaluate(context);
+                splat(match kind {
+                    GradientKind::Linear => p.x,
+                    GradientKind::Radial => (p.x * p.x + p.y * p.y).sqrt(),
+                    GradientKind::Spherical => (1.0 - p.length()).max(0.0),
+                })
+            }
+            Node::Math { op, a, b } => {
+                let a = a.evaluate(context);
+                let channel = |f: fn(f64, f64) -> f64, b: Color| {
+                    Color::new(f(a.x, b.x), f(a.y, b.y), f(a.z, b.z))
+                };
+                let unary = |f: fn(f64) -> f64| Color::new(f(a.x), f(a.y), f(a.z));
+
+                match op {
+                    MathOp::Add => a + b.evaluate(context),
+                    MathOp::Subtract => a - b.evaluate(context),
+                    MathOp::Multiply => a * b.evaluate(context),
+                    MathOp::Power => channel(f64::powf, b.evaluate(context)),
+                    MathOp::Sine => unary(f64::sin),
+                    MathOp::Fract => unary(|x| x - x.floor()),
+                    MathOp::Abs => unary(f64::abs),
+                }
+            }
+            Node::Mix { a, b, factor } => {
+                let t = factor.evaluate(context);
+                let t = Color::new(
+                    t.x.clamp(0.0, 1.0),
+                    t.y.clamp(0.0, 1.0),
+                    t.z.clamp(0.0, 1.0),
+                );
+                a.evaluate(context) * (Color::new(1.0, 1.0, 1.0) - t) + b.evaluate(context) * t
+            }
+        }
+    }
+}
+
+fn fractal(p: Vec3, octaves: u32, lacunarity: f64, gain: f64, noise: impl Fn(Vec3) -> f64) -> f64 {
+    let mut sum = 0.0;
+    let mut frequency = 1.0;
+    let mut amplitude = 1.0;
+
+    for _ in 0..octaves {
+        sum += amplitude * noise(p * frequency);
+        frequency *= lacunarity;
+        amplitude *= gain;
+    }
+
+    sum
+}
@@ -0,0 +1,555 @@
+use std::{f64::consts::PI, thread};
+
+use super::{
+    bdpt::{remap, Bdpt, Vertex, VertexKind},
+    film::Film,
+    hash_grid::HashGrid,
+    material::Color,
+    random::{Sampler, XorShiftRandom},
+    spectrum::{self, Wavelengths},
+    Render,
+};
+
+/// Partial sums of the balance heuristic over the other ways of sampling a subpath, carried
+/// from vertex to vertex so the weight of a connection or merge at its end takes constant time
+/// (Georgiev et al. 2012). They are kept in area measure to build on the densities stored with
+/// the BDPT vertices.
+#[derive(Clone, Copy)]
+struct Mis {
+    /// Reciprocal density of sampling the vertex from its own subpath.
+    vcm: f64,
+    /// Connections and merges that take the previous vertex from the other subpath, relative
+    /// to this subpath sampling it. The density of the other subpath reaching that vertex is
+    /// left out, as it depends on where the subpaths are joined.
+    vc: f64,
+    vm: f64,
+}
+
+impl Mis {
+    /// Quantities at `vertex`, which follows the one these are for. `pdf_before` is the reverse
+    /// density of the vertex before that one, `connectible` whether the edge into it can be
+    /// connected and `eta` the weight of merging at it.
+    fn next(&self, vertex: &Vertex, pdf_before: f64, connectible: bool, eta: f64) -> Mis {
+        let connection = if connectible { 1.0 } else { 0.0 };
+        Mis {
+            vcm: 1.0 / remap(vertex.pdf_fwd),
+            vc: self.vcm * (connection + remap(pdf_before) * self.vc),
+            vm: eta + self.vcm * remap(pdf_before) * self.vm,
+        }
+    }
+
+    /// Strategies relative to joining the subpath at its vertex to the other one, which
+    /// samples the vertex and the one before it with the densities `pdf` and `pdf_prev`.
+    fn connection(&self, pdf: f64, pdf_prev: f64, connectible: bool, eta: f64) -> f64 {
+        let connection = if connectible { 1.0 } else { 0.0 };
+        remap(pdf) * (self.vcm * (connection + remap(pdf_prev) * (self.vc + self.vm)) + eta)
+    }
+
+    /// Strategies of the subpath relative to merging at its vertex, where the other subpath
+    /// samples the vertex before it with the density `pdf_prev`.
+    fn merge(&self, pdf_prev: f64, connectible: bool, eta: f64) -> f64 {
+        let connection = if connectible { 1.0 } else { 0.0 };
+        self.vcm * (connection + remap(pdf_prev) * (self.vc + self.vm)) / eta
+    }
+}
+
+/// Constants of one iteration, shared by all of its paths.
+#[derive(Clone, Copy)]
+struct Iteration {
+    wavelengths: Option<Wavelengths>,
+    radius: f64,
+    /// Number of light subpaths, which scales the weights of merging.
+    paths: f64,
+}
+
+/// Light subpaths of an iteration, stored one after the other.
+struct LightPaths<'a> {
+    vertices: Vec<Vertex<'a>>,
+    info: Vec<LightVertex>,
+    /// Index of the first vertex of each subpath, followed by the number of vertices.
+    starts: Vec<usize>,
+}
+
+#[derive(Clone, Copy)]
+struct LightVertex {
+    mis: Mis,
+    /// Number of segments from the emitter.
+    depth: usize,
+    /// Whether the subpath passed a dispersive surface up to here.
+    dispersive: bool,
+}
+
+/// Vertex connection and merging. Each iteration traces one light subpath per pixel, splats
+/// its connections to the camera, then traces a camera subpath per pixel that connects to the
+/// lights and to the vertices of one light subpath, and merges with the vertices of all of
+/// them within a shrinking radius, with every technique weighted by the balance heuristic.
+/// Subpaths are traced and joined by the bidirectional path tracer. Surfaces merge within a
+/// disk and media within a sphere, each with vertices of their own kind.
+pub struct Vcm<'a> {
+    render: &'a Render,
+}
+
+impl<'a> Vcm<'a> {
+    pub fn new(render: &'a Render) -> Vcm<'a> {
+        Vcm { render }
+    }
+
+    pub fn render(&self) -> Vec<Color> {
+        let config = self.render.config;
+        let width = config.width;
+        let tasks = config.tasks.max(1);
+        let iterations = config.samples * config.super_samples * config.super_samples;
+        let path_count = config.width * config.height;
+
+        let film = Film::new(config.width, config.height);
+        let mut pixels = vec![Color::new(0.0, 0.0, 0.0); path_count as usize];
+
+        let mut rnd = XorShiftRandom::new(1);
+        for index in 0..iterations {
+            println!("Rendering (iteration = {} / {})", index, iterations);
+
+            let iteration = Iteration {
+                // Paths only merge when they carry the same wavelengths.
+                wavelengths: config.spectral.then(|| Wavelengths::sample(rnd.next_f64())),
+                radius: config.merge_radius
+                    / ((index + 1) as f64).powf(0.5 * (1.0 - config.merge_radius_alpha)),
+                paths: path_count as f64,
+            };
+            let bdpt = Bdpt::new(
+                &self.render.scene,
+                &self.render.camera,
+                &config,
+                iteration.wavelengths,
+            );
+            let seed = index * tasks * 2;
+
+            let paths_per_task = path_count.div_ceil(tasks);
+            let (film_ref, bdpt_ref) = (&film, &bdpt);
+            let subpaths: Vec<LightPaths> = thread::scope(|s| {
+                let handles: Vec<_> = (0..tasks)
+                    .map(|task| {
+                        s.spawn(move || {
+                            let mut rnd = XorShiftRandom::new(seed + task + 1);
+                            let mut paths = LightPaths {
+                                vertices: Vec::new(),
+                                info: Vec::new(),
+                                starts: Vec::new(),
+                            };
+
+                            let start = (task * paths_per_task).min(path_count);
+                            let end = ((task + 1) * paths_per_task).min(path_count);
+                            for _ in start..end {
+                                paths.starts.push(paths.vertices.len());
+                                self.light_subpath(
+                                    &iteration, bdpt_ref, film_ref, &mut rnd, &mut paths,
+                                );
+                            }
+                            paths
+                        })
+                    })
+                    .collect();
+
+                handles
+                    .into_iter()
+                    .map(|handle| handle.join().unwrap())
+                    .collect()
+            });
+
+            let mut light_paths = LightPaths {
+                vertices: Vec::new(),
+                info: Vec::new(),
+                starts: Vec::with_capacity(path_count as usize + 1),
+            };
+            for paths in subpaths {
+                let offset = light_paths.vertices.len();
+                light_paths
+                    .starts
+                    .extend(paths.starts.into_iter().map(|start| offset + start));
+                light_paths.vertices.extend(paths.vertices);
+                light_paths.info.extend(paths.info);
+            }
+            light_paths.starts.push(light_paths.vertices.len());
+
+            let mut grid = HashGrid::new(iteration.radius * 2.0);
+            for (index, (vertex, info)) in light_paths
+                .vertices
+                .iter()
+                .zip(&light_paths.info)
+                .enumerate()
+            {
+                if info.depth > 0 && self.eta(&iteration, vertex) > 0.0 {
+                    grid.insert(index as u32, vertex.position, iteration.radius);
+                }
+            }
+
+            let rows = config.height.div_ceil(tasks);
+            let (light_paths, grid) = (&light_paths, &grid);
+            thread::scope(|s| {
+                for (task, chunk) in pixels.chunks_mut((rows * width) as usize).enumerate() {
+                    s.spawn(move || {
+                        let mut rnd = XorShiftRandom::new(seed + tasks + task as u32 + 1);
+                        let first = task as u32 * rows * width;
+
+                        for (i, pixel) in chunk.iter_mut().enumerate() {
+                            let index = (first + i as u32) as usize;
+                            let radiance = self.camera_subpath(
+                                &iteration,
+                                bdpt_ref,
+                                (index as u32 % width) as f64 + rnd.next_f64(),
+                                (index as u32 / width) as f64 + rnd.next_f64(),
+                                index,
+                                light_paths,
+                                grid,
+                                &mut rnd,
+                            );
+                            *pixel = *pixel + spectrum::to_rgb(iteration.wavelengths, radiance);
+                        }
+                    });
+                }
+            });
+        }
+
+        for (y, row) in pixels.chunks(width as usize).enumerate() {
+            let row: Vec<Color> = row.iter().map(|pixel| *pixel / iterations as f64).collect();
+            film.set_row(y as u32, &row);
+        }
+        film.develop(1.0 / iterations as f64)
+    }
+
+    /// Traces a light subpath, appending its vertices to `paths` and splatting their
+    /// connections to the camera onto `film`.
+    fn light_subpath(
+        &self,
+        iteration: &Iteration,
+        bdpt: &Bdpt<'a>,
+        film: &Film,
+        rnd: &mut dyn Sampler,
+        paths: &mut LightPaths<'a>,
+    ) {
+        let path = bdpt.light_subpath(rnd, &mut false);
+        let mis = self.subpath_mis(iteration, bdpt, &path);
+
+        let mut dispersive = false;
+        for (depth, vertex) in path.iter().enumerate() {
+            dispersive |= self.is_dispersive(iteration, vertex);
+            paths.info.push(LightVertex {
+                mis: mis[depth],
+                depth,
+                dispersive,
+            });
+            if depth == 0 {
+                continue;
+            }
+
+            let Some((radiance, camera, (x, y))) = bdpt.join_camera(vertex, rnd) else {
+                continue;
+            };
+            if radiance.max() <= 0.0 {
+                continue;
+            }
+
+            // The camera cannot be reached by the other subpaths.
+            let reverse = bdpt.reverse_densities(&path[..=depth], &[camera]);
+            let light = mis[depth].connection(
+                reverse.qs,
+                reverse.qs_minus,
+                !path[depth - 1].delta,
+                self.eta(iteration, vertex),
+            );
+
+            let radiance = radiance * dispersion(iteration.wavelengths, dispersive) / (1.0 + light);
+            film.splat(x, y, spectrum::to_rgb(iteration.wavelengths, radiance));
+        }
+
+        paths.vertices.extend(path);
+    }
+
+    /// Radiance arriving along a camera ray through the raster position `(x, y)`, connecting
+    /// to the lights and the vertices of the light subpath of the pixel `index`, and merging
+    /// with any light vertex nearby.
+    #[allow(clippy::too_many_arguments)]
+    fn camera_subpath(
+        &self,
+        iteration: &Iteration,
+        bdpt: &Bdpt<'a>,
+        x: f64,
+        y: f64,
+        index: usize,
+        light_paths: &LightPaths<'a>,
+        grid: &HashGrid,
+        rnd: &mut dyn Sampler,
+    ) -> Color {
+        let max_depth = self.render.config.max_depth as usize;
+        let ray = self
+            .render
+            .camera
+            .ray(x, y)
+            .with_wavelengths(iteration.wavelengths);
+        let path = bdpt.camera_subpath(&ray, rnd, &mut false);
+        let mis = self.subpath_mis(iteration, bdpt, &path);
+
+        let (start, end) = (light_paths.starts[index], light_paths.starts[index + 1]);
+        let light_path = &light_paths.vertices[start..end];
+        let light_info = &light_paths.info[start..end];
+
+        let mut radiance = Color::new(0.0, 0.0, 0.0);
+        let mut dispersive = false;
+        for depth in 1..path.len() {
+            let pt = &path[depth];
+            let camera = &path[..=depth];
+            let connectible = !path[depth - 1].delta;
+            dispersive |= self.is_dispersive(iteration, pt);
+
+            if pt.emitter().is_some() {
+                let emitted = bdpt.join_emitter(pt, &path[depth - 1]);
+                let weight = if depth == 1 {
+                    1.0
+                } else {
+                    let reverse = bdpt.reverse_densities(&[], camera);
+                    1.0 / (1.0
+                        + mis[depth].connection(reverse.pt, reverse.pt_minus, connectible, 0.0))
+                };
+                radiance =
+                    radiance + emitted * dispersion(iteration.wavelengths, dispersive) * weight;
+            }
+            if matches!(pt.kind, VertexKind::Light(_)) {
+                break;
+            }
+
+            let eta = self.eta(iteration, pt);
+
+            if depth <= max_depth {
+                if let Some((contribution, light)) = bdpt.join_light(pt, rnd) {
+                    if contribution.max() > 0.0 {
+                        let reverse = bdpt.reverse_densities(&[light], camera);
+                        let light_mis = Mis {
+                            vcm: 1.0 / remap(light.pdf_fwd),
+                            vc: 0.0,
+                            vm: 0.0,
+                        };
+                        let weight = 1.0
+                            / (1.0
+                                + light_mis.connection(
+                                    reverse.qs,
+                                    0.0,
+                                    !bdpt.is_delta_light(&light),
+                                    0.0,
+                                )
+                                + mis[depth].connection(
+                                    reverse.pt,
+                                    reverse.pt_minus,
+                                    connectible,
+                                    eta,
+                                ));
+                        radiance = radiance
+                            + contribution * dispersion(iteration.wavelengths, dispersive) * weight;
+                    }
+                }
+            }
+
+            for (light_depth, qs) in light_path.iter().enumerate().skip(1) {
+                if light_depth + depth > max_depth {
+                    break;
+                }
+
+                let contribution = bdpt.join(qs, pt, rnd);
+                if contribution.max() <= 0.0 {
+                    continue;
+                }
+
+                let info = &light_info[light_depth];
+                let reverse = bdpt.reverse_densities(&light_path[..=light_depth], camera);
+                let weight = 1.0
+                    / (1.0
+                        + info.mis.connection(
+                            reverse.qs,
+                            reverse.qs_minus,
+                            !light_path[light_depth - 1].delta,
+                            self.eta(iteration, qs),
+                        )
+                        + mis[depth].connection(reverse.pt, reverse.pt_minus, connectible, eta));
+                radiance = radiance
+                    + contribution
+                        * dispersion(iteration.wavelengths, dispersive || info.dispersive)
+                        * weight;
+            }
+
+            if eta > 0.0 {
+                radiance = radiance
+                    + self.merge(
+                        iteration,
+                        bdpt,
+                        &path[..=depth],
+                        mis[depth],
+                        eta,
+                        dispersive,
+                        light_paths,
+                        grid,
+                    );
+            }
+        }
+
+        radiance
+    }
+
+    /// Density estimate from the light vertices within the merging radius of the last vertex of
+    /// the camera subpath `camera`.
+    #[allow(clippy::too_many_arguments)]
+    fn merge(
+        &self,
+        iteration: &Iteration,
+        bdpt: &Bdpt<'a>,
+        camera: &[Vertex<'a>],
+        mis: Mis,
+        eta: f64,
+        dispersive: bool,
+        light_paths: &LightPaths<'a>,
+        grid: &HashGrid,
+    ) -> Color {
+        let max_depth = self.render.config.max_depth as usize;
+        let depth = camera.len() - 1;
+        let (pt, pt_minus) = (&camera[depth], &camera[depth - 1]);
+        let radius_squared = iteration.radius * iteration.radius;
+
+        let mut radiance = Color::new(0.0, 0.0, 0.0);
+        for &index in grid.candidates(pt.position) {
+            let index = index as usize;
+            let (qs, info) = (&light_paths.vertices[index], &light_paths.info[index]);
+            if info.depth + depth > max_depth + 1
+                || !same_kind(qs, pt)
+                || (qs.position - pt.position).squared_length() > radius_squared
+            {
+                continue;
+            }
+
+            // The light vertex stands in for `pt`, so its predecessor is joined to the camera
+            // subpath there.
+            let qs_minus = &light_paths.vertices[index - 1];
+            let contribution = pt.beta * bdpt.f(pt, qs_minus) * qs.beta;
+            if contribution.max() <= 0.0 {
+                continue;
+            }
+
+            let light =
+                info.mis
+                    .merge(bdpt.pdf(pt, Some(pt_minus), qs_minus), !qs_minus.delta, eta);
+            let camera = mis.merge(bdpt.pdf(pt, Some(qs_minus), pt_minus), !pt_minus.delta, eta);
+            radiance = radiance
+                + contribution * dispersion(iteration.wavelengths, dispersive || info.dispersive)
+                    / (1.0 + light + camera);
+        }
+
+        radiance * self.kernel(iteration, pt)
+    }
+
+    /// MIS quantities at each vertex of a subpath.
+    fn subpath_mis(&self, iteration: &Iteration, bdpt: &Bdpt<'a>, path: &[Vertex<'a>]) -> Vec<Mis> {
+        let Some(first) = path.first() else {
+            return Vec::new();
+        };
+
+        let mut mis = Vec::with_capacity(path.len());
+        mis.push(Mis {
+            vcm: 1.0 / remap(first.pdf_fwd),
+            vc: 0.0,
+            vm: 0.0,
+        });
+        for i in 1..path.len() {
+            let prev = &path[i - 1];
+            // Subpaths cannot start by connecting to the camera or to a delta light, nor merge
+            // at their endpoint.
+            let (pdf_before, connectible, eta) = if i == 1 {
+                let connectible =
+                    matches!(prev.kind, VertexKind::Light(_)) && !bdpt.is_delta_light(prev);
+                (0.0, connectible, 0.0)
+            } else {
+                let before = &path[i - 2];
+                (
+                    before.pdf_rev,
+                    !prev.delta && !before.delta,
+                    self.eta(iteration, prev),
+                )
+            };
+            mis.push(mis[i - 1].next(&path[i], pdf_before, connectible, eta));
+        }
+
+        mis
+    }
+
+    /// Weight of merging at `vertex` relative to connecting there: the number of light
+    /// subpaths times the size of the region merged over, or zero where nothing merges. In
+    /// media the densities of the subpaths leave out the extinction coefficient of distance
+    /// sampling, so it is made up here.
+    fn eta(&self, iteration: &Iteration, vertex: &Vertex) -> f64 {
+        let radius = iteration.radius;
+        match vertex.kind {
+            VertexKind::Surface { material, .. } if !material.bsdf.is_specular() => {
+                iteration.paths * PI * radius * radius
+            }
+            VertexKind::Medium(_) => {
+                let (_, sigma_t) = self.coefficients(iteration, vertex);
+                let sigma_t = (sigma_t.x + sigma_t.y + sigma_t.z) / 3.0;
+                iteration.paths * 4.0 / 3.0 * PI * radius * radius * radius * sigma_t
+            }
+            _ => 0.0,
+        }
+    }
+
+    /// Normalization of the light vertices merged with at `vertex`. Light vertices in media
+    /// were weighted by the scattering coefficient at their collision, which the camera
+    /// subpath already accounts for at its own.
+    fn kernel(&self, iteration: &Iteration, vertex: &Vertex) -> Color {
+        let radius = iteration.radius;
+        match vertex.kind {
+            VertexKind::Medium(_) => {
+                let (sigma_s, _) = self.coefficients(iteration, vertex);
+                let volume = iteration.paths * 4.0 / 3.0 * PI * radius * radius * radius;
+                let inverse = |sigma_s: f64| {
+                    if sigma_s > 0.0 {
+                        1.0 / (volume * sigma_s)
+                    } else {
+                        0.0
+                    }
+                };
+                Color::new(inverse(sigma_s.x), inverse(sigma_s.y), inverse(sigma_s.z))
+            }
+            _ => {
+                let area = 1.0 / (iteration.paths * PI * radius * radius);
+                Color::new(area, area, area)
+            }
+        }
+    }
+
+    fn coefficients(&self, iteration: &Iteration, vertex: &Vertex) -> (Color, Color) {
+        let scene = &self.render.scene;
+        match vertex.medium {
+            Some(medium_id) => scene
+                .medium(medium_id)
+                .coefficients(vertex.position, iteration.wavelengths),
+            None => (Color::new(0.0, 0.0, 0.0), Color::new(0.0, 0.0, 0.0)),
+        }
+    }
+
+    fn is_dispersive(&self, iteration: &Iteration, vertex: &Vertex) -> bool {
+        iteration.wavelengths.is_some()
+            && matches!(vertex.kind, VertexKind::Surface { material, .. } if material.bsdf.is_dispersive())
+    }
+}
+
+/// Whether two vertices can be merged: both on surfaces, or both in the same medium.
+fn same_kind(a: &Vertex, b: &Vertex) -> bool {
+    match (a.kind, b.kind) {
+        (VertexKind::Surface { .. }, VertexKind::Surface { .. }) => true,
+        (VertexKind::Medium(_), VertexKind::Medium(_)) => a.medium == b.medium,
+        _ => false,
+    }
+}
+
+/// Weight of a path that went through a dispersive surface, which leaves it only the hero
+/// wavelength.
+fn dispersion(wavelengths: Option<Wavelengths>, dispersive: bool) -> Color {
+    match wavelengths {
+        Some(mut wavelengths) if dispersive => wavelengths.terminate_secondary(),
+        _ => Color::new(1.0, 1.0, 1.0),
+    }
+}
@@ -66,6 +66,10 @@ impl Vec3 {
     pub fn max(&self) -> f64 {
         self.x.max(self.y).max(self.z)
     }
+
+    pub fn luminance(&self) -> f64 {
+        0.2126 * self.x + 0.7152 * self.y + 0.0722 * self.z
+    }
 }
 
 impl std::ops::Add for Vec3 {